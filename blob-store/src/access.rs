@@ -0,0 +1,701 @@
+use crate::checksum::{from_hex, to_hex};
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::store::{
+    CopyOptions, DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions, Store,
+};
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Grants to this principal apply to every caller, including anonymous ones.
+pub const ANYONE: &str = "*";
+
+pub const ACCESS_KEY_PARAM: &str = "X-Blob-Access-Key";
+pub const EXPIRES_PARAM: &str = "X-Blob-Expires";
+pub const SIGNATURE_PARAM: &str = "X-Blob-Signature";
+
+const PATH: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Read,
+    Write,
+    List,
+    Delete,
+}
+
+impl Action {
+    pub const ALL: [Action; 4] = [Action::Read, Action::Write, Action::List, Action::Delete];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Grant {
+    principal: String,
+    prefix: String,
+    actions: Vec<Action>,
+}
+
+// Grants principals, named by their access keys, actions on the objects of a bucket whose names
+// start with a prefix. Anything not granted is denied. The bucket itself is covered by grants on
+// the empty prefix: creating it takes `Write` and deleting it `Delete`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policy {
+    grants: Vec<Grant>,
+}
+
+impl Policy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow(mut self, principal: &str, prefix: &str, actions: &[Action]) -> Self {
+        self.grants.push(Grant {
+            principal: principal.into(),
+            prefix: prefix.into(),
+            actions: actions.to_vec(),
+        });
+        self
+    }
+
+    // Listing is allowed under a prefix when it is granted on that prefix or a shorter one.
+    pub fn allows(&self, principal: Option<&str>, name: &str, action: Action) -> bool {
+        self.grants.iter().any(|grant| {
+            (grant.principal == ANYONE || Some(grant.principal.as_str()) == principal)
+                && name.starts_with(&grant.prefix)
+                && grant.actions.contains(&action)
+        })
+    }
+
+    fn grants_any(&self, principal: Option<&str>, action: Action) -> bool {
+        self.grants.iter().any(|grant| {
+            (grant.principal == ANYONE || Some(grant.principal.as_str()) == principal)
+                && grant.actions.contains(&action)
+        })
+    }
+}
+
+// Keeps the access keys and bucket policies of any store. The store is used through a
+// `PrincipalStore` acting as one principal, so who makes a call is never left behind in the store
+// for the next caller. Only principals with a registered access key are granted anything beyond
+// what `ANYONE` is, so removing a key revokes its grants.
+pub struct AccessControlledStore<S> {
+    inner: S,
+    keys: HashMap<String, String>,
+    policies: HashMap<String, Policy>,
+    default_policy: Policy,
+}
+
+// Enforces the policies of an access controlled store for one principal, anonymous for `None`.
+// Calls that fail are denied with `AccessDenied`; calls that can't fail return nothing instead,
+// so a denied `head_object` looks like a missing object and `list_buckets` leaves out buckets the
+// principal may not list anything in.
+pub struct PrincipalStore<'a, S> {
+    store: &'a mut AccessControlledStore<S>,
+    principal: Option<String>,
+}
+
+impl<S: Store> AccessControlledStore<S> {
+    pub fn new(inner: S) -> Self {
+        AccessControlledStore {
+            inner,
+            keys: HashMap::new(),
+            policies: HashMap::new(),
+            default_policy: Policy::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // The secret signs presigned URLs of the access key.
+    pub fn add_key(&mut self, access_key: &str, secret: &str) {
+        self.keys.insert(access_key.into(), secret.into());
+    }
+
+    pub fn remove_key(&mut self, access_key: &str) {
+        self.keys.remove(access_key);
+    }
+
+    // Policies can be set ahead of creating their bucket, and outlive its deletion.
+    pub fn set_policy(&mut self, bucket: &str, policy: Policy) {
+        self.policies.insert(bucket.into(), policy);
+    }
+
+    pub fn remove_policy(&mut self, bucket: &str) {
+        self.policies.remove(bucket);
+    }
+
+    // Applies to buckets without a policy of their own.
+    pub fn set_default_policy(&mut self, policy: Policy) {
+        self.default_policy = policy;
+    }
+
+    pub fn policy(&self, bucket: &str) -> &Policy {
+        self.policies.get(bucket).unwrap_or(&self.default_policy)
+    }
+
+    pub fn as_principal(&mut self, access_key: Option<&str>) -> PrincipalStore<'_, S> {
+        PrincipalStore {
+            store: self,
+            principal: access_key.map(String::from),
+        }
+    }
+
+    pub fn is_allowed(
+        &self,
+        principal: Option<&str>,
+        bucket: &str,
+        name: &str,
+        action: Action,
+    ) -> bool {
+        self.policy(bucket)
+            .allows(self.known_principal(principal), name, action)
+    }
+
+    fn known_principal<'a>(&self, principal: Option<&'a str>) -> Option<&'a str> {
+        principal.filter(|key| self.keys.contains_key(*key))
+    }
+
+    // Returns the path and query of a URL that lets anyone make a `method` request on the object,
+    // or on the bucket for an empty name, as the access key until `expires` has passed. The
+    // request is still subject to the key's grants. `None` when the access key is unknown.
+    pub fn presign(
+        &self,
+        access_key: &str,
+        method: &str,
+        bucket: &str,
+        name: &str,
+        expires: Duration,
+    ) -> Option<String> {
+        let expires = SystemTime::now()
+            .checked_add(expires)?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        let signature = self.sign(access_key, method, bucket, name, expires)?;
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair(ACCESS_KEY_PARAM, access_key)
+            .append_pair(EXPIRES_PARAM, &expires.to_string())
+            .append_pair(SIGNATURE_PARAM, &signature)
+            .finish();
+        let path = match name {
+            "" => format!("/{}", bucket),
+            name => format!("/{}/{}", bucket, utf8_percent_encode(name, PATH)),
+        };
+        Some(format!("{}?{}", path, query))
+    }
+
+    // Checks the presigned parameters of a request and returns the access key it acts as.
+    pub fn verify_presigned(
+        &self,
+        method: &str,
+        bucket: &str,
+        name: &str,
+        query: &HashMap<String, String>,
+        now: SystemTime,
+    ) -> Option<&str> {
+        let (access_key, _) = self.keys.get_key_value(query.get(ACCESS_KEY_PARAM)?)?;
+        let expires = query.get(EXPIRES_PARAM)?.parse::<u64>().ok()?;
+        let now = now.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        if now > expires {
+            return None;
+        }
+        let signature = from_hex(query.get(SIGNATURE_PARAM)?)?;
+        let mac = self.mac(access_key, method, bucket, name, expires)?;
+        match mac.verify_slice(&signature) {
+            Err(_) => None,
+            Ok(()) => Some(access_key),
+        }
+    }
+
+    fn sign(
+        &self,
+        access_key: &str,
+        method: &str,
+        bucket: &str,
+        name: &str,
+        expires: u64,
+    ) -> Option<String> {
+        let mac = self.mac(access_key, method, bucket, name, expires)?;
+        Some(to_hex(&mac.finalize().into_bytes()))
+    }
+
+    fn mac(
+        &self,
+        access_key: &str,
+        method: &str,
+        bucket: &str,
+        name: &str,
+        expires: u64,
+    ) -> Option<Hmac<Sha256>> {
+        let secret = self.keys.get(access_key)?;
+        let message = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            access_key,
+            method.to_ascii_uppercase(),
+            bucket,
+            name,
+            expires
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+        mac.update(message.as_bytes());
+        Some(mac)
+    }
+}
+
+impl<S: Store> PrincipalStore<'_, S> {
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    fn is_allowed(&self, bucket: &str, name: &str, action: Action) -> bool {
+        self.store
+            .is_allowed(self.principal(), bucket, name, action)
+    }
+
+    fn check(&self, bucket: &str, name: &str, action: Action) -> Result<()> {
+        match self.is_allowed(bucket, name, action) {
+            false => Err(Error::new(ErrorKind::AccessDenied, bucket, name)),
+            true => Ok(()),
+        }
+    }
+}
+
+impl<S: Store> Store for PrincipalStore<'_, S> {
+    fn list_buckets(&self) -> Vec<&str> {
+        let principal = self.store.known_principal(self.principal());
+        self.store
+            .inner
+            .list_buckets()
+            .into_iter()
+            .filter(|bucket| {
+                self.store
+                    .policy(bucket)
+                    .grants_any(principal, Action::List)
+            })
+            .collect()
+    }
+
+    fn create_bucket(&mut self, name: &str) -> Result<()> {
+        self.check(name, "", Action::Write)?;
+        self.store.inner.create_bucket(name)
+    }
+
+    fn delete_bucket_with(&mut self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        self.check(name, "", Action::Delete)?;
+        self.store.inner.delete_bucket_with(name, options)
+    }
+
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Result<ObjectList<'_>> {
+        self.check(bucket, options.key_prefix(), Action::List)?;
+        self.store.inner.list_objects_with(bucket, options)
+    }
+
+    fn insert_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.check(bucket, name, Action::Write)?;
+        self.store
+            .inner
+            .insert_object_with(bucket, name, reader, options)
+    }
+
+    fn put_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.check(bucket, name, Action::Write)?;
+        self.store
+            .inner
+            .put_object_with(bucket, name, reader, options)
+    }
+
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        if !self.is_allowed(bucket, name, Action::Read) {
+            return None;
+        }
+        self.store.inner.head_object(bucket, name)
+    }
+
+    fn head_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Option<ObjectMetadata> {
+        if !self.is_allowed(bucket, name, Action::Read) {
+            return None;
+        }
+        self.store
+            .inner
+            .head_object_version(bucket, name, version_id)
+    }
+
+    fn set_object_tags(&mut self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        self.check(bucket, name, Action::Write)?;
+        self.store.inner.set_object_tags(bucket, name, tags)
+    }
+
+    // Only finds objects the principal may list.
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<&str>> {
+        let names = self.store.inner.find_objects(bucket, query)?;
+        let names = names
+            .into_iter()
+            .filter(|name| self.is_allowed(bucket, name, Action::List))
+            .collect();
+        Some(names)
+    }
+
+    fn get_object_with<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<Box<dyn Read + 'a>> {
+        self.check(bucket, name, Action::Read)?;
+        self.store.inner.get_object_with(bucket, name, options)
+    }
+
+    fn get_object_range<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + 'a>> {
+        self.check(bucket, name, Action::Read)?;
+        self.store.inner.get_object_range(bucket, name, range)
+    }
+
+    fn copy_object_with(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+        options: CopyOptions,
+    ) -> Result<()> {
+        self.check(src_bucket, src_name, Action::Read)?;
+        self.check(dst_bucket, dst_name, Action::Write)?;
+        self.store
+            .inner
+            .copy_object_with(src_bucket, src_name, dst_bucket, dst_name, options)
+    }
+
+    // Takes reading and deleting the source as well as writing the destination.
+    fn rename_object(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        self.check(src_bucket, src_name, Action::Read)?;
+        self.check(src_bucket, src_name, Action::Delete)?;
+        self.check(dst_bucket, dst_name, Action::Write)?;
+        self.store
+            .inner
+            .rename_object(src_bucket, src_name, dst_bucket, dst_name)
+    }
+
+    fn remove_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        self.check(bucket, name, Action::Delete)?;
+        self.store.inner.remove_object_with(bucket, name, options)
+    }
+
+    fn remove_object_version(&mut self, bucket: &str, name: &str, version_id: &str) -> Result<()> {
+        self.check(bucket, name, Action::Delete)?;
+        self.store
+            .inner
+            .remove_object_version(bucket, name, version_id)
+    }
+
+    // Only lists versions of objects the principal may list.
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        let mut versions = self.store.inner.list_object_versions(bucket)?;
+        versions.retain(|version| self.is_allowed(bucket, &version.name, Action::List));
+        Some(versions)
+    }
+
+    fn create_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        self.check(bucket, name, Action::Write)?;
+        self.store
+            .inner
+            .create_multipart_upload(bucket, name, options)
+    }
+
+    fn upload_part(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String> {
+        self.check(bucket, name, Action::Write)?;
+        self.store
+            .inner
+            .upload_part(bucket, name, upload_id, part_number, reader)
+    }
+
+    // Only lists uploads of objects the principal may list.
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        let mut uploads = self.store.inner.list_multipart_uploads(bucket)?;
+        uploads.retain(|upload| self.is_allowed(bucket, &upload.name, Action::List));
+        Some(uploads)
+    }
+
+    fn abort_multipart_upload(&mut self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        self.check(bucket, name, Action::Write)?;
+        self.store
+            .inner
+            .abort_multipart_upload(bucket, name, upload_id)
+    }
+
+    fn complete_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        self.check(bucket, name, Action::Write)?;
+        self.store
+            .inner
+            .complete_multipart_upload(bucket, name, upload_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::in_memory::store::InMemoryStore;
+    use crate::store::tests::{get_data_reader, store_tests};
+
+    // The shared cases take their store by value, so the store behind the handle is leaked.
+    fn with_store(test: fn(PrincipalStore<'static, InMemoryStore>)) {
+        let mut store = AccessControlledStore::new(InMemoryStore::new());
+        store.add_key("admin", "secret");
+        store.set_default_policy(Policy::new().allow("admin", "", &Action::ALL));
+        test(Box::leak(Box::new(store)).as_principal(Some("admin")));
+    }
+
+    store_tests!(with_store);
+
+    fn is_access_denied<T>(result: Result<T>) -> bool {
+        matches!(result, Err(e) if *e.kind() == ErrorKind::AccessDenied)
+    }
+
+    fn get<S: Store>(store: &S, name: &str) -> Result<()> {
+        store
+            .get_object_with("earth", name, GetOptions::new())
+            .map(|_| ())
+    }
+
+    fn get_store() -> AccessControlledStore<InMemoryStore> {
+        let mut inner = InMemoryStore::new();
+        let _ = inner.create_bucket("earth");
+        for name in &["public/map", "private/diary"] {
+            let _ = inner.insert_object("earth", name, &mut get_data_reader(b"Luna"));
+        }
+        let mut store = AccessControlledStore::new(inner);
+        store.add_key("alice", "wonderland");
+        store.add_key("bob", "builder");
+        let policy = Policy::new()
+            .allow(ANYONE, "public/", &[Action::Read, Action::List])
+            .allow("alice", "", &Action::ALL)
+            .allow("bob", "private/", &[Action::Read, Action::Write]);
+        store.set_policy("earth", policy);
+        store
+    }
+
+    #[test]
+    fn access_controlled_store_should_deny_actions_that_are_not_granted() {
+        let mut store = get_store();
+        let mut bob = store.as_principal(Some("bob"));
+        assert!(bob.get_object("earth", "private/diary").is_some());
+        assert!(bob.get_object("earth", "public/map").is_some());
+        assert!(
+            is_access_denied(bob.remove_object("earth", "private/diary")),
+            "AccessControlledStore let bob delete an object."
+        );
+        assert!(
+            is_access_denied(bob.put_object("earth", "public/map", &mut get_data_reader(b"Luna"))),
+            "AccessControlledStore let bob write outside the granted prefix."
+        );
+        assert!(
+            is_access_denied(bob.delete_bucket("earth")),
+            "AccessControlledStore let bob delete the bucket."
+        );
+        assert!(bob.head_object("earth", "private/diary").is_some());
+        let mut alice = store.as_principal(Some("alice"));
+        assert!(alice.remove_object("earth", "private/diary").is_ok());
+    }
+
+    #[test]
+    fn access_controlled_store_should_only_grant_anyone_to_anonymous_and_unknown_keys() {
+        let mut store = get_store();
+        for principal in &[None, Some("mallory")] {
+            let anyone = store.as_principal(*principal);
+            assert!(anyone.get_object("earth", "public/map").is_some());
+            assert!(
+                is_access_denied(get(&anyone, "private/diary")),
+                "AccessControlledStore let {:?} read a private object.",
+                principal
+            );
+            assert!(anyone.head_object("earth", "private/diary").is_none());
+        }
+        store.remove_key("alice");
+        assert!(
+            is_access_denied(get(&store.as_principal(Some("alice")), "private/diary")),
+            "AccessControlledStore kept the grants of a removed key."
+        );
+    }
+
+    #[test]
+    fn access_controlled_store_should_limit_listing_to_granted_prefixes() {
+        let mut store = get_store();
+        let anonymous = store.as_principal(None);
+        assert_eq!(anonymous.list_buckets(), vec!["earth"]);
+        assert!(
+            is_access_denied(anonymous.list_objects_with("earth", ListOptions::new())),
+            "AccessControlledStore did not deny listing outside granted prefixes."
+        );
+        let list = anonymous
+            .list_objects_with("earth", ListOptions::new().prefix("public/"))
+            .unwrap();
+        assert_eq!(list.objects, vec!["public/map"]);
+        let versions = anonymous.list_object_versions("earth").unwrap();
+        assert!(
+            versions.iter().all(|v| v.name == "public/map"),
+            "AccessControlledStore listed versions outside granted prefixes."
+        );
+        store.remove_policy("earth");
+        assert!(store.as_principal(None).list_buckets().is_empty());
+    }
+
+    #[test]
+    fn access_controlled_store_should_check_both_ends_of_copy_and_rename() {
+        let mut store = get_store();
+        let mut bob = store.as_principal(Some("bob"));
+        assert!(is_access_denied(bob.copy_object(
+            "earth",
+            "private/diary",
+            "earth",
+            "public/diary"
+        )));
+        assert!(bob
+            .copy_object("earth", "private/diary", "earth", "private/copy")
+            .is_ok());
+        assert!(
+            is_access_denied(bob.rename_object("earth", "private/diary", "earth", "private/moved")),
+            "AccessControlledStore let bob rename without delete access."
+        );
+    }
+
+    #[test]
+    fn access_controlled_store_should_create_buckets_with_policy_set_ahead() {
+        let mut store = get_store();
+        assert!(is_access_denied(
+            store.as_principal(Some("bob")).create_bucket("mars")
+        ));
+        store.set_policy("mars", Policy::new().allow("bob", "", &[Action::Write]));
+        assert!(store
+            .as_principal(Some("bob"))
+            .create_bucket("mars")
+            .is_ok());
+    }
+
+    #[test]
+    fn access_controlled_store_should_keep_principals_of_handles_apart() {
+        let mut store = get_store();
+        let alice = store.as_principal(Some("alice"));
+        assert_eq!(alice.principal(), Some("alice"));
+        assert!(alice.head_object("earth", "private/diary").is_some());
+        assert!(
+            store
+                .as_principal(None)
+                .head_object("earth", "private/diary")
+                .is_none(),
+            "AccessControlledStore kept the principal of another handle."
+        );
+    }
+
+    #[test]
+    fn verify_presigned_should_accept_only_unexpired_matching_requests() {
+        let store = get_store();
+        let url = store
+            .presign(
+                "bob",
+                "GET",
+                "earth",
+                "private/my diary",
+                Duration::from_secs(60),
+            )
+            .unwrap();
+        let (path, query) = url.split_at(url.find('?').unwrap());
+        assert_eq!(path, "/earth/private/my%20diary");
+        let query: HashMap<String, String> = form_urlencoded::parse(&query.as_bytes()[1..])
+            .into_owned()
+            .collect();
+        let now = SystemTime::now();
+        assert_eq!(
+            store.verify_presigned("GET", "earth", "private/my diary", &query, now),
+            Some("bob"),
+            "verify_presigned rejected a valid URL."
+        );
+        for (method, name, now) in &[
+            ("DELETE", "private/my diary", now),
+            ("GET", "private/other", now),
+            ("GET", "private/my diary", now + Duration::from_secs(120)),
+        ] {
+            assert!(
+                store
+                    .verify_presigned(method, "earth", name, &query, *now)
+                    .is_none(),
+                "verify_presigned accepted {} {} at the wrong time.",
+                method,
+                name
+            );
+        }
+        assert!(store
+            .presign(
+                "mallory",
+                "GET",
+                "earth",
+                "public/map",
+                Duration::from_secs(60)
+            )
+            .is_none());
+    }
+}
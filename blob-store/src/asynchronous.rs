@@ -0,0 +1,3 @@
+pub mod blocking;
+pub mod in_memory;
+pub mod store;
@@ -0,0 +1,344 @@
+use crate::asynchronous::store::{read_chunk, AsyncReader, AsyncStore, ByteStream};
+use crate::error::{Error, Result};
+use crate::list::{ListOptions, OwnedObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::store::{DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions, Store};
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream;
+use std::io;
+use std::io::Read;
+use std::panic;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task;
+use tokio_util::io::SyncIoBridge;
+
+// Number of chunks a reader may get ahead of the stream consuming it.
+const STREAM_BUFFER: usize = 4;
+
+// Makes a blocking `Store` usable as an `AsyncStore`. Every call runs on tokio's blocking thread
+// pool while holding the store's lock; object data is streamed between the task and the pool, so
+// the lock is held until an upload has been read or a download has been consumed or dropped.
+pub struct BlockingStore<S> {
+    store: Arc<Mutex<S>>,
+}
+
+impl<S: Store + Send + 'static> BlockingStore<S> {
+    pub fn new(store: S) -> Self {
+        BlockingStore {
+            store: Arc::new(Mutex::new(store)),
+        }
+    }
+
+    async fn run<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut S) -> T + Send + 'static,
+    {
+        let store = self.store.clone();
+        match task::spawn_blocking(move || f(&mut lock(&store))).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("blocking store call was cancelled: {}", e),
+        }
+    }
+
+    // Opens a reader on the blocking thread pool and streams it back, failing early when the
+    // reader couldn't be opened.
+    async fn stream<F>(&self, open: F) -> Result<ByteStream>
+    where
+        F: for<'a> FnOnce(&'a S) -> Result<Box<dyn Read + 'a>> + Send + 'static,
+    {
+        let store = self.store.clone();
+        let (opened_tx, opened_rx) = oneshot::channel::<Result<()>>();
+        let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(STREAM_BUFFER);
+        task::spawn_blocking(move || {
+            let store = lock(&store);
+            let mut reader = match open(&store) {
+                Err(e) => {
+                    let _ = opened_tx.send(Err(e));
+                    return;
+                }
+                Ok(reader) => reader,
+            };
+            let _ = opened_tx.send(Ok(()));
+            while let Some(chunk) = read_chunk(&mut reader) {
+                let failed = chunk.is_err();
+                if tx.blocking_send(chunk).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        match opened_rx.await {
+            Err(e) => Err(Error::io(io::Error::other(e), "", "")),
+            Ok(Err(e)) => Err(e),
+            Ok(Ok(())) => Ok(Box::pin(stream::unfold(rx, |mut rx| async move {
+                rx.recv().await.map(|chunk| (chunk, rx))
+            }))),
+        }
+    }
+}
+
+// Bridges an async input to the blocking `Read` a store expects. It must only be read on the
+// blocking thread pool.
+fn bridge(reader: AsyncReader) -> Box<dyn Read> {
+    Box::new(SyncIoBridge::new(reader))
+}
+
+fn lock<T>(lock: &Mutex<T>) -> MutexGuard<'_, T> {
+    lock.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[async_trait]
+impl<S: Store + Send + 'static> AsyncStore for BlockingStore<S> {
+    async fn list_buckets(&self) -> Vec<String> {
+        self.run(|store| {
+            store
+                .list_buckets()
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<String>>()
+        })
+        .await
+    }
+
+    async fn create_bucket(&self, name: &str) -> Result<()> {
+        let name = name.to_string();
+        self.run(move |store| store.create_bucket(&name)).await
+    }
+
+    async fn delete_bucket_with(&self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        let name = name.to_string();
+        self.run(move |store| store.delete_bucket_with(&name, options))
+            .await
+    }
+
+    async fn list_objects_with(
+        &self,
+        bucket: &str,
+        options: ListOptions,
+    ) -> Option<OwnedObjectList> {
+        let bucket = bucket.to_string();
+        self.run(move |store| {
+            store
+                .list_objects_with(&bucket, options)
+                .ok()
+                .map(Into::into)
+        })
+        .await
+    }
+
+    async fn insert_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: AsyncReader,
+        options: PutOptions,
+    ) -> Result<()> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.run(move |store| {
+            store.insert_object_with(&bucket, &name, &mut bridge(reader), options)
+        })
+        .await
+    }
+
+    async fn put_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: AsyncReader,
+        options: PutOptions,
+    ) -> Result<()> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.run(move |store| store.put_object_with(&bucket, &name, &mut bridge(reader), options))
+            .await
+    }
+
+    async fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.run(move |store| store.head_object(&bucket, &name))
+            .await
+    }
+
+    async fn set_object_tags(&self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.run(move |store| store.set_object_tags(&bucket, &name, tags))
+            .await
+    }
+
+    async fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<String>> {
+        let (bucket, query) = (bucket.to_string(), query.clone());
+        self.run(move |store| {
+            let names = store.find_objects(&bucket, &query)?;
+            Some(names.into_iter().map(String::from).collect())
+        })
+        .await
+    }
+
+    async fn get_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<ByteStream> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.stream(move |store| store.get_object_with(&bucket, &name, options))
+            .await
+    }
+
+    async fn get_object_range(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<ByteStream> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.stream(move |store| store.get_object_range(&bucket, &name, range))
+            .await
+    }
+
+    async fn remove_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.run(move |store| store.remove_object_with(&bucket, &name, options))
+            .await
+    }
+
+    async fn remove_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Result<()> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        let version_id = version_id.to_string();
+        self.run(move |store| store.remove_object_version(&bucket, &name, &version_id))
+            .await
+    }
+
+    async fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        let bucket = bucket.to_string();
+        self.run(move |store| store.list_object_versions(&bucket))
+            .await
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        self.run(move |store| store.create_multipart_upload(&bucket, &name, options))
+            .await
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: AsyncReader,
+    ) -> Result<String> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        let upload_id = upload_id.to_string();
+        self.run(move |store| {
+            let mut reader = bridge(reader);
+            store.upload_part(&bucket, &name, &upload_id, part_number, &mut reader)
+        })
+        .await
+    }
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        let bucket = bucket.to_string();
+        self.run(move |store| store.list_multipart_uploads(&bucket))
+            .await
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        let upload_id = upload_id.to_string();
+        self.run(move |store| store.abort_multipart_upload(&bucket, &name, &upload_id))
+            .await
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        let (bucket, name) = (bucket.to_string(), name.to_string());
+        let upload_id = upload_id.to_string();
+        self.run(move |store| store.complete_multipart_upload(&bucket, &name, &upload_id))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asynchronous::store::read_to_end;
+    use crate::asynchronous::store::tests::{async_store_tests, get_data_reader};
+    use crate::fs::store::tests::TempDir;
+    use crate::fs::store::FsStore;
+    use crate::in_memory::store::InMemoryStore;
+
+    fn with_store() -> BlockingStore<InMemoryStore> {
+        BlockingStore::new(InMemoryStore::new())
+    }
+
+    async_store_tests!(with_store);
+
+    #[tokio::test]
+    async fn blocking_store_should_stream_objects_larger_than_a_chunk() {
+        let dir = TempDir::new();
+        let store = BlockingStore::new(FsStore::open(dir.path()).unwrap());
+        let _ = store.create_bucket("earth").await;
+        let data = vec![7u8; 3 * crate::asynchronous::store::CHUNK_SIZE + 1];
+        let reader = Box::new(io::Cursor::new(data.clone())) as AsyncReader;
+        let _ = store.put_object("earth", "Moon", reader).await;
+        let output = match store.get_object("earth", "Moon").await {
+            None => Vec::new(),
+            Some(stream) => read_to_end(stream).await.unwrap_or_default(),
+        };
+        assert!(
+            output == data,
+            "BlockingStore failed to stream large object."
+        );
+    }
+
+    #[tokio::test]
+    async fn blocking_store_should_release_lock_when_stream_is_dropped() {
+        let store = with_store();
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .put_object("earth", "Moon", get_data_reader(b"Luna"))
+            .await;
+        drop(store.get_object("earth", "Moon").await);
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            store.remove_object("earth", "Moon"),
+        )
+        .await;
+        assert!(
+            matches!(result, Ok(Ok(()))),
+            "BlockingStore kept the store locked after the stream was dropped."
+        );
+    }
+}
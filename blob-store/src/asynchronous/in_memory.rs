@@ -0,0 +1,213 @@
+use crate::asynchronous::store::{read_chunk, AsyncReader, AsyncStore, ByteStream};
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::in_memory::shared::SharedInMemoryStore;
+use crate::list::{ListOptions, OwnedObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::shared::{SharedReader, SharedStore};
+use crate::store::{DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions};
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use async_trait::async_trait;
+use futures::stream;
+use std::io::{Cursor, Read};
+use tokio::io::AsyncReadExt;
+
+// Uploads are buffered whole, so larger objects must be sent in parts.
+const MAX_INPUT_SIZE: u64 = 256 * 1024 * 1024;
+
+// Reads the whole input before handing it to the store, which keeps its locks out of any await.
+// The store can't be moved to the blocking thread pool to read the input as it arrives, so this
+// takes as much memory again as the object while it is written. Inputs over `limit` bytes are
+// rejected with `QuotaExceeded`.
+async fn read_input(
+    reader: AsyncReader,
+    limit: u64,
+    bucket: &str,
+    name: &str,
+) -> Result<Box<dyn Read>> {
+    let mut data = Vec::<u8>::new();
+    if let Err(e) = reader.take(limit + 1).read_to_end(&mut data).await {
+        return Err(Error::io(e, bucket, name));
+    }
+    if data.len() as u64 > limit {
+        return Err(Error::new(ErrorKind::QuotaExceeded, bucket, name));
+    }
+    Ok(Box::new(Cursor::new(data)))
+}
+
+// Snapshot readers of in-memory objects never block, so they can be read on the async task.
+fn snapshot_stream(reader: SharedReader) -> ByteStream {
+    Box::pin(stream::unfold(Some(reader), |reader| async move {
+        let mut reader = reader?;
+        match read_chunk(&mut reader)? {
+            Ok(chunk) => Some((Ok(chunk), Some(reader))),
+            Err(e) => Some((Err(e), None)),
+        }
+    }))
+}
+
+#[async_trait]
+impl AsyncStore for SharedInMemoryStore {
+    async fn list_buckets(&self) -> Vec<String> {
+        SharedStore::list_buckets(self)
+    }
+
+    async fn create_bucket(&self, name: &str) -> Result<()> {
+        SharedStore::create_bucket(self, name)
+    }
+
+    async fn delete_bucket_with(&self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        SharedStore::delete_bucket_with(self, name, options)
+    }
+
+    async fn list_objects_with(
+        &self,
+        bucket: &str,
+        options: ListOptions,
+    ) -> Option<OwnedObjectList> {
+        SharedStore::list_objects_with(self, bucket, options)
+    }
+
+    async fn insert_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: AsyncReader,
+        options: PutOptions,
+    ) -> Result<()> {
+        let mut reader = read_input(reader, MAX_INPUT_SIZE, bucket, name).await?;
+        SharedStore::insert_object_with(self, bucket, name, &mut reader, options)
+    }
+
+    async fn put_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: AsyncReader,
+        options: PutOptions,
+    ) -> Result<()> {
+        let mut reader = read_input(reader, MAX_INPUT_SIZE, bucket, name).await?;
+        SharedStore::put_object_with(self, bucket, name, &mut reader, options)
+    }
+
+    async fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        SharedStore::head_object(self, bucket, name)
+    }
+
+    async fn set_object_tags(&self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        SharedStore::set_object_tags(self, bucket, name, tags)
+    }
+
+    async fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<String>> {
+        SharedStore::find_objects(self, bucket, query)
+    }
+
+    async fn get_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<ByteStream> {
+        SharedStore::get_object_with(self, bucket, name, options).map(snapshot_stream)
+    }
+
+    async fn get_object_range(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<ByteStream> {
+        SharedStore::get_object_range(self, bucket, name, range).map(snapshot_stream)
+    }
+
+    async fn remove_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        SharedStore::remove_object_with(self, bucket, name, options)
+    }
+
+    async fn remove_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Result<()> {
+        SharedStore::remove_object_version(self, bucket, name, version_id)
+    }
+
+    async fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        SharedStore::list_object_versions(self, bucket)
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        SharedStore::create_multipart_upload(self, bucket, name, options)
+    }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: AsyncReader,
+    ) -> Result<String> {
+        let mut reader = read_input(reader, MAX_INPUT_SIZE, bucket, name).await?;
+        SharedStore::upload_part(self, bucket, name, upload_id, part_number, &mut reader)
+    }
+
+    async fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        SharedStore::list_multipart_uploads(self, bucket)
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        SharedStore::abort_multipart_upload(self, bucket, name, upload_id)
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        SharedStore::complete_multipart_upload(self, bucket, name, upload_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asynchronous::store::tests::async_store_tests;
+
+    fn with_store() -> SharedInMemoryStore {
+        SharedInMemoryStore::new()
+    }
+
+    async_store_tests!(with_store);
+
+    #[tokio::test]
+    async fn read_input_should_reject_input_over_limit() {
+        let reader = Box::new(&b"Luna"[..]) as AsyncReader;
+        let result = read_input(reader, 3, "earth", "Moon").await;
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::QuotaExceeded)),
+            "read_input accepted input over its limit."
+        );
+        let reader = Box::new(&b"Luna"[..]) as AsyncReader;
+        assert!(read_input(reader, 4, "earth", "Moon").await.is_ok());
+    }
+}
@@ -0,0 +1,408 @@
+use crate::error::Result;
+use crate::list::{ListOptions, OwnedObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::store::{DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions};
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::io;
+use std::io::Read;
+use std::pin::Pin;
+use tokio::io::AsyncRead;
+
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+pub type AsyncReader = Box<dyn AsyncRead + Unpin + Send>;
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+// The asynchronous counterpart of `Store`. Like `SharedStore` every method takes `&self`, so a
+// store can be shared between tasks.
+#[async_trait]
+pub trait AsyncStore: Send + Sync {
+    async fn list_buckets(&self) -> Vec<String>;
+    async fn create_bucket(&self, name: &str) -> Result<()>;
+    async fn delete_bucket(&self, name: &str) -> Result<()> {
+        self.delete_bucket_with(name, DeleteBucketOptions::new())
+            .await
+    }
+    async fn delete_bucket_with(&self, name: &str, options: DeleteBucketOptions) -> Result<()>;
+    async fn list_objects(&self, bucket: &str) -> Option<Vec<String>> {
+        self.list_objects_with(bucket, ListOptions::new())
+            .await
+            .map(|list| list.objects)
+    }
+    async fn list_objects_with(
+        &self,
+        bucket: &str,
+        options: ListOptions,
+    ) -> Option<OwnedObjectList>;
+    async fn insert_object(&self, bucket: &str, name: &str, reader: AsyncReader) -> Result<()> {
+        self.insert_object_with(bucket, name, reader, PutOptions::new())
+            .await
+    }
+    async fn insert_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: AsyncReader,
+        options: PutOptions,
+    ) -> Result<()>;
+    async fn put_object(&self, bucket: &str, name: &str, reader: AsyncReader) -> Result<()> {
+        self.put_object_with(bucket, name, reader, PutOptions::new())
+            .await
+    }
+    async fn put_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: AsyncReader,
+        options: PutOptions,
+    ) -> Result<()>;
+    async fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata>;
+    async fn set_object_tags(&self, bucket: &str, name: &str, tags: Tags) -> Result<()>;
+    async fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<String>>;
+    async fn get_object(&self, bucket: &str, name: &str) -> Option<ByteStream> {
+        self.get_object_with(bucket, name, GetOptions::new())
+            .await
+            .ok()
+    }
+    async fn get_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<ByteStream>;
+    async fn get_object_range(
+        &self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<ByteStream>;
+    async fn remove_object(&self, bucket: &str, name: &str) -> Result<()> {
+        self.remove_object_with(bucket, name, RemoveOptions::new())
+            .await
+    }
+    async fn remove_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()>;
+    async fn remove_object_version(&self, bucket: &str, name: &str, version_id: &str)
+        -> Result<()>;
+    async fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>>;
+    async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String>;
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: AsyncReader,
+    ) -> Result<String>;
+    async fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>>;
+    async fn abort_multipart_upload(&self, bucket: &str, name: &str, upload_id: &str)
+        -> Result<()>;
+    async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()>;
+}
+
+// Reads the next chunk of a blocking reader, or returns None at the end of the data.
+pub fn read_chunk(reader: &mut dyn Read) -> Option<io::Result<Bytes>> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return None,
+            Ok(n) => {
+                buf.truncate(n);
+                return Some(Ok(Bytes::from(buf)));
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+pub async fn read_to_end(mut stream: ByteStream) -> io::Result<Vec<u8>> {
+    let mut data = Vec::<u8>::new();
+    while let Some(chunk) = stream.next().await {
+        data.extend_from_slice(&chunk?);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::condition::Conditions;
+    use crate::error::Kind as ErrorKind;
+
+    pub fn get_data_reader(data: &'static [u8]) -> AsyncReader {
+        Box::new(data)
+    }
+
+    async fn read_object<S: AsyncStore>(store: &S, bucket: &str, name: &str) -> Option<Vec<u8>> {
+        read_to_end(store.get_object(bucket, name).await?)
+            .await
+            .ok()
+    }
+
+    macro_rules! async_store_tests {
+        ($with_store:ident) => {
+            async_store_tests!(
+                $with_store,
+                async_store_create_bucket_should_add_bucket,
+                async_store_delete_bucket_should_reject_non_empty_bucket,
+                async_store_put_then_get_object_should_return_identical_data,
+                async_store_get_object_with_should_return_error_when_object_did_not_exist,
+                async_store_insert_object_should_not_replace_existing_object,
+                async_store_head_object_should_return_metadata,
+                async_store_list_objects_with_should_apply_prefix,
+                async_store_get_object_range_should_return_requested_bytes,
+                async_store_get_object_with_verify_checksum_should_read_intact_object,
+                async_store_remove_object_should_delete_existing_object,
+                async_store_conditional_writes_should_reject_stale_etag,
+                async_store_complete_multipart_upload_should_assemble_parts_in_order,
+            );
+        };
+        ($with_store:ident, $($test:ident),+ $(,)?) => {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    crate::asynchronous::store::tests::$test($with_store()).await;
+                }
+            )+
+        };
+    }
+
+    pub(crate) use async_store_tests;
+
+    pub async fn async_store_create_bucket_should_add_bucket<S: AsyncStore>(store: S) {
+        assert!(
+            store.create_bucket("earth").await.is_ok(),
+            "AsyncStore failed to create new bucket."
+        );
+        assert_eq!(
+            store.list_buckets().await,
+            vec!["earth"],
+            "AsyncStore failed to create bucket."
+        );
+    }
+
+    pub async fn async_store_delete_bucket_should_reject_non_empty_bucket<S: AsyncStore>(store: S) {
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .put_object("earth", "Moon", get_data_reader(b"Luna"))
+            .await;
+        let result = store.delete_bucket("earth").await;
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotEmpty)),
+            "AsyncStore deleted a non-empty bucket."
+        );
+        let options = DeleteBucketOptions::new().force();
+        assert!(store.delete_bucket_with("earth", options).await.is_ok());
+        assert!(
+            store.list_buckets().await.is_empty(),
+            "AsyncStore failed to force deletion of a non-empty bucket."
+        );
+    }
+
+    pub async fn async_store_put_then_get_object_should_return_identical_data<S: AsyncStore>(
+        store: S,
+    ) {
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .put_object("earth", "Moon", get_data_reader(b"Luna"))
+            .await;
+        assert_eq!(
+            read_object(&store, "earth", "Moon").await,
+            Some(b"Luna".to_vec()),
+            "AsyncStore failed to return object data."
+        );
+    }
+
+    pub async fn async_store_get_object_with_should_return_error_when_object_did_not_exist<
+        S: AsyncStore,
+    >(
+        store: S,
+    ) {
+        let _ = store.create_bucket("earth").await;
+        let result = store
+            .get_object_with("earth", "Moon", GetOptions::new())
+            .await;
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ObjectNotFound)),
+            "AsyncStore returned object that did not exist."
+        );
+    }
+
+    pub async fn async_store_insert_object_should_not_replace_existing_object<S: AsyncStore>(
+        store: S,
+    ) {
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .insert_object("earth", "Moon", get_data_reader(b"Luna"))
+            .await;
+        let result = store
+            .insert_object("earth", "Moon", get_data_reader(b"Selene"))
+            .await;
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ObjectAlreadyExist)),
+            "AsyncStore replaced existing object."
+        );
+        assert_eq!(
+            read_object(&store, "earth", "Moon").await,
+            Some(b"Luna".to_vec())
+        );
+    }
+
+    pub async fn async_store_head_object_should_return_metadata<S: AsyncStore>(store: S) {
+        let _ = store.create_bucket("earth").await;
+        let options = PutOptions::new().content_type("text/plain");
+        let _ = store
+            .put_object_with("earth", "Moon", get_data_reader(b"Luna"), options)
+            .await;
+        let metadata = store.head_object("earth", "Moon").await.unwrap_or_default();
+        assert_eq!(metadata.length, 4, "AsyncStore returned wrong length.");
+        assert_eq!(metadata.etag, checksum::digest(b"Luna"));
+        assert_eq!(metadata.content_type.as_deref(), Some("text/plain"));
+    }
+
+    pub async fn async_store_list_objects_with_should_apply_prefix<S: AsyncStore>(store: S) {
+        let _ = store.create_bucket("jupiter").await;
+        for name in &["Io", "Europa", "Ganymede", "Callisto"] {
+            let _ = store
+                .put_object("jupiter", name, get_data_reader(b""))
+                .await;
+        }
+        let list = store
+            .list_objects_with("jupiter", ListOptions::new().prefix("G"))
+            .await
+            .unwrap_or_default();
+        assert_eq!(
+            list.objects,
+            vec!["Ganymede"],
+            "AsyncStore failed to apply prefix."
+        );
+    }
+
+    pub async fn async_store_get_object_range_should_return_requested_bytes<S: AsyncStore>(
+        store: S,
+    ) {
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .put_object("earth", "Moon", get_data_reader(b"Selene"))
+            .await;
+        let output = match store
+            .get_object_range("earth", "Moon", ByteRange::from(1..3))
+            .await
+        {
+            Err(_) => Vec::new(),
+            Ok(stream) => read_to_end(stream).await.unwrap_or_default(),
+        };
+        assert_eq!(
+            output, b"el",
+            "AsyncStore failed to return the requested range."
+        );
+    }
+
+    pub async fn async_store_get_object_with_verify_checksum_should_read_intact_object<
+        S: AsyncStore,
+    >(
+        store: S,
+    ) {
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .put_object("earth", "Moon", get_data_reader(b"Luna"))
+            .await;
+        let result = match store
+            .get_object_with("earth", "Moon", GetOptions::new().verify_checksum())
+            .await
+        {
+            Err(_) => None,
+            Ok(stream) => read_to_end(stream).await.ok(),
+        };
+        assert_eq!(
+            result,
+            Some(b"Luna".to_vec()),
+            "AsyncStore failed to verify intact object."
+        );
+    }
+
+    pub async fn async_store_remove_object_should_delete_existing_object<S: AsyncStore>(store: S) {
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .put_object("earth", "Moon", get_data_reader(b"Luna"))
+            .await;
+        let _ = store.remove_object("earth", "Moon").await;
+        assert!(
+            store.head_object("earth", "Moon").await.is_none(),
+            "AsyncStore failed to remove object."
+        );
+    }
+
+    pub async fn async_store_conditional_writes_should_reject_stale_etag<S: AsyncStore>(store: S) {
+        let _ = store.create_bucket("earth").await;
+        let _ = store
+            .put_object("earth", "Moon", get_data_reader(b"Luna"))
+            .await;
+        let stale = Conditions::new().if_match(&checksum::digest(b"Lune"));
+        let options = PutOptions::new().conditions(stale.clone());
+        let put = store
+            .put_object_with("earth", "Moon", get_data_reader(b"Selene"), options)
+            .await;
+        let options = RemoveOptions::new().conditions(stale);
+        let removed = store.remove_object_with("earth", "Moon", options).await;
+        assert!(
+            [put, removed]
+                .iter()
+                .all(|result| matches!(result, Err(e) if matches!(e.kind(), ErrorKind::PreconditionFailed))),
+            "AsyncStore accepted a write with stale ETag."
+        );
+        assert_eq!(
+            read_object(&store, "earth", "Moon").await,
+            Some(b"Luna".to_vec()),
+            "AsyncStore changed object despite stale ETag."
+        );
+    }
+
+    pub async fn async_store_complete_multipart_upload_should_assemble_parts_in_order<
+        S: AsyncStore,
+    >(
+        store: S,
+    ) {
+        let _ = store.create_bucket("earth").await;
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .await
+            .unwrap_or_default();
+        let _ = store
+            .upload_part("earth", "Moon", &upload_id, 2, get_data_reader(b"na"))
+            .await;
+        let _ = store
+            .upload_part("earth", "Moon", &upload_id, 1, get_data_reader(b"Lu"))
+            .await;
+        let _ = store
+            .complete_multipart_upload("earth", "Moon", &upload_id)
+            .await;
+        assert_eq!(
+            read_object(&store, "earth", "Moon").await,
+            Some(b"Luna".to_vec()),
+            "AsyncStore failed to assemble multipart upload."
+        );
+    }
+}
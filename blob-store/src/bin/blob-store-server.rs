@@ -0,0 +1,66 @@
+use blob_store::fs::store::FsStore;
+use blob_store::in_memory::store::InMemoryStore;
+use blob_store::server;
+use blob_store::store::Store;
+use clap::clap_app;
+use hyper::Uri;
+use std::net::SocketAddr;
+
+#[tokio::main]
+pub async fn main() {
+    let args = clap_app!(app =>
+        (name: "blob-store-server")
+        (version: "0.1.0")
+        (about: "Serve a blob store over an S3 compatible HTTP API.")
+        (@arg ADDRESS: -a --address [ADDRESS] +takes_value "Address to listen on (default 127.0.0.1:9000)")
+        (@arg ROOT: -r --root [ROOT] +takes_value "Store objects under this directory instead of in memory")
+        (@arg WEBHOOK: -w --webhook [URL] +takes_value "Post every change to this HTTP URL as JSON")
+    ).get_matches();
+
+    let addr = match args
+        .value_of("ADDRESS")
+        .unwrap_or("127.0.0.1:9000")
+        .parse::<SocketAddr>()
+    {
+        Err(err) => {
+            eprintln!("Invalid address: {}", err);
+            std::process::exit(1);
+        }
+        Ok(addr) => addr,
+    };
+
+    let webhook = match args.value_of("WEBHOOK").map(|url| url.parse::<Uri>()) {
+        Some(Err(err)) => {
+            eprintln!("Invalid webhook URL: {}", err);
+            std::process::exit(1);
+        }
+        webhook => webhook.and_then(Result::ok),
+    };
+
+    println!("Listening on http://{}", addr);
+    let result = match args.value_of("ROOT") {
+        None => serve(InMemoryStore::new(), addr, webhook).await,
+        Some(root) => match FsStore::open(root) {
+            Err(err) => {
+                eprintln!("Error opening {}: {}", root, err);
+                std::process::exit(1);
+            }
+            Ok(store) => serve(store, addr, webhook).await,
+        },
+    };
+    if let Err(err) = result {
+        eprintln!("Server error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn serve<S: Store + Send + 'static>(
+    store: S,
+    addr: SocketAddr,
+    webhook: Option<Uri>,
+) -> hyper::Result<()> {
+    match webhook {
+        None => server::serve(store, addr).await,
+        Some(url) => server::serve_with_webhook(store, addr, url).await,
+    }
+}
@@ -0,0 +1,308 @@
+use blob_store::checksum;
+use blob_store::error::{Error, Kind as ErrorKind};
+use blob_store::fs::store::FsStore;
+use blob_store::list::ListOptions;
+use blob_store::store::{DeleteBucketOptions, GetOptions, PutOptions, Store};
+use blob_store::tag::{TagQuery, Tags};
+use clap::{clap_app, ArgMatches, Values};
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+
+const ROOT_VAR: &str = "BLOB_STORE_ROOT";
+
+enum Failure {
+    Usage(String),
+    Local(String, io::Error),
+    Store(Error),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Usage(message) => write!(f, "{}", message),
+            Failure::Local(path, err) => write!(f, "{}: {}", path, err),
+            Failure::Store(err) => write!(f, "{} [{}]", err, err.code()),
+        }
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Self {
+        Failure::Store(err)
+    }
+}
+
+fn main() {
+    let args = clap_app!(app =>
+        (name: "blob")
+        (version: "0.1.0")
+        (about: "Manage buckets and objects of a filesystem blob store.")
+        (@setting SubcommandRequiredElseHelp)
+        (@arg ROOT: -r --root [ROOT] +global "Root directory of the store\n(the default is $BLOB_STORE_ROOT)")
+        (@subcommand mb =>
+            (about: "Make a bucket")
+            (@arg BUCKET: +required "Bucket name"))
+        (@subcommand rb =>
+            (about: "Remove an empty bucket")
+            (@arg BUCKET: +required "Bucket name")
+            (@arg FORCE: -f --force "Also remove all objects of the bucket"))
+        (@subcommand ls =>
+            (about: "List buckets, or objects of a bucket")
+            (@arg BUCKET: "Bucket name, optionally followed by /prefix"))
+        (@subcommand put =>
+            (about: "Upload a file, - reads standard input")
+            (@arg FILE: +required "Local file")
+            (@arg TARGET: +required "Destination as bucket/key")
+            (@arg CONTENT_TYPE: -t --("content-type") [TYPE] "Content type of the object")
+            (@arg TAG: --tag [TAG] ... number_of_values(1) "Tag of the object as key=value"))
+        (@subcommand get =>
+            (about: "Download an object, to standard output by default")
+            (@arg SOURCE: +required "Source as bucket/key")
+            (@arg FILE: "Local file"))
+        (@subcommand rm =>
+            (about: "Remove an object")
+            (@arg TARGET: +required "Object as bucket/key"))
+        (@subcommand cp =>
+            (about: "Copy an object with its metadata")
+            (@arg SOURCE: +required "Source as bucket/key")
+            (@arg TARGET: +required "Destination as bucket/key"))
+        (@subcommand mv =>
+            (about: "Move an object with its metadata")
+            (@arg SOURCE: +required "Source as bucket/key")
+            (@arg TARGET: +required "Destination as bucket/key"))
+        (@subcommand tag =>
+            (about: "Replace the tags of an object, none removes them all")
+            (@arg TARGET: +required "Object as bucket/key")
+            (@arg TAGS: ... "Tags as key=value"))
+        (@subcommand find =>
+            (about: "List objects whose tags match a query, such as 'env=prod & !archived'")
+            (@arg BUCKET: +required "Bucket name")
+            (@arg QUERY: +required "Tag query"))
+    )
+    .get_matches();
+
+    if let Err(failure) = run(&args) {
+        eprintln!("blob: {}", failure);
+        std::process::exit(exit_code(&failure));
+    }
+}
+
+// Every store error kind gets its own exit code so scripts can tell failures apart. Usage errors
+// share clap's exit code.
+fn exit_code(failure: &Failure) -> i32 {
+    match failure {
+        Failure::Usage(_) => 1,
+        Failure::Local(_, _) => 2,
+        Failure::Store(err) => match err.kind() {
+            ErrorKind::IO => 2,
+            ErrorKind::BucketNotFound => 3,
+            ErrorKind::ObjectNotFound => 4,
+            ErrorKind::VersionNotFound => 5,
+            ErrorKind::ObjectAlreadyExist => 6,
+            ErrorKind::ChecksumMismatch => 7,
+            ErrorKind::InvalidRange => 8,
+            ErrorKind::UploadNotFound => 9,
+            ErrorKind::InvalidPart => 10,
+            ErrorKind::QuotaExceeded => 11,
+            ErrorKind::AuthenticationFailed => 12,
+            ErrorKind::BucketAlreadyExists => 13,
+            ErrorKind::BucketNotEmpty => 14,
+            ErrorKind::InvalidBucketName => 15,
+            ErrorKind::InvalidObjectName => 16,
+            ErrorKind::PreconditionFailed => 17,
+            ErrorKind::InvalidTag => 18,
+            ErrorKind::AccessDenied => 19,
+        },
+    }
+}
+
+fn run(args: &ArgMatches) -> Result<(), Failure> {
+    let root = match args.value_of("ROOT") {
+        Some(root) => root.into(),
+        None => match env::var(ROOT_VAR) {
+            Ok(root) => root,
+            Err(_) => {
+                return Err(Failure::Usage(format!(
+                    "no store root given, use --root or set {}",
+                    ROOT_VAR
+                )))
+            }
+        },
+    };
+    let mut store = match FsStore::open(&root) {
+        Err(err) => return Err(Failure::Local(root, err)),
+        Ok(store) => store,
+    };
+
+    match args.subcommand() {
+        ("mb", Some(args)) => Ok(store.create_bucket(args.value_of("BUCKET").unwrap())?),
+        ("rb", Some(args)) => {
+            let mut options = DeleteBucketOptions::new();
+            if args.is_present("FORCE") {
+                options = options.force();
+            }
+            Ok(store.delete_bucket_with(args.value_of("BUCKET").unwrap(), options)?)
+        }
+        ("ls", Some(args)) => list(&store, args.value_of("BUCKET")),
+        ("put", Some(args)) => put(
+            &mut store,
+            args.value_of("FILE").unwrap(),
+            args.value_of("TARGET").unwrap(),
+            args.value_of("CONTENT_TYPE"),
+            parse_tags(args.values_of("TAG"))?,
+        ),
+        ("get", Some(args)) => get(
+            &store,
+            args.value_of("SOURCE").unwrap(),
+            args.value_of("FILE"),
+        ),
+        ("rm", Some(args)) => {
+            let (bucket, key) = parse_object(args.value_of("TARGET").unwrap())?;
+            Ok(store.remove_object(bucket, key)?)
+        }
+        ("cp", Some(args)) => {
+            let (bucket, key) = parse_object(args.value_of("SOURCE").unwrap())?;
+            let (target_bucket, target_key) = parse_object(args.value_of("TARGET").unwrap())?;
+            Ok(store.copy_object(bucket, key, target_bucket, target_key)?)
+        }
+        ("mv", Some(args)) => {
+            let (bucket, key) = parse_object(args.value_of("SOURCE").unwrap())?;
+            let (target_bucket, target_key) = parse_object(args.value_of("TARGET").unwrap())?;
+            Ok(store.rename_object(bucket, key, target_bucket, target_key)?)
+        }
+        ("tag", Some(args)) => {
+            let (bucket, key) = parse_object(args.value_of("TARGET").unwrap())?;
+            let tags = parse_tags(args.values_of("TAGS"))?;
+            Ok(store.set_object_tags(bucket, key, tags)?)
+        }
+        ("find", Some(args)) => find(
+            &store,
+            args.value_of("BUCKET").unwrap(),
+            args.value_of("QUERY").unwrap(),
+        ),
+        _ => Err(Failure::Usage("unknown command".into())),
+    }
+}
+
+fn list(store: &FsStore, path: Option<&str>) -> Result<(), Failure> {
+    let (bucket, prefix) = match path {
+        None => {
+            let mut buckets = store.list_buckets();
+            buckets.sort_unstable();
+            for bucket in buckets {
+                println!("{}", bucket);
+            }
+            return Ok(());
+        }
+        Some(path) => split(path),
+    };
+    let list = store.list_objects_with(bucket, ListOptions::new().prefix(prefix))?;
+    for name in list.objects {
+        let length = store
+            .head_object(bucket, name)
+            .map(|metadata| metadata.length)
+            .unwrap_or_default();
+        println!("{:>12} {}", length, name);
+    }
+    Ok(())
+}
+
+fn put(
+    store: &mut FsStore,
+    file: &str,
+    target: &str,
+    content_type: Option<&str>,
+    tags: Tags,
+) -> Result<(), Failure> {
+    let (bucket, key) = parse_object(target)?;
+    let mut reader: Box<dyn Read> = match file {
+        "-" => Box::new(io::stdin()),
+        file => match File::open(file) {
+            Err(err) => return Err(Failure::Local(file.into(), err)),
+            Ok(file) => Box::new(file),
+        },
+    };
+    let mut options = PutOptions::new();
+    if let Some(content_type) = content_type {
+        options = options.content_type(content_type);
+    }
+    for (key, value) in &tags {
+        options = options.tag(key, value);
+    }
+    Ok(store.put_object_with(bucket, key, &mut reader, options)?)
+}
+
+fn get(store: &FsStore, source: &str, file: Option<&str>) -> Result<(), Failure> {
+    let (bucket, key) = parse_object(source)?;
+    let mut reader = store.get_object_with(bucket, key, GetOptions::new().verify_checksum())?;
+    let (path, mut writer): (&str, Box<dyn Write>) = match file {
+        None | Some("-") => ("stdout", Box::new(io::stdout())),
+        Some(file) => match File::create(file) {
+            Err(err) => return Err(Failure::Local(file.into(), err)),
+            Ok(writer) => (file, Box::new(writer)),
+        },
+    };
+    match io::copy(&mut reader, &mut writer).and_then(|_| writer.flush()) {
+        // A failed checksum belongs to the object rather than the local file.
+        Err(err) if checksum::is_mismatch(&err) => Err(Error::io(err, bucket, key).into()),
+        Err(err) => Err(Failure::Local(path.into(), err)),
+        Ok(_) => Ok(()),
+    }
+}
+
+fn find(store: &FsStore, bucket: &str, query: &str) -> Result<(), Failure> {
+    let query = match TagQuery::parse(query) {
+        None => {
+            return Err(Failure::Usage(format!(
+                "{} is not a valid tag query",
+                query
+            )))
+        }
+        Some(query) => query,
+    };
+    match store.find_objects(bucket, &query) {
+        None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "").into()),
+        Some(names) => {
+            for name in names {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn split(path: &str) -> (&str, &str) {
+    match path.find('/') {
+        None => (path, ""),
+        Some(i) => (&path[..i], &path[i + 1..]),
+    }
+}
+
+fn parse_object(path: &str) -> Result<(&str, &str), Failure> {
+    match split(path) {
+        (bucket, key) if !bucket.is_empty() && !key.is_empty() => Ok((bucket, key)),
+        _ => Err(Failure::Usage(format!(
+            "{} is not an object path, expected bucket/key",
+            path
+        ))),
+    }
+}
+
+fn parse_tags(values: Option<Values>) -> Result<Tags, Failure> {
+    let mut tags = Tags::new();
+    for value in values.into_iter().flatten() {
+        match value.find('=') {
+            None => {
+                return Err(Failure::Usage(format!(
+                    "{} is not a tag, expected key=value",
+                    value
+                )))
+            }
+            Some(i) => tags.insert(value[..i].into(), value[i + 1..].into()),
+        };
+    }
+    Ok(tags)
+}
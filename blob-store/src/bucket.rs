@@ -0,0 +1,86 @@
+use crate::error::{Error, Kind as ErrorKind, Result};
+use std::net::Ipv4Addr;
+
+const MIN_NAME_LENGTH: usize = 3;
+const MAX_NAME_LENGTH: usize = 63;
+
+// Bucket names must be usable as a DNS host name: 3 to 63 lowercase letters, digits, hyphens and
+// dots, where every dot separated label starts and ends with a letter or a digit. Names that look
+// like an IPv4 address are rejected.
+pub fn validate_name(name: &str) -> Result<()> {
+    match is_valid_name(name) {
+        false => Err(Error::new(ErrorKind::InvalidBucketName, name, "")),
+        true => Ok(()),
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    (MIN_NAME_LENGTH..=MAX_NAME_LENGTH).contains(&name.len())
+        && name.parse::<Ipv4Addr>().is_err()
+        && name.split('.').all(is_valid_label)
+}
+
+fn is_valid_label(label: &str) -> bool {
+    let bytes = label.as_bytes();
+    match (bytes.first(), bytes.last()) {
+        (Some(first), Some(last)) => {
+            is_lowercase_alphanumeric(*first)
+                && is_lowercase_alphanumeric(*last)
+                && bytes
+                    .iter()
+                    .all(|b| is_lowercase_alphanumeric(*b) || *b == b'-')
+        }
+        _ => false,
+    }
+}
+
+fn is_lowercase_alphanumeric(b: u8) -> bool {
+    b.is_ascii_lowercase() || b.is_ascii_digit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_name_should_accept_dns_names() {
+        for name in &[
+            "earth",
+            "mars-2",
+            "jupiter.moons",
+            "a.b.c",
+            "x".repeat(63).as_str(),
+        ] {
+            assert!(
+                validate_name(name).is_ok(),
+                "validate_name failed to accept {}.",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn validate_name_should_reject_invalid_names() {
+        let long = "x".repeat(64);
+        for name in &[
+            "",
+            "io",
+            "-mars",
+            "mars-",
+            "mars..moons",
+            ".mars",
+            "mars_2",
+            "mars 2",
+            "Earth",
+            "mars.Moons",
+            "192.168.1.1",
+            &long,
+        ] {
+            assert!(
+                matches!(validate_name(name), Err(err) if matches!(err.kind(), ErrorKind::InvalidBucketName)),
+                "validate_name failed to reject {}.",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::{error, fmt, io};
+
+// Passes data through while computing its SHA-256 digest, which is used as the object's ETag.
+pub struct ChecksumReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(inner: R) -> Self {
+        ChecksumReader {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn finish(self) -> String {
+        to_hex(&self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// The cause of the `InvalidData` error a `VerifyingReader` fails with. `Error::io` reports it as
+// `ChecksumMismatch`.
+#[derive(Debug)]
+pub struct ChecksumMismatch;
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "object checksum mismatch")
+    }
+}
+
+impl error::Error for ChecksumMismatch {}
+
+pub fn is_mismatch(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<ChecksumMismatch>())
+}
+
+// Computes the digest of the data while it is read and fails the read that reaches the end of
+// the data if it doesn't match the expected digest.
+pub struct VerifyingReader<R> {
+    inner: R,
+    hasher: Option<Sha256>,
+    expected: String,
+}
+
+impl<R: Read> VerifyingReader<R> {
+    pub fn new(inner: R, expected: &str) -> Self {
+        VerifyingReader {
+            inner,
+            hasher: Some(Sha256::new()),
+            expected: expected.into(),
+        }
+    }
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 || buf.is_empty() {
+            if let Some(hasher) = self.hasher.as_mut() {
+                hasher.update(&buf[..n]);
+            }
+            return Ok(n);
+        }
+        if let Some(hasher) = self.hasher.take() {
+            if to_hex(&hasher.finalize()) != self.expected {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, ChecksumMismatch));
+            }
+        }
+        Ok(0)
+    }
+}
+
+pub fn digest(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_should_return_sha256_hex() {
+        assert_eq!(
+            digest(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn from_hex_should_parse_what_to_hex_returns() {
+        assert_eq!(from_hex(&to_hex(b"Luna")), Some(b"Luna".to_vec()));
+        for hex in &["4", "4g", "é1"] {
+            assert_eq!(from_hex(hex), None, "from_hex accepted {}.", hex);
+        }
+    }
+
+    #[test]
+    fn checksum_reader_should_pass_data_through_and_compute_digest() {
+        let mut reader = ChecksumReader::new(&b"Luna"[..]);
+        let mut output = Vec::new();
+        let _ = io::copy(&mut reader, &mut output);
+        assert_eq!(output, b"Luna", "ChecksumReader altered the data.");
+        assert_eq!(reader.finish(), digest(b"Luna"));
+    }
+
+    #[test]
+    fn verifying_reader_should_accept_matching_data() {
+        let mut reader = VerifyingReader::new(&b"Luna"[..], &digest(b"Luna"));
+        assert!(
+            io::copy(&mut reader, &mut io::sink()).is_ok(),
+            "VerifyingReader rejected matching data."
+        );
+    }
+
+    #[test]
+    fn verifying_reader_should_reject_corrupted_data() {
+        let mut reader = VerifyingReader::new(&b"Lune"[..], &digest(b"Luna"));
+        let err = io::copy(&mut reader, &mut io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            is_mismatch(&err),
+            "VerifyingReader failed with another error."
+        );
+    }
+}
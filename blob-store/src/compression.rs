@@ -0,0 +1,134 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io;
+use std::io::{Read, Write};
+
+// How the data of an object is stored. Objects keep reporting their original length, only the
+// stored data is compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Compression::Zstd => zstd::encode_all(data, 0),
+            Compression::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data)?;
+                encoder.finish().map_err(io::Error::other)
+            }
+        }
+    }
+
+    pub fn decoder<'a, R: Read + Send + 'a>(
+        &self,
+        inner: R,
+    ) -> io::Result<Box<dyn Read + Send + 'a>> {
+        match self {
+            Compression::None => Ok(Box::new(inner)),
+            Compression::Gzip => Ok(Box::new(GzDecoder::new(inner))),
+            Compression::Zstd => Ok(Box::new(zstd::Decoder::new(inner)?)),
+            Compression::Lz4 => Ok(Box::new(lz4_flex::frame::FrameDecoder::new(inner))),
+        }
+    }
+}
+
+// Reads a range of the original data out of compressed data. Everything before the range has to
+// be decompressed and skipped, which happens on the first read.
+pub struct RangeDecoder<'a> {
+    decoder: io::Result<Box<dyn Read + Send + 'a>>,
+    skip: u64,
+    remaining: u64,
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new<R: Read + Send + 'a>(
+        compression: Compression,
+        inner: R,
+        start: u64,
+        end: u64,
+    ) -> Self {
+        RangeDecoder {
+            decoder: compression.decoder(inner),
+            skip: start,
+            remaining: end.saturating_sub(start),
+        }
+    }
+}
+
+impl<'a> Read for RangeDecoder<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let decoder = match self.decoder.as_mut() {
+            Err(err) => return Err(io::Error::new(err.kind(), err.to_string())),
+            Ok(decoder) => decoder,
+        };
+        if self.skip > 0 {
+            let skipped = io::copy(&mut decoder.take(self.skip), &mut io::sink())?;
+            if skipped < self.skip {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.skip = 0;
+        }
+        let max = self.remaining.min(buf.len() as u64) as usize;
+        let n = decoder.read(&mut buf[..max])?;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_text() -> Vec<u8> {
+        "Mercury Venus Earth Mars ".repeat(100).into_bytes()
+    }
+
+    #[test]
+    fn compression_should_round_trip_data() {
+        let text = get_text();
+        for compression in &[
+            Compression::None,
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::Lz4,
+        ] {
+            let stored = compression.compress(&text).unwrap_or_default();
+            let mut output = Vec::new();
+            let _ = RangeDecoder::new(*compression, &stored[..], 0, text.len() as u64)
+                .read_to_end(&mut output);
+            assert_eq!(output, text, "{:?} failed to round trip data.", compression);
+        }
+    }
+
+    #[test]
+    fn compression_should_shrink_repetitive_data() {
+        let text = get_text();
+        let stored = Compression::Zstd.compress(&text).unwrap_or_default();
+        assert!(stored.len() * 10 < text.len());
+    }
+
+    #[test]
+    fn range_decoder_should_output_part_of_data() {
+        let text = get_text();
+        let stored = Compression::Gzip.compress(&text).unwrap_or_default();
+        let mut output = Vec::new();
+        let _ = RangeDecoder::new(Compression::Gzip, &stored[..], 8, 13).read_to_end(&mut output);
+        assert_eq!(
+            output, b"Venus",
+            "RangeDecoder failed to output the requested range."
+        );
+    }
+}
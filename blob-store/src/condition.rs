@@ -0,0 +1,182 @@
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::metadata::ObjectMetadata;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Entity tag matching any existing object.
+pub const ANY: &str = "*";
+
+// Preconditions on the current state of an object, evaluated like their HTTP counterparts. An
+// operation whose preconditions don't hold fails with `PreconditionFailed` and changes nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conditions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+    if_unmodified_since: Option<SystemTime>,
+}
+
+impl Conditions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Holds when the object exists and has this ETag, or any ETag for `ANY`.
+    pub fn if_match(mut self, etag: &str) -> Self {
+        self.if_match = Some(etag.into());
+        self
+    }
+
+    // Holds when the object doesn't have this ETag. With `ANY` it only holds when there is no
+    // object, which makes a write create the object or fail.
+    pub fn if_none_match(mut self, etag: &str) -> Self {
+        self.if_none_match = Some(etag.into());
+        self
+    }
+
+    // Ignored when an ETag not to match is given.
+    pub fn if_modified_since(mut self, time: SystemTime) -> Self {
+        self.if_modified_since = Some(time);
+        self
+    }
+
+    // Ignored when an ETag to match is given.
+    pub fn if_unmodified_since(mut self, time: SystemTime) -> Self {
+        self.if_unmodified_since = Some(time);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Conditions::default()
+    }
+
+    // Evaluates the conditions against the current version of an object, `None` when there is
+    // none.
+    pub fn check(&self, bucket: &str, name: &str, current: Option<&ObjectMetadata>) -> Result<()> {
+        match self.matches(current) && self.is_modified(current) {
+            false => Err(Error::new(ErrorKind::PreconditionFailed, bucket, name)),
+            true => Ok(()),
+        }
+    }
+
+    // Whether the conditions only fail because the object didn't change, which a read reports
+    // as not modified rather than as a failed precondition.
+    pub fn is_not_modified(&self, current: Option<&ObjectMetadata>) -> bool {
+        self.matches(current) && !self.is_modified(current)
+    }
+
+    fn matches(&self, current: Option<&ObjectMetadata>) -> bool {
+        match (&self.if_match, self.if_unmodified_since, current) {
+            (None, None, _) => true,
+            (_, _, None) => false,
+            (Some(etag), _, Some(metadata)) => etag == ANY || *etag == metadata.etag,
+            (None, Some(time), Some(metadata)) => seconds(metadata.modified) <= seconds(time),
+        }
+    }
+
+    fn is_modified(&self, current: Option<&ObjectMetadata>) -> bool {
+        match (&self.if_none_match, self.if_modified_since, current) {
+            (None, None, _) | (_, _, None) => true,
+            (Some(etag), _, Some(metadata)) => etag != ANY && *etag != metadata.etag,
+            (None, Some(time), Some(metadata)) => seconds(metadata.modified) > seconds(time),
+        }
+    }
+}
+
+// Times are compared to the second, the resolution of HTTP dates, so the modification time
+// handed out with an object can be given back as is.
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn metadata(etag: &str, modified: u64) -> ObjectMetadata {
+        let mut metadata = ObjectMetadata::new();
+        metadata.etag = etag.into();
+        metadata.modified = UNIX_EPOCH + Duration::from_millis(modified * 1000 + 500);
+        metadata
+    }
+
+    fn holds(conditions: &Conditions, current: Option<&ObjectMetadata>) -> bool {
+        conditions.check("earth", "Moon", current).is_ok()
+    }
+
+    #[test]
+    fn conditions_if_match_should_require_current_etag() {
+        let current = metadata("abc", 10);
+        let any = Conditions::new().if_match(ANY);
+        assert!(holds(&Conditions::new().if_match("abc"), Some(&current)));
+        assert!(holds(&any, Some(&current)));
+        assert!(
+            !holds(&Conditions::new().if_match("def"), Some(&current)),
+            "Conditions failed to reject a stale ETag."
+        );
+        assert!(
+            !holds(&any, None),
+            "Conditions failed to reject a missing object."
+        );
+    }
+
+    #[test]
+    fn conditions_if_none_match_should_reject_current_etag() {
+        let current = metadata("abc", 10);
+        let absent = Conditions::new().if_none_match(ANY);
+        assert!(holds(&absent, None));
+        assert!(holds(
+            &Conditions::new().if_none_match("def"),
+            Some(&current)
+        ));
+        assert!(
+            !holds(&absent, Some(&current)),
+            "Conditions failed to reject an existing object."
+        );
+        assert!(
+            Conditions::new()
+                .if_none_match("abc")
+                .is_not_modified(Some(&current)),
+            "Conditions failed to report an unmodified object."
+        );
+    }
+
+    #[test]
+    fn conditions_should_compare_times_to_the_second() {
+        let current = metadata("abc", 10);
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        assert!(holds(
+            &Conditions::new().if_modified_since(at(9)),
+            Some(&current)
+        ));
+        assert!(
+            !holds(&Conditions::new().if_modified_since(at(10)), Some(&current)),
+            "Conditions took an object modified within a second as modified."
+        );
+        assert!(holds(
+            &Conditions::new().if_unmodified_since(at(10)),
+            Some(&current)
+        ));
+        assert!(!holds(
+            &Conditions::new().if_unmodified_since(at(9)),
+            Some(&current)
+        ));
+    }
+
+    #[test]
+    fn conditions_should_ignore_times_when_etags_are_given() {
+        let current = metadata("abc", 10);
+        let past = UNIX_EPOCH;
+        let conditions = Conditions::new()
+            .if_match("abc")
+            .if_unmodified_since(past)
+            .if_none_match("def")
+            .if_modified_since(UNIX_EPOCH + Duration::from_secs(20));
+        assert!(
+            holds(&conditions, Some(&current)),
+            "Conditions failed to give ETags precedence over times."
+        );
+    }
+}
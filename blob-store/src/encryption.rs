@@ -0,0 +1,3 @@
+pub mod key;
+pub mod store;
+pub mod stream;
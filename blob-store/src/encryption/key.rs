@@ -0,0 +1,129 @@
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{thread_rng, Rng};
+use std::convert::TryInto;
+use std::path::Path;
+use std::{env, fs, io};
+
+pub const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+pub const WRAPPED_KEY_SIZE: usize = NONCE_SIZE + KEY_SIZE + TAG_SIZE;
+
+// The key supplied by the caller. It never encrypts object data itself, only the random data key
+// of every object, so each object can be read with nothing but the master key and its own data.
+#[derive(Clone)]
+pub struct MasterKey {
+    key: [u8; KEY_SIZE],
+}
+
+impl MasterKey {
+    pub fn new(key: [u8; KEY_SIZE]) -> Self {
+        MasterKey { key }
+    }
+
+    pub fn generate() -> Self {
+        MasterKey::new(random_key())
+    }
+
+    // Parses a key written as 64 hex digits.
+    pub fn from_hex(hex: &str) -> io::Result<Self> {
+        let hex = hex.trim();
+        if hex.len() != KEY_SIZE * 2 || !hex.is_ascii() {
+            return Err(invalid_key());
+        }
+        let mut key = [0u8; KEY_SIZE];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = match u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16) {
+                Err(_) => return Err(invalid_key()),
+                Ok(byte) => byte,
+            };
+        }
+        Ok(MasterKey::new(key))
+    }
+
+    // Reads a key file holding either the 32 bytes of the key or its hex form.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        match data.as_slice().try_into() {
+            Ok(key) => Ok(MasterKey::new(key)),
+            Err(_) => match String::from_utf8(data) {
+                Err(_) => Err(invalid_key()),
+                Ok(hex) => MasterKey::from_hex(&hex),
+            },
+        }
+    }
+
+    // Reads a key from an environment variable holding its hex form.
+    pub fn from_env(var: &str) -> io::Result<Self> {
+        match env::var(var) {
+            Err(err) => Err(io::Error::new(io::ErrorKind::NotFound, err)),
+            Ok(hex) => MasterKey::from_hex(&hex),
+        }
+    }
+
+    pub fn wrap_key(&self, key: &[u8; KEY_SIZE]) -> io::Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        thread_rng().fill(&mut nonce);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.key));
+        let sealed = match cipher.encrypt(Nonce::from_slice(&nonce), &key[..]) {
+            Err(_) => return Err(io::Error::other("failed to wrap data key")),
+            Ok(sealed) => sealed,
+        };
+        let mut wrapped = nonce.to_vec();
+        wrapped.extend_from_slice(&sealed);
+        Ok(wrapped)
+    }
+
+    // Returns None when the key was wrapped with another master key or has been tampered with.
+    pub fn unwrap_key(&self, wrapped: &[u8]) -> Option<[u8; KEY_SIZE]> {
+        if wrapped.len() != WRAPPED_KEY_SIZE {
+            return None;
+        }
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.key));
+        let nonce = Nonce::from_slice(&wrapped[..NONCE_SIZE]);
+        let key = cipher.decrypt(nonce, &wrapped[NONCE_SIZE..]).ok()?;
+        key.as_slice().try_into().ok()
+    }
+}
+
+pub fn random_key() -> [u8; KEY_SIZE] {
+    let mut key = [0u8; KEY_SIZE];
+    thread_rng().fill(&mut key);
+    key
+}
+
+fn invalid_key() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "master key must be 32 bytes or 64 hex digits",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn master_key_should_unwrap_its_own_keys_only() {
+        let master = MasterKey::generate();
+        let key = random_key();
+        let wrapped = master.wrap_key(&key).unwrap_or_default();
+        assert_eq!(wrapped.len(), WRAPPED_KEY_SIZE);
+        assert_eq!(master.unwrap_key(&wrapped), Some(key));
+        assert_eq!(
+            MasterKey::generate().unwrap_key(&wrapped),
+            None,
+            "MasterKey unwrapped a key of another master key."
+        );
+    }
+
+    #[test]
+    fn master_key_from_hex_should_parse_64_digits() {
+        let hex = "00".repeat(31) + "ff";
+        let master = MasterKey::from_hex(&hex);
+        assert!(matches!(master, Ok(master) if master.key[31] == 0xff));
+        assert!(MasterKey::from_hex("ff").is_err());
+        assert!(MasterKey::from_hex(&"zz".repeat(32)).is_err());
+    }
+}
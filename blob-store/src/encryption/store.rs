@@ -0,0 +1,586 @@
+use crate::checksum;
+use crate::condition::Conditions;
+use crate::encryption::key::MasterKey;
+use crate::encryption::stream::{
+    chunk_offset, data_length, segment_length, DecryptingReader, EncryptingReader, Trailer,
+    CHUNK_SIZE, HEADER_SIZE, TRAILER_SIZE,
+};
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::store::{
+    CopyOptions, DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions, Store,
+};
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Read;
+use std::ops::Range;
+
+// Encrypts objects of any store. Every upload and every uploaded part gets its own data key,
+// wrapped by the master key and stored along with the data, so the wrapped store only ever holds
+// encrypted data. Lengths and ETags are those of the original data, except for older versions
+// in version listings: they can't be read from the middle, so their stored ETag is reported, and
+// their stored length too when they were multipart uploads. Conditions are checked against the
+// original data as well. Multipart uploads are checked when they complete: the expected checksum
+// is that of the single part, or the digest of the part digests reported as ETag.
+pub struct EncryptedStore<S> {
+    inner: S,
+    master: MasterKey,
+    uploads: HashMap<String, PendingUpload>,
+}
+
+// The options of a multipart upload the inner store can't check on encrypted data, and the digest
+// of the original data of every part uploaded so far.
+struct PendingUpload {
+    options: PutOptions,
+    digests: BTreeMap<u32, [u8; 32]>,
+}
+
+// Where a segment starts in the stored data, the length of its data and its sealed trailer.
+struct Extent {
+    start: u64,
+    length: u64,
+    trailer: Vec<u8>,
+}
+
+impl<S: Store> EncryptedStore<S> {
+    pub fn new(inner: S, master: MasterKey) -> Self {
+        EncryptedStore {
+            inner,
+            master,
+            uploads: HashMap::<String, PendingUpload>::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn encrypt<F>(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+        write: F,
+    ) -> Result<(String, Option<[u8; 32]>)>
+    where
+        F: FnOnce(&mut S, &mut Box<dyn Read>, PutOptions) -> Result<String>,
+    {
+        self.check(bucket, name, options.preconditions())?;
+        // The inner store needs a reader of its own, the caller's one is used up anyway.
+        let plain = std::mem::replace(reader, Box::new(io::empty()));
+        let encrypting = match EncryptingReader::new(plain, &self.master, options.clone()) {
+            Err(e) => return Err(Error::io(e, bucket, name)),
+            Ok(encrypting) => encrypting,
+        };
+        let mismatch = encrypting.mismatch();
+        let digest = encrypting.digest();
+        let mut encrypted = Box::new(encrypting) as Box<dyn Read>;
+        let options = options.without_checksum().conditions(Conditions::new());
+        match write(&mut self.inner, &mut encrypted, options) {
+            Err(_) if mismatch.get() => Err(Error::new(ErrorKind::ChecksumMismatch, bucket, name)),
+            Err(e) => Err(e),
+            Ok(etag) => Ok((etag, digest.get())),
+        }
+    }
+
+    // The inner store only knows the ETags of the encrypted data, so conditions are checked here.
+    // A missing bucket is left for the inner store to report.
+    fn check(&self, bucket: &str, name: &str, conditions: &Conditions) -> Result<()> {
+        if conditions.is_empty() || !self.inner.list_buckets().contains(&bucket) {
+            return Ok(());
+        }
+        conditions.check(bucket, name, self.head_object(bucket, name).as_ref())
+    }
+
+    fn read_stored(&self, bucket: &str, name: &str, range: Range<u64>) -> Result<Vec<u8>> {
+        let mut reader = self
+            .inner
+            .get_object_range(bucket, name, ByteRange::from(range))?;
+        let mut data = Vec::new();
+        match reader.read_to_end(&mut data) {
+            Err(e) => Err(Error::io(e, bucket, name)),
+            Ok(_) => Ok(data),
+        }
+    }
+
+    // Finds the segments of an object by walking their trailers from the end of the data.
+    fn extents(&self, bucket: &str, name: &str, stored: u64) -> Result<Vec<Extent>> {
+        let corrupted = || Error::new(ErrorKind::AuthenticationFailed, bucket, name);
+        let mut extents = Vec::<Extent>::new();
+        let mut end = stored;
+        while end > 0 {
+            let start = match end.checked_sub(TRAILER_SIZE as u64) {
+                None => return Err(corrupted()),
+                Some(start) => start,
+            };
+            let trailer = self.read_stored(bucket, name, start..end)?;
+            let length = match Trailer::length(&trailer) {
+                None => return Err(corrupted()),
+                Some(length) => length,
+            };
+            end = match end.checked_sub(segment_length(length)) {
+                None => return Err(corrupted()),
+                Some(end) => end,
+            };
+            extents.push(Extent {
+                start: end,
+                length,
+                trailer,
+            });
+        }
+        extents.reverse();
+        Ok(extents)
+    }
+
+    // The digest of single uploads, and a digest of the part digests for multipart uploads, as
+    // their data was encrypted part by part. Trailers are decrypted with the keys of their
+    // segments, which fails for trailers that were tampered with.
+    fn etag(&self, bucket: &str, name: &str, extents: &[Extent]) -> Result<String> {
+        let mut digests = Vec::<[u8; 32]>::new();
+        for extent in extents {
+            let header = self.read_stored(
+                bucket,
+                name,
+                extent.start..extent.start + HEADER_SIZE as u64,
+            )?;
+            match Trailer::open(&self.master, &header, &extent.trailer) {
+                None => return Err(Error::new(ErrorKind::AuthenticationFailed, bucket, name)),
+                Some(trailer) => digests.push(trailer.digest),
+            }
+        }
+        Ok(etag_of(&digests))
+    }
+}
+
+fn etag_of(digests: &[[u8; 32]]) -> String {
+    match digests {
+        [digest] => checksum::to_hex(digest),
+        _ => {
+            let mut hasher = Sha256::new();
+            for digest in digests {
+                hasher.update(digest);
+            }
+            let etag = checksum::to_hex(&hasher.finalize());
+            format!("{}-{}", etag, digests.len())
+        }
+    }
+}
+
+impl<S: Store> Store for EncryptedStore<S> {
+    fn list_buckets(&self) -> Vec<&str> {
+        self.inner.list_buckets()
+    }
+
+    fn create_bucket(&mut self, name: &str) -> Result<()> {
+        self.inner.create_bucket(name)
+    }
+
+    fn delete_bucket_with(&mut self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        self.inner.delete_bucket_with(name, options)
+    }
+
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Result<ObjectList<'_>> {
+        self.inner.list_objects_with(bucket, options)
+    }
+
+    fn insert_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.encrypt(bucket, name, reader, options, |inner, reader, options| {
+            inner
+                .insert_object_with(bucket, name, reader, options)
+                .map(|_| String::new())
+        })
+        .map(|_| ())
+    }
+
+    fn put_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.encrypt(bucket, name, reader, options, |inner, reader, options| {
+            inner
+                .put_object_with(bucket, name, reader, options)
+                .map(|_| String::new())
+        })
+        .map(|_| ())
+    }
+
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        let mut metadata = self.inner.head_object(bucket, name)?;
+        let extents = self.extents(bucket, name, metadata.length).ok()?;
+        metadata.length = extents.iter().map(|extent| extent.length).sum();
+        metadata.etag = self.etag(bucket, name, &extents).ok()?;
+        Some(metadata)
+    }
+
+    // Older versions have their stored length and ETag, as in version listings.
+    fn head_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Option<ObjectMetadata> {
+        let metadata = self.inner.head_object_version(bucket, name, version_id)?;
+        match self.head_object(bucket, name) {
+            Some(latest) if latest.version_id == version_id => Some(latest),
+            _ => Some(metadata),
+        }
+    }
+
+    // Tags are kept in the clear, like the rest of the metadata.
+    fn set_object_tags(&mut self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        self.inner.set_object_tags(bucket, name, tags)
+    }
+
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<&str>> {
+        self.inner.find_objects(bucket, query)
+    }
+
+    fn get_object_with<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<Box<dyn Read + 'a>> {
+        // Older versions are known by their stored ETag, conditions on them are the inner store's.
+        let options = match options.version() {
+            Some(_) => options,
+            None => {
+                self.check(bucket, name, options.preconditions())?;
+                options.conditions(Conditions::new())
+            }
+        };
+        let stored = self.inner.get_object_with(bucket, name, options)?;
+        let mut reader = DecryptingReader::new(stored, &self.master, bucket, name);
+        match reader.start() {
+            Err(_) => Err(Error::new(ErrorKind::AuthenticationFailed, bucket, name)),
+            Ok(_) => Ok(Box::new(reader)),
+        }
+    }
+
+    fn get_object_range<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + 'a>> {
+        let stored = match self.inner.head_object(bucket, name) {
+            None => return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            Some(metadata) => metadata.length,
+        };
+        let extents = self.extents(bucket, name, stored)?;
+        let length = extents.iter().map(|extent| extent.length).sum();
+        let range = match range.resolve(length) {
+            None => return Err(Error::new(ErrorKind::InvalidRange, bucket, name)),
+            Some(range) => range,
+        };
+        // Decryption starts at the chunk holding the first byte of the range.
+        let mut offset = range.start;
+        let mut extents = extents.iter();
+        let extent = loop {
+            match extents.next() {
+                None => return Err(Error::new(ErrorKind::InvalidRange, bucket, name)),
+                Some(extent) if offset < extent.length => break extent,
+                Some(extent) => offset -= extent.length,
+            }
+        };
+        let chunk = offset / CHUNK_SIZE as u64;
+        let header = self.read_stored(
+            bucket,
+            name,
+            extent.start..extent.start + HEADER_SIZE as u64,
+        )?;
+        let start = extent.start + chunk_offset(chunk);
+        let stored = self
+            .inner
+            .get_object_range(bucket, name, ByteRange::From(start))?;
+        match DecryptingReader::new(stored, &self.master, bucket, name).resume(&header, chunk) {
+            None => Err(Error::new(ErrorKind::AuthenticationFailed, bucket, name)),
+            Some(reader) => {
+                let skip = offset - chunk * CHUNK_SIZE as u64;
+                Ok(Box::new(reader.range(skip, range.end - range.start)))
+            }
+        }
+    }
+
+    // The encrypted data isn't tied to the name it was written under, so copies share it as is.
+    fn copy_object_with(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+        options: CopyOptions,
+    ) -> Result<()> {
+        self.inner
+            .copy_object_with(src_bucket, src_name, dst_bucket, dst_name, options)
+    }
+
+    fn rename_object(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        self.inner
+            .rename_object(src_bucket, src_name, dst_bucket, dst_name)
+    }
+
+    fn remove_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        self.check(bucket, name, options.preconditions())?;
+        self.inner.remove_object(bucket, name)
+    }
+
+    fn remove_object_version(&mut self, bucket: &str, name: &str, version_id: &str) -> Result<()> {
+        self.inner.remove_object_version(bucket, name, version_id)
+    }
+
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        let mut versions = self.inner.list_object_versions(bucket)?;
+        for version in versions.iter_mut().filter(|v| !v.is_delete_marker) {
+            if version.is_latest {
+                if let Some(metadata) = self.head_object(bucket, &version.name) {
+                    version.length = metadata.length;
+                    version.etag = metadata.etag;
+                    continue;
+                }
+            }
+            if let Some(length) = data_length(version.length) {
+                version.length = length;
+            }
+        }
+        Some(versions)
+    }
+
+    fn create_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        let inner_options = options
+            .clone()
+            .without_checksum()
+            .conditions(Conditions::new());
+        let upload_id = self
+            .inner
+            .create_multipart_upload(bucket, name, inner_options)?;
+        let upload = PendingUpload {
+            options,
+            digests: BTreeMap::new(),
+        };
+        self.uploads.insert(upload_id.clone(), upload);
+        Ok(upload_id)
+    }
+
+    fn upload_part(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String> {
+        let (etag, digest) = self.encrypt(
+            bucket,
+            name,
+            reader,
+            PutOptions::new(),
+            |inner, reader, _| inner.upload_part(bucket, name, upload_id, part_number, reader),
+        )?;
+        if let (Some(upload), Some(digest)) = (self.uploads.get_mut(upload_id), digest) {
+            upload.digests.insert(part_number, digest);
+        }
+        Ok(etag)
+    }
+
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        self.inner.list_multipart_uploads(bucket)
+    }
+
+    fn abort_multipart_upload(&mut self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        self.inner.abort_multipart_upload(bucket, name, upload_id)?;
+        self.uploads.remove(upload_id);
+        Ok(())
+    }
+
+    fn complete_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        if let Some(upload) = self.uploads.get(upload_id) {
+            self.check(bucket, name, upload.options.preconditions())?;
+            let digests = upload.digests.values().copied().collect::<Vec<_>>();
+            if !digests.is_empty() && !upload.options.verify(&etag_of(&digests)) {
+                return Err(Error::new(ErrorKind::ChecksumMismatch, bucket, name));
+            }
+        }
+        self.inner
+            .complete_multipart_upload(bucket, name, upload_id)?;
+        self.uploads.remove(upload_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::in_memory::store::InMemoryStore;
+    use crate::store::tests::{get_data_reader, store_tests};
+
+    fn with_store(test: fn(EncryptedStore<InMemoryStore>)) {
+        test(EncryptedStore::new(
+            InMemoryStore::new(),
+            MasterKey::generate(),
+        ));
+    }
+
+    // Multipart uploads are encrypted part by part, so they get a digest of the part digests
+    // as their ETag and the shared multipart ETag case doesn't apply.
+    store_tests!(
+        with_store,
+        store_list_buckets_should_return_all_bucket_names,
+        store_create_bucket_should_add_bucket_when_bucket_did_not_exist,
+        store_create_bucket_should_reject_existing_bucket,
+        store_create_bucket_should_reject_invalid_name,
+        store_delete_bucket_should_delete_existing_bucket,
+        store_delete_bucket_should_return_error_when_bucket_did_not_exist,
+        store_delete_bucket_should_reject_non_empty_bucket,
+        store_delete_bucket_with_force_should_delete_non_empty_bucket,
+        store_list_objects_should_list_all_object_names_in_bucket,
+        store_list_objects_should_return_sorted_names,
+        store_list_objects_with_should_apply_prefix_delimiter_and_max_keys,
+        store_insert_object_should_add_object_when_object_did_not_exist,
+        store_insert_object_should_return_error_when_bucket_did_not_exist,
+        store_insert_object_should_not_add_object_when_object_already_exist,
+        store_put_object_should_replace_existing_object,
+        store_head_object_should_return_object_length,
+        store_head_object_should_return_supplied_metadata,
+        store_head_object_should_keep_creation_time_on_replace,
+        store_head_object_should_return_none_when_object_did_not_exist,
+        store_head_object_should_return_sha256_etag,
+        store_set_object_tags_should_keep_data_and_metadata,
+        store_set_object_tags_should_reject_invalid_tags,
+        store_find_objects_should_return_objects_with_matching_tags,
+        store_put_object_should_accept_matching_checksum,
+        store_put_object_should_reject_mismatched_checksum,
+        store_put_object_with_if_match_should_reject_stale_etag,
+        store_put_object_with_if_none_match_should_only_create_object,
+        store_get_object_should_return_existing_object,
+        store_get_object_with_should_return_error_when_object_did_not_exist,
+        store_get_object_with_verify_checksum_should_read_intact_object,
+        store_get_object_with_conditions_should_check_latest_version,
+        store_get_object_should_return_identical_data,
+        store_get_object_range_should_return_requested_bytes,
+        store_get_object_range_should_reject_unsatisfiable_range,
+        store_copy_object_should_keep_data_and_metadata,
+        store_copy_object_with_replaced_metadata_should_use_new_metadata,
+        store_copy_object_should_keep_tags_unless_replaced,
+        store_copy_object_should_stay_independent_of_source,
+        store_copy_object_should_return_error_when_source_or_bucket_did_not_exist,
+        store_rename_object_should_move_object_across_buckets,
+        store_remove_object_should_delete_existing_object,
+        store_remove_object_with_if_match_should_keep_changed_object,
+        store_list_object_versions_should_return_null_version_without_versioning,
+        store_get_object_with_version_id_should_return_null_version,
+        store_get_object_with_unknown_version_id_should_return_error,
+        store_remove_object_version_should_delete_null_version,
+        store_multipart_upload_should_not_be_visible_until_completed,
+        store_abort_multipart_upload_should_discard_upload,
+        store_upload_part_should_reject_invalid_part_number,
+        store_complete_multipart_upload_should_reject_upload_without_parts,
+        store_complete_multipart_upload_should_keep_upload_when_it_failed,
+        store_complete_multipart_upload_should_accept_matching_checksum,
+    );
+
+    fn read_all(reader: Result<Box<dyn Read + '_>>) -> Vec<u8> {
+        let mut output = Vec::new();
+        if let Ok(mut reader) = reader {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        output
+    }
+
+    #[test]
+    fn encrypted_store_should_not_store_original_data() {
+        let mut store = EncryptedStore::new(InMemoryStore::new(), MasterKey::generate());
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let store = store.into_inner();
+        let stored = read_all(store.get_object_with("earth", "Moon", GetOptions::new()));
+        assert!(
+            !stored.windows(4).any(|window| window == b"Luna"),
+            "EncryptedStore stored original data."
+        );
+    }
+
+    #[test]
+    fn encrypted_store_should_reject_other_master_key() {
+        let mut store = EncryptedStore::new(InMemoryStore::new(), MasterKey::generate());
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let store = EncryptedStore::new(store.into_inner(), MasterKey::generate());
+        let result = store.get_object_with("earth", "Moon", GetOptions::new());
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::AuthenticationFailed)),
+            "EncryptedStore decrypted object with another master key."
+        );
+    }
+
+    #[test]
+    fn encrypted_store_should_read_ranges_across_chunks_and_parts() {
+        let mut store = EncryptedStore::new(InMemoryStore::new(), MasterKey::generate());
+        let _ = store.create_bucket("earth");
+        let data = (0..CHUNK_SIZE * 3)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<u8>>();
+        let (first, second) = data.split_at(CHUNK_SIZE + 10);
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        for (number, part) in [(1, first), (2, second)].iter() {
+            let mut reader = Box::new(io::Cursor::new(part.to_vec())) as Box<dyn Read>;
+            let _ = store.upload_part("earth", "Moon", &upload_id, *number, &mut reader);
+        }
+        let _ = store.complete_multipart_upload("earth", "Moon", &upload_id);
+        assert_eq!(
+            store.head_object("earth", "Moon").map(|m| m.length),
+            Some(data.len() as u64)
+        );
+        assert_eq!(
+            read_all(store.get_object_with("earth", "Moon", GetOptions::new())),
+            data
+        );
+        let start = CHUNK_SIZE as u64 - 5;
+        let end = CHUNK_SIZE as u64 * 2 + 20;
+        let output = read_all(store.get_object_range("earth", "Moon", ByteRange::from(start..end)));
+        assert_eq!(
+            output,
+            &data[start as usize..end as usize],
+            "EncryptedStore returned wrong range."
+        );
+    }
+}
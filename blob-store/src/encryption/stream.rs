@@ -0,0 +1,569 @@
+use crate::checksum;
+use crate::encryption::key::{random_key, MasterKey, WRAPPED_KEY_SIZE};
+use crate::error::{Error, Kind as ErrorKind};
+use crate::store::PutOptions;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::convert::TryInto;
+use std::io;
+use std::io::Read;
+use std::rc::Rc;
+
+// Encrypted data is made of segments, one per upload or uploaded part. A segment starts with a
+// header holding its wrapped data key, continues with chunks of at most CHUNK_SIZE bytes each
+// sealed on its own, and ends with a trailer holding the length of its data in the clear and the
+// digest of its data sealed, so the stored data can't be matched against known contents. Every
+// chunk but the last one of a segment is full, the last one is sealed with the trailer so that
+// cutting, reordering or swapping chunks fails authentication. Segments of the first format,
+// which kept the digest in the clear, are no longer read.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+const MAGIC: &[u8] = b"BSE2";
+const PREFIX_SIZE: usize = 7;
+const TAG_SIZE: usize = 16;
+const LENGTH_SIZE: usize = 4;
+pub const HEADER_SIZE: usize = MAGIC.len() + WRAPPED_KEY_SIZE + PREFIX_SIZE;
+pub const TRAILER_SIZE: usize = 8 + 32 + TAG_SIZE;
+
+// The stored length of a segment holding the given length of data.
+pub fn segment_length(length: u64) -> u64 {
+    let chunks = length / CHUNK_SIZE as u64 + 1;
+    (HEADER_SIZE + TRAILER_SIZE) as u64 + chunks * (LENGTH_SIZE + TAG_SIZE) as u64 + length
+}
+
+// The length of data held by a single segment of the given stored length.
+pub fn data_length(stored: u64) -> Option<u64> {
+    let chunked = stored.checked_sub((HEADER_SIZE + TRAILER_SIZE) as u64)?;
+    let chunks = chunked / (LENGTH_SIZE + CHUNK_SIZE + TAG_SIZE) as u64 + 1;
+    let length = chunked.checked_sub(chunks * (LENGTH_SIZE + TAG_SIZE) as u64)?;
+    match segment_length(length) == stored {
+        true => Some(length),
+        false => None,
+    }
+}
+
+// Where a chunk starts, from the start of its segment.
+pub fn chunk_offset(chunk: u64) -> u64 {
+    HEADER_SIZE as u64 + chunk * (LENGTH_SIZE + CHUNK_SIZE + TAG_SIZE) as u64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trailer {
+    pub length: u64,
+    pub digest: [u8; 32],
+}
+
+impl Trailer {
+    // The length of the data of a segment, read from its trailer without decrypting it.
+    pub fn length(data: &[u8]) -> Option<u64> {
+        match data.len() == TRAILER_SIZE {
+            false => None,
+            true => Some(u64::from_be_bytes(data[..8].try_into().ok()?)),
+        }
+    }
+
+    // Decrypts the trailer of the segment starting with the given header.
+    pub fn open(master: &MasterKey, header: &[u8], data: &[u8]) -> Option<Self> {
+        Trailer::open_with(&Segment::open(master, header)?, data)
+    }
+
+    fn open_with(segment: &Segment, data: &[u8]) -> Option<Self> {
+        let length = Trailer::length(data)?;
+        let payload = Payload {
+            msg: &data[8..],
+            aad: &data[..8],
+        };
+        let nonce = segment.trailer_nonce(length)?;
+        let digest = segment
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .ok()?;
+        Some(Trailer {
+            length,
+            digest: digest.as_slice().try_into().ok()?,
+        })
+    }
+
+    fn seal(&self, segment: &Segment) -> io::Result<Vec<u8>> {
+        let mut data = self.length.to_be_bytes().to_vec();
+        let payload = Payload {
+            msg: &self.digest,
+            aad: &data,
+        };
+        let sealed = segment.trailer_nonce(self.length).and_then(|nonce| {
+            segment
+                .cipher
+                .encrypt(Nonce::from_slice(&nonce), payload)
+                .ok()
+        });
+        match sealed {
+            None => Err(io::Error::other("failed to encrypt trailer")),
+            Some(sealed) => {
+                data.extend_from_slice(&sealed);
+                Ok(data)
+            }
+        }
+    }
+}
+
+struct Segment {
+    cipher: ChaCha20Poly1305,
+    prefix: [u8; PREFIX_SIZE],
+    counter: u32,
+    length: u64,
+    // None when the segment is read from the middle and its digest can't be checked.
+    hasher: Option<Sha256>,
+}
+
+impl Segment {
+    fn create(master: &MasterKey) -> io::Result<(Vec<u8>, Segment)> {
+        let key = random_key();
+        let mut prefix = [0u8; PREFIX_SIZE];
+        thread_rng().fill(&mut prefix);
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&master.wrap_key(&key)?);
+        header.extend_from_slice(&prefix);
+        Ok((header, Segment::new(&key, prefix)))
+    }
+
+    fn open(master: &MasterKey, header: &[u8]) -> Option<Segment> {
+        if header.len() != HEADER_SIZE || !header.starts_with(MAGIC) {
+            return None;
+        }
+        let (wrapped, prefix) = header[MAGIC.len()..].split_at(WRAPPED_KEY_SIZE);
+        let key = master.unwrap_key(wrapped)?;
+        Some(Segment::new(&key, prefix.try_into().ok()?))
+    }
+
+    fn new(key: &[u8], prefix: [u8; PREFIX_SIZE]) -> Self {
+        Segment {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            prefix,
+            counter: 0,
+            length: 0,
+            hasher: Some(Sha256::new()),
+        }
+    }
+
+    fn nonce(&self, last: bool) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce[..PREFIX_SIZE].copy_from_slice(&self.prefix);
+        nonce[PREFIX_SIZE..11].copy_from_slice(&self.counter.to_be_bytes());
+        nonce[11] = last as u8;
+        nonce
+    }
+
+    // The trailer follows the chunks of the segment under a nonce of its own, known from the
+    // length of the segment's data alone.
+    fn trailer_nonce(&self, length: u64) -> Option<[u8; 12]> {
+        let chunks: u32 = (length / CHUNK_SIZE as u64 + 1).try_into().ok()?;
+        let mut nonce = [0u8; 12];
+        nonce[..PREFIX_SIZE].copy_from_slice(&self.prefix);
+        nonce[PREFIX_SIZE..11].copy_from_slice(&chunks.to_be_bytes());
+        nonce[11] = 2;
+        Some(nonce)
+    }
+
+    fn advance(&mut self, data: &[u8]) -> io::Result<()> {
+        self.counter = match self.counter.checked_add(1) {
+            None => return Err(io::Error::other("too many chunks in one segment")),
+            Some(counter) => counter,
+        };
+        self.length += data.len() as u64;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(data);
+        }
+        Ok(())
+    }
+}
+
+// Encrypts the data read from the inner reader. The data is checked against the expected digest
+// of the upload options before the last chunk is released.
+pub struct EncryptingReader<R> {
+    inner: R,
+    segment: Segment,
+    options: PutOptions,
+    output: Vec<u8>,
+    read: usize,
+    done: bool,
+    mismatch: Rc<Cell<bool>>,
+    digest: Rc<Cell<Option<[u8; 32]>>>,
+}
+
+impl<R: Read> EncryptingReader<R> {
+    pub fn new(inner: R, master: &MasterKey, options: PutOptions) -> io::Result<Self> {
+        let (header, segment) = Segment::create(master)?;
+        Ok(EncryptingReader {
+            inner,
+            segment,
+            options,
+            output: header,
+            read: 0,
+            done: false,
+            mismatch: Rc::new(Cell::new(false)),
+            digest: Rc::new(Cell::new(None)),
+        })
+    }
+
+    // Set once the data turns out not to match the expected digest.
+    pub fn mismatch(&self) -> Rc<Cell<bool>> {
+        self.mismatch.clone()
+    }
+
+    // Set to the digest of the data once all of it has been read.
+    pub fn digest(&self) -> Rc<Cell<Option<[u8; 32]>>> {
+        self.digest.clone()
+    }
+
+    fn seal_next_chunk(&mut self) -> io::Result<()> {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        (&mut self.inner)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        let last = chunk.len() < CHUNK_SIZE;
+        let nonce = self.segment.nonce(last);
+        self.segment.advance(&chunk)?;
+        let trailer = match last {
+            false => Vec::new(),
+            true => {
+                let hasher = self.segment.hasher.take().unwrap_or_default();
+                let digest: [u8; 32] = hasher.finalize().into();
+                if !self.options.verify(&checksum::to_hex(&digest)) {
+                    self.mismatch.set(true);
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "checksum mismatch",
+                    ));
+                }
+                self.digest.set(Some(digest));
+                let trailer = Trailer {
+                    length: self.segment.length,
+                    digest,
+                };
+                trailer.seal(&self.segment)?
+            }
+        };
+        let payload = Payload {
+            msg: &chunk,
+            aad: &trailer,
+        };
+        let sealed = match self
+            .segment
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+        {
+            Err(_) => return Err(io::Error::other("failed to encrypt chunk")),
+            Ok(sealed) => sealed,
+        };
+        self.output.clear();
+        self.output
+            .extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+        self.output.extend_from_slice(&sealed);
+        self.output.extend_from_slice(&trailer);
+        self.read = 0;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for EncryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.output.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.seal_next_chunk()?;
+        }
+        let n = (self.output.len() - self.read).min(buf.len());
+        buf[..n].copy_from_slice(&self.output[self.read..self.read + n]);
+        self.read += n;
+        Ok(n)
+    }
+}
+
+// Decrypts one or more segments read from the inner reader. Failed authentication, including
+// data that was cut short, fails the read with an `InvalidData` error wrapping an
+// `AuthenticationFailed` error of the object.
+pub struct DecryptingReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    master: MasterKey,
+    segment: Option<Segment>,
+    output: Vec<u8>,
+    read: usize,
+    skip: u64,
+    remaining: Option<u64>,
+    bucket: String,
+    name: String,
+}
+
+impl<'a> DecryptingReader<'a> {
+    pub fn new(inner: Box<dyn Read + 'a>, master: &MasterKey, bucket: &str, name: &str) -> Self {
+        DecryptingReader {
+            inner,
+            master: master.clone(),
+            segment: None,
+            output: Vec::new(),
+            read: 0,
+            skip: 0,
+            remaining: None,
+            bucket: bucket.into(),
+            name: name.into(),
+        }
+    }
+
+    // Continues a segment at the given chunk, with the inner reader starting at that chunk.
+    pub fn resume(mut self, header: &[u8], chunk: u64) -> Option<Self> {
+        let mut segment = Segment::open(&self.master, header)?;
+        segment.counter = chunk.try_into().ok()?;
+        segment.length = chunk * CHUNK_SIZE as u64;
+        segment.hasher = None;
+        self.segment = Some(segment);
+        Some(self)
+    }
+
+    // Skips the first bytes of the data and stops after the given length.
+    pub fn range(mut self, skip: u64, length: u64) -> Self {
+        self.skip = skip;
+        self.remaining = Some(length);
+        self
+    }
+
+    // Opens the first segment, so that a wrong master key is detected before any data is read.
+    pub fn start(&mut self) -> io::Result<()> {
+        if self.segment.is_none() && !self.open_segment()? {
+            return Err(self.failure());
+        }
+        Ok(())
+    }
+
+    fn failure(&self) -> io::Error {
+        let err = Error::new(ErrorKind::AuthenticationFailed, &self.bucket, &self.name);
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+
+    // Fills the buffer with as much data as there is, stopping short only at the end of data.
+    fn read_full(&mut self, length: usize) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(length);
+        (&mut self.inner)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    // Returns false at the end of data.
+    fn open_segment(&mut self) -> io::Result<bool> {
+        let header = self.read_full(HEADER_SIZE)?;
+        if header.is_empty() {
+            return Ok(false);
+        }
+        match Segment::open(&self.master, &header) {
+            None => Err(self.failure()),
+            Some(segment) => {
+                self.segment = Some(segment);
+                Ok(true)
+            }
+        }
+    }
+
+    // Returns false at the end of data.
+    fn open_next_chunk(&mut self) -> io::Result<bool> {
+        if self.segment.is_none() && !self.open_segment()? {
+            return Ok(false);
+        }
+        let length = match self.read_full(LENGTH_SIZE)?.as_slice().try_into() {
+            Err(_) => return Err(self.failure()),
+            Ok(length) => u32::from_be_bytes(length) as usize,
+        };
+        if length > CHUNK_SIZE {
+            return Err(self.failure());
+        }
+        let sealed = self.read_full(length + TAG_SIZE)?;
+        let last = length < CHUNK_SIZE;
+        let trailer = match last {
+            false => Vec::new(),
+            true => self.read_full(TRAILER_SIZE)?,
+        };
+        if sealed.len() < length + TAG_SIZE || trailer.len() < TRAILER_SIZE * last as usize {
+            return Err(self.failure());
+        }
+        let segment = match self.segment.as_mut() {
+            None => return Err(io::Error::other("no segment")),
+            Some(segment) => segment,
+        };
+        let payload = Payload {
+            msg: &sealed,
+            aad: &trailer,
+        };
+        let nonce = segment.nonce(last);
+        let chunk = match segment.cipher.decrypt(Nonce::from_slice(&nonce), payload) {
+            Err(_) => return Err(self.failure()),
+            Ok(chunk) => chunk,
+        };
+        segment.advance(&chunk)?;
+        if last {
+            let intact = match (Trailer::open_with(segment, &trailer), segment.hasher.take()) {
+                (None, _) => false,
+                (Some(trailer), None) => trailer.length == segment.length,
+                (Some(trailer), Some(hasher)) => {
+                    let digest: [u8; 32] = hasher.finalize().into();
+                    trailer.length == segment.length && trailer.digest == digest
+                }
+            };
+            if !intact {
+                return Err(self.failure());
+            }
+            self.segment = None;
+        }
+        self.output = chunk;
+        self.read = 0;
+        Ok(true)
+    }
+}
+
+impl<'a> Read for DecryptingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == Some(0) {
+            return Ok(0);
+        }
+        loop {
+            let available = (self.output.len() - self.read) as u64;
+            if available == 0 {
+                if !self.open_next_chunk()? {
+                    return Ok(0);
+                }
+                continue;
+            }
+            if self.skip > 0 {
+                let skipped = self.skip.min(available);
+                self.skip -= skipped;
+                self.read += skipped as usize;
+                continue;
+            }
+            let n = match self.remaining {
+                None => available.min(buf.len() as u64),
+                Some(remaining) => available.min(buf.len() as u64).min(remaining),
+            } as usize;
+            buf[..n].copy_from_slice(&self.output[self.read..self.read + n]);
+            self.read += n;
+            self.remaining = self.remaining.map(|remaining| remaining - n as u64);
+            return Ok(n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypt(master: &MasterKey, data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let _ = EncryptingReader::new(data, master, PutOptions::new())
+            .and_then(|mut reader| reader.read_to_end(&mut output));
+        output
+    }
+
+    fn decrypt(master: &MasterKey, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        let mut reader = DecryptingReader::new(Box::new(io::Cursor::new(data)), master, "", "");
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).map(|_| output)
+    }
+
+    fn is_authentication_failure(result: io::Result<Vec<u8>>) -> bool {
+        match result {
+            Ok(_) => false,
+            Err(err) => matches!(
+                err.get_ref().and_then(|err| err.downcast_ref::<Error>()),
+                Some(err) if matches!(err.kind(), ErrorKind::AuthenticationFailed)
+            ),
+        }
+    }
+
+    #[test]
+    fn encrypted_data_should_decrypt_to_original_data() {
+        let master = MasterKey::generate();
+        let data = vec![7u8; CHUNK_SIZE * 2 + 100];
+        let stored = encrypt(&master, &data);
+        assert_eq!(stored.len() as u64, segment_length(data.len() as u64));
+        assert_eq!(data_length(stored.len() as u64), Some(data.len() as u64));
+        assert_eq!(
+            decrypt(&master, stored).unwrap_or_default(),
+            data,
+            "DecryptingReader failed to decrypt data."
+        );
+    }
+
+    #[test]
+    fn trailer_should_keep_digest_sealed() {
+        let master = MasterKey::generate();
+        let stored = encrypt(&master, b"Luna");
+        let digest = Sha256::digest(b"Luna");
+        assert!(
+            !stored.windows(digest.len()).any(|w| w == digest.as_slice()),
+            "EncryptingReader stored the digest in the clear."
+        );
+        let (header, trailer) = (
+            &stored[..HEADER_SIZE],
+            &stored[stored.len() - TRAILER_SIZE..],
+        );
+        let opened = Trailer::open(&master, header, trailer).map(|t| (t.length, t.digest));
+        assert_eq!(opened, Some((4, digest.into())));
+        let mut tampered = trailer.to_vec();
+        tampered[TRAILER_SIZE - 1] ^= 1;
+        assert!(
+            Trailer::open(&master, header, &tampered).is_none(),
+            "Trailer opened a tampered trailer."
+        );
+    }
+
+    #[test]
+    fn decrypting_reader_should_read_consecutive_segments() {
+        let master = MasterKey::generate();
+        let mut stored = encrypt(&master, b"Lu");
+        stored.extend(encrypt(&master, b"na"));
+        assert_eq!(decrypt(&master, stored).unwrap_or_default(), b"Luna");
+    }
+
+    #[test]
+    fn decrypting_reader_should_reject_tampered_data() {
+        let master = MasterKey::generate();
+        let mut stored = encrypt(&master, b"Luna");
+        stored[HEADER_SIZE + LENGTH_SIZE] ^= 1;
+        assert!(
+            is_authentication_failure(decrypt(&master, stored)),
+            "DecryptingReader accepted tampered data."
+        );
+    }
+
+    #[test]
+    fn decrypting_reader_should_reject_truncated_data() {
+        let master = MasterKey::generate();
+        let data = vec![7u8; CHUNK_SIZE * 2];
+        let stored = encrypt(&master, &data);
+        let truncated = stored[..chunk_offset(1) as usize].to_vec();
+        assert!(
+            is_authentication_failure(decrypt(&master, truncated)),
+            "DecryptingReader accepted truncated data."
+        );
+    }
+
+    #[test]
+    fn decrypting_reader_should_reject_other_master_key() {
+        let stored = encrypt(&MasterKey::generate(), b"Luna");
+        assert!(is_authentication_failure(decrypt(
+            &MasterKey::generate(),
+            stored
+        )));
+    }
+
+    #[test]
+    fn encrypting_reader_should_fail_on_mismatched_checksum() {
+        let options = PutOptions::new().checksum(&checksum::digest(b"Selene"));
+        let master = MasterKey::generate();
+        let mut reader = EncryptingReader::new(&b"Luna"[..], &master, options).unwrap();
+        let mismatch = reader.mismatch();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        assert!(mismatch.get(), "EncryptingReader accepted mismatched data.");
+    }
+}
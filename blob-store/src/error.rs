@@ -1,5 +1,7 @@
+use crate::checksum;
 use std::error;
 use std::fmt;
+use std::io;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -8,32 +10,122 @@ pub struct Error {
     bucket: String,
     object: String,
     kind: Kind,
+    source: Option<io::Error>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Kind {
     BucketNotFound,
+    BucketAlreadyExists,
+    BucketNotEmpty,
+    InvalidBucketName,
+    InvalidObjectName,
+    InvalidTag,
     ObjectAlreadyExist,
     ObjectNotFound,
+    VersionNotFound,
+    ChecksumMismatch,
+    InvalidRange,
+    UploadNotFound,
+    InvalidPart,
+    QuotaExceeded,
+    PreconditionFailed,
+    AuthenticationFailed,
+    AccessDenied,
     IO,
 }
 
+impl Kind {
+    // Codes never change once published, unlike the messages, so programs can rely on them.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Kind::BucketNotFound => "BucketNotFound",
+            Kind::BucketAlreadyExists => "BucketAlreadyExists",
+            Kind::BucketNotEmpty => "BucketNotEmpty",
+            Kind::InvalidBucketName => "InvalidBucketName",
+            Kind::InvalidObjectName => "InvalidObjectName",
+            Kind::InvalidTag => "InvalidTag",
+            Kind::ObjectAlreadyExist => "ObjectAlreadyExists",
+            Kind::ObjectNotFound => "ObjectNotFound",
+            Kind::VersionNotFound => "VersionNotFound",
+            Kind::ChecksumMismatch => "ChecksumMismatch",
+            Kind::InvalidRange => "InvalidRange",
+            Kind::UploadNotFound => "UploadNotFound",
+            Kind::InvalidPart => "InvalidPart",
+            Kind::QuotaExceeded => "QuotaExceeded",
+            Kind::PreconditionFailed => "PreconditionFailed",
+            Kind::AuthenticationFailed => "AuthenticationFailed",
+            Kind::AccessDenied => "AccessDenied",
+            Kind::IO => "IOError",
+        }
+    }
+}
+
 impl Error {
     pub fn new(kind: Kind, bucket: &str, object: &str) -> Self {
         Error {
             bucket: bucket.into(),
             object: object.into(),
             kind,
+            source: None,
         }
     }
+
+    // An I/O failure, keeping the error that caused it as the source. A checksum found not to
+    // match while reading an object is reported as a `ChecksumMismatch` instead.
+    pub fn io(source: io::Error, bucket: &str, object: &str) -> Self {
+        let kind = match checksum::is_mismatch(&source) {
+            false => Kind::IO,
+            true => Kind::ChecksumMismatch,
+        };
+        Error {
+            source: Some(source),
+            ..Error::new(kind, bucket, object)
+        }
+    }
+
+    pub fn kind(&self) -> &Kind {
+        &self.kind
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    pub fn object(&self) -> &str {
+        &self.object
+    }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|source| source as &(dyn error::Error + 'static))
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.kind {
             Kind::BucketNotFound => write!(f, "bucket {} not found", self.bucket),
+            Kind::BucketAlreadyExists => write!(f, "bucket {} already exists", self.bucket),
+            Kind::BucketNotEmpty => write!(f, "bucket {} is not empty", self.bucket),
+            Kind::InvalidBucketName => write!(f, "{} is not a valid bucket name", self.bucket),
+            Kind::InvalidObjectName => write!(
+                f,
+                "{:?} is not a valid object name in {} bucket",
+                self.object, self.bucket
+            ),
+            Kind::InvalidTag => write!(
+                f,
+                "invalid tags for object {} in {} bucket",
+                self.object, self.bucket
+            ),
             Kind::ObjectAlreadyExist => write!(
                 f,
                 "object {} already exist in {} bucket",
@@ -44,11 +136,118 @@ impl fmt::Display for Error {
                 "object {} is not found in {} bucket",
                 self.object, self.bucket
             ),
-            Kind::IO => write!(
+            Kind::VersionNotFound => write!(
+                f,
+                "version of object {} is not found in {} bucket",
+                self.object, self.bucket
+            ),
+            Kind::ChecksumMismatch => write!(
+                f,
+                "checksum of object {} in {} bucket does not match",
+                self.object, self.bucket
+            ),
+            Kind::InvalidRange => write!(
+                f,
+                "requested range is not satisfiable for object {} in {} bucket",
+                self.object, self.bucket
+            ),
+            Kind::UploadNotFound => write!(
+                f,
+                "upload of object {} into {} bucket is not found",
+                self.object, self.bucket
+            ),
+            Kind::InvalidPart => write!(
+                f,
+                "invalid part for object {} in {} bucket",
+                self.object, self.bucket
+            ),
+            Kind::QuotaExceeded => write!(
+                f,
+                "storing object {} in {} bucket exceeds a quota",
+                self.object, self.bucket
+            ),
+            Kind::PreconditionFailed => write!(
+                f,
+                "precondition on object {} in {} bucket does not hold",
+                self.object, self.bucket
+            ),
+            Kind::AuthenticationFailed => write!(
                 f,
-                "error writing object {} into {} bucket",
+                "object {} in {} bucket failed authentication",
                 self.object, self.bucket
             ),
+            Kind::AccessDenied => match self.object.is_empty() {
+                true => write!(f, "access to {} bucket is denied", self.bucket),
+                false => write!(
+                    f,
+                    "access to object {} in {} bucket is denied",
+                    self.object, self.bucket
+                ),
+            },
+            Kind::IO => {
+                match (self.bucket.is_empty(), self.object.is_empty()) {
+                    (true, _) => write!(f, "I/O error")?,
+                    (false, true) => write!(f, "I/O error on {} bucket", self.bucket)?,
+                    (false, false) => write!(
+                        f,
+                        "I/O error on object {} in {} bucket",
+                        self.object, self.bucket
+                    )?,
+                }
+                match &self.source {
+                    None => Ok(()),
+                    Some(source) => write!(f, ": {}", source),
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn error_io_should_keep_source_and_describe_it() {
+        let source = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        let err = Error::io(source, "earth", "Moon");
+        assert_eq!(err.code(), "IOError");
+        assert_eq!(
+            err.to_string(),
+            "I/O error on object Moon in earth bucket: denied"
+        );
+        assert!(
+            matches!(err.source().and_then(|e| e.downcast_ref::<io::Error>()), Some(e) if e.kind() == io::ErrorKind::PermissionDenied),
+            "Error lost its source."
+        );
+    }
+
+    #[test]
+    fn error_codes_should_be_unique() {
+        let kinds = [
+            Kind::BucketNotFound,
+            Kind::BucketAlreadyExists,
+            Kind::BucketNotEmpty,
+            Kind::InvalidBucketName,
+            Kind::InvalidObjectName,
+            Kind::InvalidTag,
+            Kind::ObjectAlreadyExist,
+            Kind::ObjectNotFound,
+            Kind::VersionNotFound,
+            Kind::ChecksumMismatch,
+            Kind::InvalidRange,
+            Kind::UploadNotFound,
+            Kind::InvalidPart,
+            Kind::QuotaExceeded,
+            Kind::PreconditionFailed,
+            Kind::AuthenticationFailed,
+            Kind::AccessDenied,
+            Kind::IO,
+        ];
+        let mut codes = kinds.iter().map(Kind::code).collect::<Vec<&str>>();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), kinds.len(), "Error kinds share a code.");
+    }
+}
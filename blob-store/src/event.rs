@@ -0,0 +1,448 @@
+use crate::error::Result;
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::store::{
+    CopyOptions, DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions, Store,
+};
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use std::io::Read;
+use std::sync::mpsc;
+use std::sync::mpsc::{Receiver, Sender};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    BucketCreated,
+    BucketDeleted,
+    ObjectPut,
+    ObjectRemoved,
+}
+
+impl EventKind {
+    // Names never change once published, so receivers can rely on them.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::BucketCreated => "BucketCreated",
+            EventKind::BucketDeleted => "BucketDeleted",
+            EventKind::ObjectPut => "ObjectPut",
+            EventKind::ObjectRemoved => "ObjectRemoved",
+        }
+    }
+}
+
+// A change made to a store. Bucket events have an empty key, size and ETag. Object events carry
+// the size and ETag of the object written, or of the one removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub bucket: String,
+    pub key: String,
+    pub size: u64,
+    pub etag: String,
+}
+
+impl Event {
+    fn bucket(kind: EventKind, bucket: &str) -> Self {
+        Event {
+            kind,
+            bucket: bucket.into(),
+            key: String::new(),
+            size: 0,
+            etag: String::new(),
+        }
+    }
+
+    fn object(kind: EventKind, bucket: &str, key: &str, metadata: Option<&ObjectMetadata>) -> Self {
+        Event {
+            key: key.into(),
+            size: metadata.map(|m| m.length).unwrap_or_default(),
+            etag: metadata.map(|m| m.etag.clone()).unwrap_or_default(),
+            ..Event::bucket(kind, bucket)
+        }
+    }
+}
+
+// Identifies a subscriber, to unsubscribe it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subscription(u64);
+
+enum Subscriber {
+    Callback(Box<dyn FnMut(&Event) + Send>),
+    Channel(Sender<Event>),
+}
+
+// Tells subscribers about the changes made to any store. Events are only sent once a change has
+// succeeded, in the order the changes were made. Callbacks run on the thread making the change
+// before its call returns, so they should be quick; channels hand events over to other threads.
+// Changes the store makes on its own, such as lifecycle expiry, aren't reported.
+pub struct NotifyingStore<S> {
+    inner: S,
+    subscribers: Vec<(Subscription, Subscriber)>,
+    next: u64,
+}
+
+impl<S: Store> NotifyingStore<S> {
+    pub fn new(inner: S) -> Self {
+        NotifyingStore {
+            inner,
+            subscribers: Vec::<(Subscription, Subscriber)>::new(),
+            next: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn subscribe<F: FnMut(&Event) + Send + 'static>(&mut self, callback: F) -> Subscription {
+        self.add(Subscriber::Callback(Box::new(callback)))
+    }
+
+    // The subscription ends once the receiver is dropped.
+    pub fn subscribe_channel(&mut self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.add(Subscriber::Channel(sender));
+        receiver
+    }
+
+    pub fn unsubscribe(&mut self, subscription: Subscription) {
+        self.subscribers.retain(|(id, _)| *id != subscription);
+    }
+
+    fn add(&mut self, subscriber: Subscriber) -> Subscription {
+        let subscription = Subscription(self.next);
+        self.next += 1;
+        self.subscribers.push((subscription, subscriber));
+        subscription
+    }
+
+    fn emit(&mut self, event: Event) {
+        self.subscribers
+            .retain_mut(|(_, subscriber)| match subscriber {
+                Subscriber::Callback(callback) => {
+                    callback(&event);
+                    true
+                }
+                Subscriber::Channel(sender) => sender.send(event.clone()).is_ok(),
+            });
+    }
+
+    fn emit_put(&mut self, bucket: &str, name: &str) {
+        let metadata = self.inner.head_object(bucket, name);
+        let event = Event::object(EventKind::ObjectPut, bucket, name, metadata.as_ref());
+        self.emit(event);
+    }
+}
+
+impl<S: Store> Store for NotifyingStore<S> {
+    fn list_buckets(&self) -> Vec<&str> {
+        self.inner.list_buckets()
+    }
+
+    fn create_bucket(&mut self, name: &str) -> Result<()> {
+        self.inner.create_bucket(name)?;
+        self.emit(Event::bucket(EventKind::BucketCreated, name));
+        Ok(())
+    }
+
+    // Objects removed along with a bucket aren't reported one by one.
+    fn delete_bucket_with(&mut self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        self.inner.delete_bucket_with(name, options)?;
+        self.emit(Event::bucket(EventKind::BucketDeleted, name));
+        Ok(())
+    }
+
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Result<ObjectList<'_>> {
+        self.inner.list_objects_with(bucket, options)
+    }
+
+    fn insert_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.inner
+            .insert_object_with(bucket, name, reader, options)?;
+        self.emit_put(bucket, name);
+        Ok(())
+    }
+
+    fn put_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.inner.put_object_with(bucket, name, reader, options)?;
+        self.emit_put(bucket, name);
+        Ok(())
+    }
+
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        self.inner.head_object(bucket, name)
+    }
+
+    fn head_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Option<ObjectMetadata> {
+        self.inner.head_object_version(bucket, name, version_id)
+    }
+
+    fn set_object_tags(&mut self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        self.inner.set_object_tags(bucket, name, tags)
+    }
+
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<&str>> {
+        self.inner.find_objects(bucket, query)
+    }
+
+    fn get_object_with<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<Box<dyn Read + 'a>> {
+        self.inner.get_object_with(bucket, name, options)
+    }
+
+    fn get_object_range<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + 'a>> {
+        self.inner.get_object_range(bucket, name, range)
+    }
+
+    fn copy_object_with(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+        options: CopyOptions,
+    ) -> Result<()> {
+        self.inner
+            .copy_object_with(src_bucket, src_name, dst_bucket, dst_name, options)?;
+        self.emit_put(dst_bucket, dst_name);
+        Ok(())
+    }
+
+    // Reported as a write of the destination followed by a removal of the source.
+    fn rename_object(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        self.inner
+            .rename_object(src_bucket, src_name, dst_bucket, dst_name)?;
+        if (src_bucket, src_name) == (dst_bucket, dst_name) {
+            return Ok(());
+        }
+        self.emit_put(dst_bucket, dst_name);
+        let moved = self.inner.head_object(dst_bucket, dst_name);
+        let event = Event::object(
+            EventKind::ObjectRemoved,
+            src_bucket,
+            src_name,
+            moved.as_ref(),
+        );
+        self.emit(event);
+        Ok(())
+    }
+
+    // Only reported when there was an object to remove.
+    fn remove_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        let removed = self.inner.head_object(bucket, name);
+        self.inner.remove_object_with(bucket, name, options)?;
+        if removed.is_some() {
+            let event = Event::object(EventKind::ObjectRemoved, bucket, name, removed.as_ref());
+            self.emit(event);
+        }
+        Ok(())
+    }
+
+    // Removing a delete marker isn't reported, only removing a version of the object.
+    fn remove_object_version(&mut self, bucket: &str, name: &str, version_id: &str) -> Result<()> {
+        let removed = self
+            .inner
+            .list_object_versions(bucket)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|v| v.name == name && v.version_id == version_id && !v.is_delete_marker);
+        self.inner.remove_object_version(bucket, name, version_id)?;
+        if let Some(version) = removed {
+            self.emit(Event {
+                size: version.length,
+                etag: version.etag,
+                ..Event::object(EventKind::ObjectRemoved, bucket, name, None)
+            });
+        }
+        Ok(())
+    }
+
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        self.inner.list_object_versions(bucket)
+    }
+
+    fn create_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        self.inner.create_multipart_upload(bucket, name, options)
+    }
+
+    fn upload_part(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String> {
+        self.inner
+            .upload_part(bucket, name, upload_id, part_number, reader)
+    }
+
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        self.inner.list_multipart_uploads(bucket)
+    }
+
+    fn abort_multipart_upload(&mut self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        self.inner.abort_multipart_upload(bucket, name, upload_id)
+    }
+
+    fn complete_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        self.inner
+            .complete_multipart_upload(bucket, name, upload_id)?;
+        self.emit_put(bucket, name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::in_memory::store::InMemoryStore;
+    use crate::store::tests::{get_data_reader, store_tests};
+    use std::sync::{Arc, Mutex};
+
+    fn with_store(test: fn(NotifyingStore<InMemoryStore>)) {
+        let mut store = NotifyingStore::new(InMemoryStore::new());
+        store.subscribe(|_| {});
+        test(store);
+    }
+
+    store_tests!(with_store);
+
+    fn kinds(receiver: &Receiver<Event>) -> Vec<(EventKind, String)> {
+        receiver
+            .try_iter()
+            .map(|event| (event.kind, event.key))
+            .collect()
+    }
+
+    #[test]
+    fn notifying_store_should_only_report_successful_changes() {
+        let mut store = NotifyingStore::new(InMemoryStore::new());
+        let events = store.subscribe_channel();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let put = events.try_recv().ok().and_then(|_| events.try_recv().ok());
+        assert_eq!(
+            put,
+            Some(Event {
+                kind: EventKind::ObjectPut,
+                bucket: "earth".into(),
+                key: "Moon".into(),
+                size: 4,
+                etag: checksum::digest(b"Luna"),
+            }),
+            "NotifyingStore did not report a write."
+        );
+        let _ = store.insert_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let _ = store.create_bucket("earth");
+        let _ = store.remove_object("earth", "Phobos");
+        let _ = store.delete_bucket("mars");
+        assert!(
+            events.try_recv().is_err(),
+            "NotifyingStore reported a failed change."
+        );
+        let _ = store.remove_object("earth", "Moon");
+        let _ = store.delete_bucket("earth");
+        assert_eq!(
+            kinds(&events),
+            vec![
+                (EventKind::ObjectRemoved, "Moon".into()),
+                (EventKind::BucketDeleted, String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn notifying_store_should_report_copies_and_renames() {
+        let mut store = NotifyingStore::new(InMemoryStore::new());
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let events = store.subscribe_channel();
+        let _ = store.copy_object("earth", "Moon", "earth", "Selene");
+        let _ = store.rename_object("earth", "Moon", "earth", "Luna");
+        assert_eq!(
+            kinds(&events),
+            vec![
+                (EventKind::ObjectPut, "Selene".into()),
+                (EventKind::ObjectPut, "Luna".into()),
+                (EventKind::ObjectRemoved, "Moon".into()),
+            ],
+            "NotifyingStore did not report a copy or rename."
+        );
+    }
+
+    #[test]
+    fn notifying_store_should_stop_notifying_after_unsubscribe() {
+        let mut store = NotifyingStore::new(InMemoryStore::new());
+        let seen = Arc::new(Mutex::new(Vec::<String>::new()));
+        let sink = seen.clone();
+        let subscription = store.subscribe(move |event| {
+            if let Ok(mut seen) = sink.lock() {
+                seen.push(event.bucket.clone());
+            }
+        });
+        drop(store.subscribe_channel());
+        let _ = store.create_bucket("earth");
+        store.unsubscribe(subscription);
+        let _ = store.create_bucket("mars");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["earth".to_string()],
+            "NotifyingStore notified a removed subscriber."
+        );
+        assert!(
+            store.subscribers.is_empty(),
+            "NotifyingStore kept a channel without a receiver."
+        );
+    }
+}
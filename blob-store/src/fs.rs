@@ -0,0 +1,3 @@
+pub mod metadata;
+pub mod name;
+pub mod store;
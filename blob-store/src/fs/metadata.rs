@@ -0,0 +1,147 @@
+use crate::checksum::ChecksumReader;
+use crate::fs::name::{escape, unescape};
+use crate::metadata::ObjectMetadata;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Object metadata is kept in a small text file next to the object, one field per line:
+//
+//   created <nanoseconds since epoch>
+//   modified <nanoseconds since epoch>
+//   etag <SHA-256 digest in hex>
+//   content-type <escaped content type>
+//   meta <escaped key> <escaped value>
+//   tag <escaped key> <escaped value>
+//   data <length> <nanoseconds since epoch>
+//
+// The length of an object is always taken from the object file itself. The data line holds the
+// length and modification time of the object file the metadata was written for, metadata found
+// next to any other file is ignored: an object replaced by a crash before its metadata was is read
+// like an object without metadata file.
+pub fn serialize(metadata: &ObjectMetadata, data: &fs::Metadata) -> String {
+    let mut s = format!(
+        "created {}\nmodified {}\netag {}\ndata {}\n",
+        to_nanos(metadata.created),
+        to_nanos(metadata.modified),
+        metadata.etag,
+        stamp(data)
+    );
+    if let Some(content_type) = &metadata.content_type {
+        s.push_str(&format!("content-type {}\n", escape(content_type)));
+    }
+    for (key, value) in &metadata.user {
+        s.push_str(&format!("meta {} {}\n", escape(key), escape(value)));
+    }
+    for (key, value) in &metadata.tags {
+        s.push_str(&format!("tag {} {}\n", escape(key), escape(value)));
+    }
+    s
+}
+
+pub fn deserialize(s: &str) -> Option<ObjectMetadata> {
+    let mut metadata = ObjectMetadata::new();
+    for line in s.lines() {
+        let mut fields = line.split(' ');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some("created"), Some(nanos), None) => metadata.created = from_nanos(nanos)?,
+            (Some("modified"), Some(nanos), None) => metadata.modified = from_nanos(nanos)?,
+            (Some("etag"), Some(etag), None) => metadata.etag = etag.into(),
+            (Some("data"), Some(_), Some(_)) => {}
+            (Some("content-type"), Some(value), None) => {
+                metadata.content_type = Some(unescape(value)?)
+            }
+            (Some("meta"), Some(key), Some(value)) => {
+                metadata.user.insert(unescape(key)?, unescape(value)?);
+            }
+            (Some("tag"), Some(key), Some(value)) => {
+                metadata.tags.insert(unescape(key)?, unescape(value)?);
+            }
+            _ => return None,
+        }
+    }
+    Some(metadata)
+}
+
+// Reads the metadata of the object at `path` from its metadata file at `metadata_path`. Objects
+// without a readable metadata file get their timestamps from the file system instead, and their
+// ETag is computed from the data.
+pub fn read(path: &Path, metadata_path: &Path) -> io::Result<ObjectMetadata> {
+    let stat = fs::metadata(path)?;
+    let mut metadata = match fs::read_to_string(metadata_path)
+        .ok()
+        .filter(|s| is_written_for(s, &stat))
+        .and_then(|s| deserialize(&s))
+    {
+        Some(metadata) => metadata,
+        None => {
+            let mut metadata = ObjectMetadata::new();
+            metadata.modified = stat.modified()?;
+            metadata.created = metadata.modified;
+            let mut reader = ChecksumReader::new(File::open(path)?);
+            io::copy(&mut reader, &mut io::sink())?;
+            metadata.etag = reader.finish();
+            metadata
+        }
+    };
+    metadata.length = stat.len();
+    Ok(metadata)
+}
+
+// Metadata written before the data line was added is taken to be that of the file next to it.
+fn is_written_for(s: &str, data: &fs::Metadata) -> bool {
+    match s.lines().find_map(|line| line.strip_prefix("data ")) {
+        None => true,
+        Some(written) => written == stamp(data),
+    }
+}
+
+fn stamp(data: &fs::Metadata) -> String {
+    let modified = data.modified().map(to_nanos).unwrap_or_default();
+    format!("{} {}", data.len(), modified)
+}
+
+fn to_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+fn from_nanos(s: &str) -> Option<SystemTime> {
+    let nanos = s.parse::<u128>().ok()?;
+    let secs = (nanos / 1_000_000_000) as u64;
+    let nanos = (nanos % 1_000_000_000) as u32;
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum;
+
+    #[test]
+    fn deserialize_should_reverse_serialize() {
+        let mut metadata = ObjectMetadata::new();
+        metadata.etag = checksum::digest(b"Luna");
+        metadata.content_type = Some("text/plain; charset=utf-8".into());
+        metadata
+            .user
+            .insert("discovered by".into(), "Galileo Galilei".into());
+        metadata.tags.insert("moon".into(), "".into());
+        assert_eq!(
+            deserialize(&serialize(&metadata, &fs::metadata(".").unwrap())),
+            Some(metadata),
+            "Metadata changed after serialization."
+        );
+    }
+
+    #[test]
+    fn deserialize_should_reject_unknown_fields() {
+        assert!(
+            deserialize("owner root\n").is_none(),
+            "Metadata with unknown field is deserialized."
+        );
+    }
+}
@@ -0,0 +1,90 @@
+// Most file systems don't allow longer file names.
+const MAX_ENCODED_LENGTH: usize = 255;
+
+// Percent-encodes everything but ASCII letters, digits, '-', '_' and '.'.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    escaped
+}
+
+pub fn unescape(escaped: &str) -> Option<String> {
+    let bytes = escaped.as_bytes();
+    let mut unescaped = Vec::<u8>::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = escaped.get(i + 1..i + 3)?;
+            unescaped.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            unescaped.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(unescaped).ok()
+}
+
+// Encodes a bucket or object name into a single path component. A leading '.' is escaped as
+// well, so names can't escape the root directory or collide with the store's own hidden files.
+pub fn encode(name: &str) -> String {
+    match name.strip_prefix('.') {
+        Some(rest) => format!("%2E{}", escape(rest)),
+        None => escape(name),
+    }
+}
+
+// Names must not be empty and must fit in a file name once encoded, which can take up to three
+// times as many bytes.
+pub fn is_valid(name: &str) -> bool {
+    !name.is_empty() && encode(name).len() <= MAX_ENCODED_LENGTH
+}
+
+pub fn decode(encoded: &str) -> Option<String> {
+    if encoded.is_empty() || encoded.starts_with('.') {
+        return None;
+    }
+    unescape(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_should_escape_path_separators_and_leading_dot() {
+        assert_eq!(encode("../a/.b"), "%2E.%2Fa%2F.b");
+    }
+
+    #[test]
+    fn decode_should_reverse_encode() {
+        let name = "photos/2021/Ünïcödé file.jpg";
+        assert_eq!(decode(&encode(name)).as_deref(), Some(name));
+    }
+
+    #[test]
+    fn is_valid_should_reject_names_too_long_once_encoded() {
+        assert!(is_valid(&"a".repeat(255)));
+        assert!(!is_valid(""), "Empty name is valid.");
+        assert!(
+            !is_valid(&"/".repeat(86)),
+            "Name too long once encoded is valid."
+        );
+    }
+
+    #[test]
+    fn decode_should_skip_hidden_files() {
+        assert!(decode(".tmp").is_none(), "Hidden file is decoded.");
+    }
+
+    #[test]
+    fn unescape_should_reverse_escape() {
+        let s = "key=value; charset=\"utf-8\"\n";
+        assert_eq!(unescape(&escape(s)).as_deref(), Some(s));
+    }
+}
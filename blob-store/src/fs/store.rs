@@ -0,0 +1,896 @@
+use crate::bucket;
+use crate::checksum::ChecksumReader;
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::fs::metadata;
+use crate::fs::name;
+use crate::fs::name::{decode, encode};
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart;
+use crate::multipart::{MultipartUpload, PartsReader, Upload};
+use crate::range::ByteRange;
+use crate::store::{
+    CopyOptions, DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions, Store,
+};
+use crate::tag;
+use crate::tag::Tags;
+use crate::version::{ObjectVersion, NULL_VERSION};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+
+// Temporary files live next to the buckets so that renaming them into place stays on the same
+// file system. Encoded names never start with a dot, so this directory can't clash with a bucket.
+const TEMP_DIR: &str = ".tmp";
+// Each bucket keeps the metadata of its objects in files of the same name inside this directory.
+const METADATA_DIR: &str = ".meta";
+// Parts of uploads in progress are kept in a directory per upload, named after the upload ID.
+const UPLOADS_DIR: &str = ".uploads";
+
+// Buckets map to directories under the root and objects to files inside them. The names of
+// buckets and objects are kept in memory, they are read from disk once when the store is opened.
+// Uploads in progress only live as long as the store, their parts are discarded when the store is
+// opened again.
+pub struct FsStore {
+    root: PathBuf,
+    buckets: HashMap<String, BTreeSet<String>>,
+    uploads: HashMap<String, Upload<()>>,
+}
+
+impl FsStore {
+    pub fn open<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        // Files left in these by a crash are never going to be committed.
+        for dir in &[TEMP_DIR, UPLOADS_DIR] {
+            match fs::remove_dir_all(root.join(dir)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => fs::create_dir_all(root.join(dir))?,
+            }
+        }
+        let mut buckets = HashMap::<String, BTreeSet<String>>::new();
+        for entry in fs::read_dir(&root)? {
+            let entry = entry?;
+            let bucket = match decode(&entry.file_name().to_string_lossy()) {
+                Some(name) if entry.file_type()?.is_dir() => name,
+                _ => continue,
+            };
+            let mut objects = BTreeSet::<String>::new();
+            for entry in fs::read_dir(entry.path())? {
+                let entry = entry?;
+                if let Some(name) = decode(&entry.file_name().to_string_lossy()) {
+                    if entry.file_type()?.is_file() {
+                        objects.insert(name);
+                    }
+                }
+            }
+            buckets.insert(bucket, objects);
+        }
+        Ok(FsStore {
+            root,
+            buckets,
+            uploads: HashMap::<String, Upload<()>>::new(),
+        })
+    }
+
+    fn bucket_path(&self, bucket: &str) -> PathBuf {
+        self.root.join(encode(bucket))
+    }
+
+    fn object_path(&self, bucket: &str, name: &str) -> PathBuf {
+        self.bucket_path(bucket).join(encode(name))
+    }
+
+    fn metadata_path(&self, bucket: &str, name: &str) -> PathBuf {
+        self.bucket_path(bucket)
+            .join(METADATA_DIR)
+            .join(encode(name))
+    }
+
+    fn upload_path(&self, upload_id: &str) -> PathBuf {
+        self.root.join(UPLOADS_DIR).join(upload_id)
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let suffix: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        self.root.join(TEMP_DIR).join(suffix)
+    }
+
+    // Writes into a temporary file that is only renamed into place by `commit_file` once it is
+    // complete, so readers never observe a partially written file.
+    fn write_temp(&self, reader: &mut dyn Read) -> io::Result<PathBuf> {
+        let temp = self.temp_path();
+        let result = File::create(&temp).and_then(|mut file| {
+            io::copy(reader, &mut file)?;
+            file.sync_all()
+        });
+        match result {
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                Err(e)
+            }
+            Ok(_) => Ok(temp),
+        }
+    }
+
+    fn write_file(&self, path: &Path, reader: &mut dyn Read) -> io::Result<()> {
+        let temp = self.write_temp(reader)?;
+        commit_file(&temp, path)
+    }
+
+    // The metadata of an object to copy or rename, once the destination bucket is known to exist.
+    fn source(
+        &self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<ObjectMetadata> {
+        if !name::is_valid(dst_name) {
+            return Err(Error::new(
+                ErrorKind::InvalidObjectName,
+                dst_bucket,
+                dst_name,
+            ));
+        }
+        let source = match self.head_object(src_bucket, src_name) {
+            Some(metadata) => metadata,
+            None if self.buckets.contains_key(src_bucket) => {
+                return Err(Error::new(ErrorKind::ObjectNotFound, src_bucket, src_name))
+            }
+            None => return Err(Error::new(ErrorKind::BucketNotFound, src_bucket, src_name)),
+        };
+        match self.buckets.contains_key(dst_bucket) {
+            false => Err(Error::new(ErrorKind::BucketNotFound, dst_bucket, dst_name)),
+            true => Ok(source),
+        }
+    }
+
+    // Writes the metadata of the object file at `data` into a temporary file. The metadata only
+    // applies to that file, so it can be written before the file is moved into place.
+    fn write_metadata_temp(&self, data: &Path, metadata: &ObjectMetadata) -> io::Result<PathBuf> {
+        let stat = fs::metadata(data)?;
+        self.write_temp(&mut metadata::serialize(metadata, &stat).as_bytes())
+    }
+
+    // Moves a complete temporary file into place as the object, then its metadata. Until the
+    // metadata is in place the new file is read like an object without metadata file, never with
+    // the metadata of the object it replaces.
+    fn commit_object(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        temp: &Path,
+        metadata: &ObjectMetadata,
+    ) -> Result<()> {
+        let metadata_temp = match self.write_metadata_temp(temp, metadata) {
+            Err(e) => {
+                let _ = fs::remove_file(temp);
+                return Err(Error::io(e, bucket, name));
+            }
+            Ok(metadata_temp) => metadata_temp,
+        };
+        if let Err(e) = commit_file(temp, &self.object_path(bucket, name)) {
+            let _ = fs::remove_file(&metadata_temp);
+            return Err(Error::io(e, bucket, name));
+        }
+        self.commit_metadata(bucket, name, &metadata_temp)
+    }
+
+    // Moves the metadata of an object whose file is in place from its temporary file into place.
+    fn commit_metadata(&mut self, bucket: &str, name: &str, metadata_temp: &Path) -> Result<()> {
+        if let Err(e) = commit_file(metadata_temp, &self.metadata_path(bucket, name)) {
+            return Err(Error::io(e, bucket, name));
+        }
+        if let Some(objects) = self.buckets.get_mut(bucket) {
+            objects.insert(name.into());
+        }
+        Ok(())
+    }
+
+    // Replaces the metadata of an object whose file is in place.
+    fn write_metadata(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        metadata: &ObjectMetadata,
+    ) -> Result<()> {
+        let path = self.object_path(bucket, name);
+        match self.write_metadata_temp(&path, metadata) {
+            Err(e) => Err(Error::io(e, bucket, name)),
+            Ok(metadata_temp) => self.commit_metadata(bucket, name, &metadata_temp),
+        }
+    }
+
+    pub fn insert_or_replace_object(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+        replace: bool,
+    ) -> Result<()> {
+        if !name::is_valid(name) {
+            return Err(Error::new(ErrorKind::InvalidObjectName, bucket, name));
+        }
+        tag::validate(bucket, name, options.tags())?;
+        match self.buckets.get(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(objects) if !replace && objects.contains(name) => {
+                return Err(Error::new(ErrorKind::ObjectAlreadyExist, bucket, name))
+            }
+            Some(_) => {}
+        }
+        let replaced = self.head_object(bucket, name);
+        options
+            .preconditions()
+            .check(bucket, name, replaced.as_ref())?;
+        let mut reader = ChecksumReader::new(reader);
+        let temp = match self.write_temp(&mut reader) {
+            Err(e) => return Err(Error::io(e, bucket, name)),
+            Ok(temp) => temp,
+        };
+        let etag = reader.finish();
+        if !options.verify(&etag) {
+            let _ = fs::remove_file(&temp);
+            return Err(Error::new(ErrorKind::ChecksumMismatch, bucket, name));
+        }
+        let mut metadata = options.into_metadata(replaced.as_ref());
+        metadata.etag = etag;
+        self.commit_object(bucket, name, &temp, &metadata)
+    }
+}
+
+impl Store for FsStore {
+    fn list_buckets(&self) -> Vec<&str> {
+        self.buckets
+            .keys()
+            .map(|k| k.as_ref())
+            .collect::<Vec<&str>>()
+    }
+
+    fn create_bucket(&mut self, name: &str) -> Result<()> {
+        bucket::validate_name(name)?;
+        if self.buckets.contains_key(name) {
+            return Err(Error::new(ErrorKind::BucketAlreadyExists, name, ""));
+        }
+        if let Err(e) = fs::create_dir_all(self.bucket_path(name).join(METADATA_DIR)) {
+            return Err(Error::io(e, name, ""));
+        }
+        self.buckets.insert(name.into(), BTreeSet::<String>::new());
+        Ok(())
+    }
+
+    fn delete_bucket_with(&mut self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        match self.buckets.get(name) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, name, "")),
+            Some(objects) if !options.is_forced() && !objects.is_empty() => {
+                return Err(Error::new(ErrorKind::BucketNotEmpty, name, ""))
+            }
+            Some(_) => {}
+        }
+        self.buckets.remove(name);
+        let upload_ids = self
+            .uploads
+            .iter()
+            .filter(|(_, upload)| upload.bucket == name)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<String>>();
+        for upload_id in upload_ids {
+            self.uploads.remove(&upload_id);
+            let _ = fs::remove_dir_all(self.upload_path(&upload_id));
+        }
+        match fs::remove_dir_all(self.bucket_path(name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Error::io(e, name, "")),
+            _ => Ok(()),
+        }
+    }
+
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Result<ObjectList<'_>> {
+        let names = match self.buckets.get(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(names) => names,
+        };
+        let names = names
+            .range::<str, _>((options.start(), Bound::Unbounded))
+            .map(|k| k.as_ref());
+        Ok(options.list(names))
+    }
+
+    fn insert_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.insert_or_replace_object(bucket, name, reader, options, false)
+    }
+
+    fn put_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.insert_or_replace_object(bucket, name, reader, options, true)
+    }
+
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        if !self.buckets.get(bucket)?.contains(name) {
+            return None;
+        }
+        metadata::read(
+            &self.object_path(bucket, name),
+            &self.metadata_path(bucket, name),
+        )
+        .ok()
+    }
+
+    // Only the metadata file is written again, the object file is left alone.
+    fn set_object_tags(&mut self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        tag::validate(bucket, name, &tags)?;
+        let mut metadata = match self.head_object(bucket, name) {
+            Some(metadata) => metadata,
+            None if self.buckets.contains_key(bucket) => {
+                return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name))
+            }
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+        };
+        metadata.tags = tags;
+        self.write_metadata(bucket, name, &metadata)
+    }
+
+    fn get_object_with<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<Box<dyn Read + 'a>> {
+        match self.buckets.get(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(_) if !matches!(options.version(), None | Some(NULL_VERSION)) => {
+                return Err(Error::new(ErrorKind::VersionNotFound, bucket, name))
+            }
+            Some(objects) if !objects.contains(name) => {
+                return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name))
+            }
+            Some(_) => {}
+        }
+        let metadata = match self.head_object(bucket, name) {
+            None => {
+                let e = io::Error::new(io::ErrorKind::InvalidData, "unreadable object metadata");
+                return Err(Error::io(e, bucket, name));
+            }
+            Some(metadata) => metadata,
+        };
+        options
+            .preconditions()
+            .check(bucket, name, Some(&metadata))?;
+        match File::open(self.object_path(bucket, name)) {
+            Err(e) => Err(Error::io(e, bucket, name)),
+            Ok(file) => Ok(options.reader(Box::new(file), &metadata)),
+        }
+    }
+
+    fn get_object_range<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + 'a>> {
+        let length = match self.head_object(bucket, name) {
+            Some(metadata) => metadata.length,
+            None if self.buckets.contains_key(bucket) => {
+                return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name))
+            }
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+        };
+        let range = match range.resolve(length) {
+            None => return Err(Error::new(ErrorKind::InvalidRange, bucket, name)),
+            Some(range) => range,
+        };
+        let file = File::open(self.object_path(bucket, name)).and_then(|mut file| {
+            file.seek(SeekFrom::Start(range.start))?;
+            Ok(file.take(range.end - range.start))
+        });
+        match file {
+            Err(e) => Err(Error::io(e, bucket, name)),
+            Ok(file) => Ok(Box::new(file)),
+        }
+    }
+
+    // The copy is a hard link to the source when the file system allows it. Objects are only ever
+    // replaced by renaming a new file into place, so a link never sees changes made to the other.
+    // Copying an object onto itself only rewrites its metadata.
+    fn copy_object_with(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+        options: CopyOptions,
+    ) -> Result<()> {
+        let source = self.source(src_bucket, src_name, dst_bucket, dst_name)?;
+        if (src_bucket, src_name) == (dst_bucket, dst_name) {
+            let options = options.into_put_options(&source);
+            tag::validate(dst_bucket, dst_name, options.tags())?;
+            let mut metadata = options.into_metadata(Some(&source));
+            metadata.etag = source.etag;
+            return self.write_metadata(dst_bucket, dst_name, &metadata);
+        }
+        let src_path = self.object_path(src_bucket, src_name);
+        let temp = self.temp_path();
+        if let Err(e) =
+            fs::hard_link(&src_path, &temp).or_else(|_| fs::copy(&src_path, &temp).map(|_| ()))
+        {
+            let _ = fs::remove_file(&temp);
+            return Err(Error::io(e, dst_bucket, dst_name));
+        }
+        let options = options.into_put_options(&source);
+        if let Err(e) = tag::validate(dst_bucket, dst_name, options.tags()) {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+        let replaced = self.head_object(dst_bucket, dst_name);
+        let mut metadata = options.into_metadata(replaced.as_ref());
+        metadata.etag = source.etag;
+        let result = self.commit_object(dst_bucket, dst_name, &temp, &metadata);
+        // Renaming a link onto a link to the same file, as when the destination is an earlier copy
+        // of the source, leaves both in place.
+        let _ = fs::remove_file(&temp);
+        result
+    }
+
+    fn rename_object(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let source = self.source(src_bucket, src_name, dst_bucket, dst_name)?;
+        if (src_bucket, src_name) == (dst_bucket, dst_name) {
+            return Ok(());
+        }
+        let replaced = self.head_object(dst_bucket, dst_name);
+        let mut metadata = CopyOptions::new()
+            .into_put_options(&source)
+            .into_metadata(replaced.as_ref());
+        metadata.etag = source.etag;
+        let src_path = self.object_path(src_bucket, src_name);
+        let dst_path = self.object_path(dst_bucket, dst_name);
+        let metadata_temp = match self.write_metadata_temp(&src_path, &metadata) {
+            Err(e) => return Err(Error::io(e, dst_bucket, dst_name)),
+            Ok(metadata_temp) => metadata_temp,
+        };
+        if let Err(e) = fs::rename(&src_path, &dst_path) {
+            let _ = fs::remove_file(&metadata_temp);
+            return Err(Error::io(e, src_bucket, src_name));
+        }
+        sync_parent(&src_path);
+        sync_parent(&dst_path);
+        // The name is taken off the source first, it has no file anymore whatever happens next.
+        if let Some(objects) = self.buckets.get_mut(src_bucket) {
+            objects.remove(src_name);
+        }
+        let _ = fs::remove_file(self.metadata_path(src_bucket, src_name));
+        self.commit_metadata(dst_bucket, dst_name, &metadata_temp)
+    }
+
+    fn remove_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        if !self.buckets.contains_key(bucket) {
+            return Err(Error::new(ErrorKind::BucketNotFound, bucket, name));
+        }
+        options
+            .preconditions()
+            .check(bucket, name, self.head_object(bucket, name).as_ref())?;
+        let objects = match self.buckets.get_mut(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(objects) => objects,
+        };
+        if !objects.remove(name) {
+            return Ok(());
+        }
+        let _ = fs::remove_file(self.metadata_path(bucket, name));
+        match fs::remove_file(self.object_path(bucket, name)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Error::io(e, bucket, name)),
+            _ => Ok(()),
+        }
+    }
+
+    // Objects on the file system are not versioned, they only have the null version.
+    fn remove_object_version(&mut self, bucket: &str, name: &str, version_id: &str) -> Result<()> {
+        match self.buckets.get(bucket) {
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(objects) if version_id == NULL_VERSION && objects.contains(name) => {
+                self.remove_object(bucket, name)
+            }
+            Some(_) => Err(Error::new(ErrorKind::VersionNotFound, bucket, name)),
+        }
+    }
+
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        let versions = self
+            .buckets
+            .get(bucket)?
+            .iter()
+            .filter_map(|name| {
+                self.head_object(bucket, name)
+                    .map(|metadata| ObjectVersion {
+                        name: name.clone(),
+                        version_id: NULL_VERSION.into(),
+                        is_latest: true,
+                        is_delete_marker: false,
+                        modified: metadata.modified,
+                        length: metadata.length,
+                        etag: metadata.etag,
+                    })
+            })
+            .collect::<Vec<ObjectVersion>>();
+        Some(versions)
+    }
+
+    fn create_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        if !name::is_valid(name) {
+            return Err(Error::new(ErrorKind::InvalidObjectName, bucket, name));
+        }
+        if !self.buckets.contains_key(bucket) {
+            return Err(Error::new(ErrorKind::BucketNotFound, bucket, name));
+        }
+        let upload_id = multipart::new_upload_id();
+        if let Err(e) = fs::create_dir_all(self.upload_path(&upload_id)) {
+            return Err(Error::io(e, bucket, name));
+        }
+        self.uploads
+            .insert(upload_id.clone(), Upload::new(bucket, name, options));
+        Ok(upload_id)
+    }
+
+    fn upload_part(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String> {
+        match self.uploads.get(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) => {}
+            _ => return Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        }
+        if !multipart::is_valid_part_number(part_number) {
+            return Err(Error::new(ErrorKind::InvalidPart, bucket, name));
+        }
+        let path = self.upload_path(upload_id).join(part_number.to_string());
+        let mut reader = ChecksumReader::new(reader);
+        if let Err(e) = self.write_file(&path, &mut reader) {
+            return Err(Error::io(e, bucket, name));
+        }
+        if let Some(upload) = self.uploads.get_mut(upload_id) {
+            upload.parts.insert(part_number, ());
+        }
+        Ok(reader.finish())
+    }
+
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        if !self.buckets.contains_key(bucket) {
+            return None;
+        }
+        Some(multipart::list(self.uploads.iter(), bucket))
+    }
+
+    fn abort_multipart_upload(&mut self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        match self.uploads.get(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) => {
+                self.uploads.remove(upload_id);
+                let _ = fs::remove_dir_all(self.upload_path(upload_id));
+                Ok(())
+            }
+            _ => Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        }
+    }
+
+    fn complete_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        let upload = match self.uploads.get(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) && upload.parts.is_empty() => {
+                return Err(Error::new(ErrorKind::InvalidPart, bucket, name))
+            }
+            Some(upload) if upload.is_for(bucket, name) => upload,
+            _ => return Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        };
+        let options = upload.options.clone();
+        let path = self.upload_path(upload_id);
+        let parts = upload
+            .parts
+            .keys()
+            .map(|part_number| File::open(path.join(part_number.to_string())))
+            .collect::<io::Result<Vec<File>>>();
+        let mut reader = match parts {
+            Err(e) => return Err(Error::io(e, bucket, name)),
+            Ok(parts) => Box::new(PartsReader::new(parts)) as Box<dyn Read>,
+        };
+        self.insert_or_replace_object(bucket, name, &mut reader, options, true)?;
+        self.uploads.remove(upload_id);
+        let _ = fs::remove_dir_all(path);
+        Ok(())
+    }
+}
+
+// Renames a complete file into place and syncs its directory, so the rename survives a crash.
+fn commit_file(temp: &Path, path: &Path) -> io::Result<()> {
+    if let Err(e) = fs::rename(temp, path) {
+        let _ = fs::remove_file(temp);
+        return Err(e);
+    }
+    sync_parent(path);
+    Ok(())
+}
+
+// Best effort, not every platform can open a directory to sync it.
+fn sync_parent(path: &Path) {
+    if let Some(Ok(dir)) = path.parent().map(File::open) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::store::tests::{get_data_reader, get_reader, store_tests};
+    use std::env;
+
+    pub struct TempDir {
+        path: PathBuf,
+    }
+
+    impl TempDir {
+        pub fn new() -> Self {
+            let suffix: String = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(16)
+                .map(char::from)
+                .collect();
+            let path = env::temp_dir().join(format!("blob-store-{}", suffix));
+            fs::create_dir_all(&path).unwrap();
+            TempDir { path }
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Default for TempDir {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn with_store(test: fn(FsStore)) {
+        let dir = TempDir::new();
+        test(FsStore::open(dir.path()).unwrap());
+    }
+
+    store_tests!(with_store);
+
+    #[test]
+    fn fs_store_open_should_load_existing_buckets_and_objects() {
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("jupiter");
+        let _ = store.insert_object("jupiter", "Io/Volcano", &mut get_reader());
+        let store = FsStore::open(dir.path()).unwrap();
+        assert_eq!(
+            store.list_objects("jupiter"),
+            Some(vec!["Io/Volcano"]),
+            "FsStore failed to load existing objects."
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fs_store_copy_object_should_link_data_of_source() {
+        use std::os::unix::fs::MetadataExt;
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.copy_object("earth", "Moon", "mars", "Phobos");
+        let links = fs::metadata(store.object_path("mars", "Phobos")).map(|m| m.nlink());
+        assert_eq!(links.ok(), Some(2), "FsStore did not link a copy.");
+        let _ = store.rename_object("mars", "Phobos", "mars", "Deimos");
+        let store = FsStore::open(dir.path()).unwrap();
+        assert_eq!(store.list_objects("mars"), Some(vec!["Deimos"]));
+        assert_eq!(
+            store.head_object("mars", "Deimos").map(|m| m.etag),
+            Some(checksum::digest(b"Luna")),
+            "FsStore lost metadata of a renamed object."
+        );
+    }
+
+    #[test]
+    fn fs_store_copy_object_should_not_leave_temporary_files() {
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.copy_object("earth", "Moon", "earth", "Moon");
+        let _ = store.copy_object("earth", "Moon", "earth", "Luna");
+        let _ = store.copy_object("earth", "Moon", "earth", "Luna");
+        assert_eq!(
+            fs::read_dir(dir.path().join(TEMP_DIR)).unwrap().count(),
+            0,
+            "FsStore left a temporary file behind after a copy."
+        );
+        assert_eq!(
+            store.head_object("earth", "Moon").map(|m| m.etag),
+            Some(checksum::digest(b"Luna")),
+            "FsStore lost metadata of an object copied onto itself."
+        );
+    }
+
+    #[test]
+    fn fs_store_get_object_with_verify_checksum_should_detect_corruption() {
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        // Data corrupted on disk keeps the modification time it was written with.
+        let path = store.object_path("earth", "Moon");
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).unwrap();
+        fs::write(&path, b"Lune").unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        let mut reader = store
+            .get_object_with("earth", "Moon", GetOptions::new().verify_checksum())
+            .unwrap();
+        let err = io::copy(&mut reader, &mut io::sink()).unwrap_err();
+        assert_eq!(
+            Error::io(err, "earth", "Moon").kind(),
+            &ErrorKind::ChecksumMismatch,
+            "FsStore did not detect corrupted object."
+        );
+    }
+
+    #[test]
+    fn fs_store_head_object_should_ignore_metadata_of_replaced_file() {
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().content_type("text/plain");
+        let _ = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        // What a crash between moving the file into place and its metadata leaves behind.
+        let temp = store.write_temp(&mut &b"Selene"[..]).unwrap();
+        commit_file(&temp, &store.object_path("earth", "Moon")).unwrap();
+        let metadata = store.head_object("earth", "Moon");
+        assert_eq!(
+            metadata.as_ref().map(|m| &m.etag),
+            Some(&checksum::digest(b"Selene")),
+            "FsStore read the metadata of a replaced file."
+        );
+        assert_eq!(metadata.and_then(|m| m.content_type), None);
+    }
+
+    #[test]
+    fn fs_store_open_should_discard_temporary_files() {
+        let dir = TempDir::new();
+        let store = FsStore::open(dir.path()).unwrap();
+        let _ = store.write_temp(&mut get_reader());
+        let _ = FsStore::open(dir.path()).unwrap();
+        assert_eq!(
+            fs::read_dir(dir.path().join(TEMP_DIR)).unwrap().count(),
+            0,
+            "FsStore did not discard temporary files."
+        );
+    }
+
+    #[test]
+    fn fs_store_open_should_discard_uploads_in_progress() {
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap();
+        let _ = store.upload_part("earth", "Moon", &upload_id, 1, &mut get_reader());
+        let store = FsStore::open(dir.path()).unwrap();
+        assert_eq!(store.list_multipart_uploads("earth"), Some(vec![]));
+        assert!(
+            !store.upload_path(&upload_id).exists(),
+            "FsStore did not discard parts of upload in progress."
+        );
+    }
+
+    #[test]
+    fn fs_store_put_object_should_not_leave_partial_object_on_error() {
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("connection reset"))
+            }
+        }
+
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        let mut reader = Box::new(FailingReader) as Box<dyn Read>;
+        let err = store.put_object("earth", "Moon", &mut reader).unwrap_err();
+        assert_eq!(
+            std::error::Error::source(&err).map(|e| e.to_string()),
+            Some("connection reset".to_string()),
+            "FsStore lost the cause of a failed write."
+        );
+        assert!(
+            store.get_object("earth", "Moon").is_none(),
+            "FsStore exposed a partially written object."
+        );
+        assert_eq!(
+            fs::read_dir(dir.path().join(TEMP_DIR)).unwrap().count(),
+            0,
+            "FsStore left a temporary file behind."
+        );
+    }
+
+    #[test]
+    fn fs_store_insert_object_should_reject_name_too_long_for_a_file() {
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_reader());
+        let long = "é".repeat(64);
+        assert!(
+            matches!(store.insert_object("earth", &long, &mut get_reader()), Err(err) if *err.kind() == ErrorKind::InvalidObjectName),
+            "FsStore failed to reject a name too long for a file."
+        );
+        assert!(
+            matches!(store.copy_object("earth", "Moon", "earth", &long), Err(err) if *err.kind() == ErrorKind::InvalidObjectName),
+            "FsStore failed to reject a copy to a name too long for a file."
+        );
+    }
+
+    #[test]
+    fn fs_store_insert_object_should_reject_empty_name() {
+        let dir = TempDir::new();
+        let mut store = FsStore::open(dir.path()).unwrap();
+        let _ = store.create_bucket("earth");
+        assert!(
+            matches!(store.insert_object("earth", "", &mut get_reader()), Err(err) if *err.kind() == ErrorKind::InvalidObjectName),
+            "FsStore failed to reject an empty object name."
+        );
+    }
+}
@@ -1,3 +1,7 @@
+pub mod archive;
 pub mod bucket;
+pub mod durable;
 pub mod object;
+pub mod pool;
+pub mod shared;
 pub mod store;
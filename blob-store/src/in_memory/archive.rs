@@ -0,0 +1,426 @@
+use crate::compression::Compression;
+use crate::in_memory::bucket::Bucket;
+use crate::in_memory::object::Object;
+use crate::in_memory::pool::DataPool;
+use crate::lifecycle::Rule;
+use crate::metadata::ObjectMetadata;
+use crate::quota::Quota;
+use crate::tag::TagQuery;
+use std::convert::TryInto;
+use std::io;
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// A store archive holds the buckets and objects of a store, written one after the other so
+// neither saving nor loading needs a second copy of the data in memory. Multipart uploads in
+// progress aren't saved. Numbers are big-endian:
+//
+//   archive  = "BLOBSTORE" version:u32 dedup:u8 quota bucket-count:u64 bucket*
+//   bucket   = name:string versioning:u8 compression:u8 quota rule-count:u32 rule*
+//              version-count:u64 version*
+//   quota    = max-bytes:option max-objects:option max-object-size:option
+//   rule     = prefix:string expire-after-nanos:option keep-versions:option
+//              tag-query:optional-string
+//   version  = name:string id:string modified:time 0:u8                 (delete marker)
+//            | name:string id:string modified:time 1:u8 object
+//   object   = compression:u8 metadata data-length:u64 data
+//   metadata = length:u64 created:time modified:time etag:string content-type:optional-string
+//              user-count:u32 (key:string value:string)* tag-count:u32 (key:string value:string)*
+//   option   = 0:u8 | 1:u8 value:u64
+//   string   = length:u32 utf-8
+//   time     = nanoseconds since epoch:u128
+//
+// The metadata length is the original length of the object, its data is saved as it is stored,
+// compressed or not. Objects are always saved with their own data, so a deduplicating store saves
+// shared data once per object and shares it again when it is loaded.
+//
+// Version 1 archives, written before tags, have neither tag queries in rules nor tags in
+// metadata. They are still read, archives are always written as the latest version.
+const MAGIC: &[u8] = b"BLOBSTORE";
+pub const VERSION: u32 = 2;
+
+pub struct Header {
+    pub version: u32,
+    pub dedup: bool,
+    pub quota: Quota,
+    pub buckets: u64,
+}
+
+pub fn write_header<W: Write>(writer: &mut W, header: &Header) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_be_bytes())?;
+    writer.write_all(&[header.dedup as u8])?;
+    write_quota(writer, &header.quota)?;
+    writer.write_all(&header.buckets.to_be_bytes())
+}
+
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<Header> {
+    let mut magic = [0u8; 9];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a store archive"));
+    }
+    let version = read_u32(reader)?;
+    if !(1..=VERSION).contains(&version) {
+        return Err(invalid("unsupported store archive version"));
+    }
+    Ok(Header {
+        version,
+        dedup: read_flag(reader)?,
+        quota: read_quota(reader)?,
+        buckets: read_u64(reader)?,
+    })
+}
+
+pub fn write_bucket<W: Write>(writer: &mut W, name: &str, bucket: &Bucket) -> io::Result<()> {
+    write_string(writer, name)?;
+    writer.write_all(&[bucket.is_versioned() as u8])?;
+    writer.write_all(&[compression_code(bucket.compression())])?;
+    write_quota(writer, bucket.quota())?;
+    writer.write_all(&(bucket.lifecycle().len() as u32).to_be_bytes())?;
+    for rule in bucket.lifecycle() {
+        write_string(writer, &rule.prefix)?;
+        write_option(writer, rule.expire_after.map(|ttl| ttl.as_nanos() as u64))?;
+        write_option(writer, rule.keep_versions.map(|count| count as u64))?;
+        match &rule.tags {
+            None => writer.write_all(&[0])?,
+            Some(query) => {
+                writer.write_all(&[1])?;
+                write_string(writer, &query.to_string())?;
+            }
+        }
+    }
+    writer.write_all(&(bucket.version_count() as u64).to_be_bytes())?;
+    for version in bucket.stored_versions() {
+        write_string(writer, version.name)?;
+        write_string(writer, version.id)?;
+        write_time(writer, version.modified)?;
+        match version.object {
+            None => writer.write_all(&[0])?,
+            Some(obj) => {
+                writer.write_all(&[1])?;
+                write_object(writer, obj)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Reads a bucket from an archive of the given version, sharing the data of its objects through
+// the pool when there is one.
+pub fn read_bucket<R: Read>(
+    reader: &mut R,
+    version: u32,
+    mut pool: Option<&mut DataPool>,
+) -> io::Result<(String, Bucket)> {
+    let name = read_string(reader)?;
+    let mut bucket = Bucket::new();
+    bucket.set_versioning(read_flag(reader)?);
+    bucket.set_compression(read_compression(reader)?);
+    bucket.set_quota(read_quota(reader)?);
+    let mut rules = Vec::<Rule>::new();
+    for _ in 0..read_u32(reader)? {
+        let mut rule = Rule::new().prefix(&read_string(reader)?);
+        if let Some(nanos) = read_option(reader)? {
+            rule = rule.expire_after(Duration::from_nanos(nanos));
+        }
+        if let Some(count) = read_option(reader)? {
+            rule = rule.keep_versions(count as usize);
+        }
+        if version >= 2 && read_flag(reader)? {
+            match TagQuery::parse(&read_string(reader)?) {
+                None => return Err(invalid("invalid tag query")),
+                Some(query) => rule = rule.tags(query),
+            }
+        }
+        rules.push(rule);
+    }
+    bucket.set_lifecycle(rules);
+    for _ in 0..read_u64(reader)? {
+        let name = read_string(reader)?;
+        let id = read_string(reader)?;
+        let modified = read_time(reader)?;
+        let object = match read_flag(reader)? {
+            false => None,
+            true => {
+                let mut obj = read_object(reader, version)?;
+                obj.metadata_mut().version_id = id.clone();
+                if let Some(pool) = pool.as_mut() {
+                    pool.intern(&mut obj);
+                }
+                Some(obj)
+            }
+        };
+        bucket.restore_version(&name, &id, object, modified);
+    }
+    Ok((name, bucket))
+}
+
+fn write_object<W: Write>(writer: &mut W, obj: &Object) -> io::Result<()> {
+    let metadata = obj.metadata();
+    writer.write_all(&[compression_code(obj.compression())])?;
+    writer.write_all(&metadata.length.to_be_bytes())?;
+    write_time(writer, metadata.created)?;
+    write_time(writer, metadata.modified)?;
+    write_string(writer, &metadata.etag)?;
+    match &metadata.content_type {
+        None => writer.write_all(&[0])?,
+        Some(content_type) => {
+            writer.write_all(&[1])?;
+            write_string(writer, content_type)?;
+        }
+    }
+    for pairs in &[&metadata.user, &metadata.tags] {
+        writer.write_all(&(pairs.len() as u32).to_be_bytes())?;
+        for (key, value) in pairs.iter() {
+            write_string(writer, key)?;
+            write_string(writer, value)?;
+        }
+    }
+    let data = obj.stored_data();
+    writer.write_all(&(data.len() as u64).to_be_bytes())?;
+    writer.write_all(data)
+}
+
+fn read_object<R: Read>(reader: &mut R, version: u32) -> io::Result<Object> {
+    let compression = read_compression(reader)?;
+    let mut metadata = ObjectMetadata::new();
+    metadata.length = read_u64(reader)?;
+    metadata.created = read_time(reader)?;
+    metadata.modified = read_time(reader)?;
+    metadata.etag = read_string(reader)?;
+    if read_flag(reader)? {
+        metadata.content_type = Some(read_string(reader)?);
+    }
+    for _ in 0..read_u32(reader)? {
+        let key = read_string(reader)?;
+        metadata.user.insert(key, read_string(reader)?);
+    }
+    if version >= 2 {
+        for _ in 0..read_u32(reader)? {
+            let key = read_string(reader)?;
+            metadata.tags.insert(key, read_string(reader)?);
+        }
+    }
+    let length = read_u64(reader)?;
+    let data = read_bytes(reader, length)?;
+    if compression == Compression::None && data.len() as u64 != metadata.length {
+        return Err(invalid("object length doesn't match its data"));
+    }
+    Ok(Object::from_stored(data, metadata, compression))
+}
+
+fn write_quota<W: Write>(writer: &mut W, quota: &Quota) -> io::Result<()> {
+    write_option(writer, quota.max_bytes)?;
+    write_option(writer, quota.max_objects)?;
+    write_option(writer, quota.max_object_size)
+}
+
+fn read_quota<R: Read>(reader: &mut R) -> io::Result<Quota> {
+    Ok(Quota {
+        max_bytes: read_option(reader)?,
+        max_objects: read_option(reader)?,
+        max_object_size: read_option(reader)?,
+    })
+}
+
+fn compression_code(compression: Compression) -> u8 {
+    match compression {
+        Compression::None => 0,
+        Compression::Gzip => 1,
+        Compression::Zstd => 2,
+        Compression::Lz4 => 3,
+    }
+}
+
+fn read_compression<R: Read>(reader: &mut R) -> io::Result<Compression> {
+    match read_u8(reader)? {
+        0 => Ok(Compression::None),
+        1 => Ok(Compression::Gzip),
+        2 => Ok(Compression::Zstd),
+        3 => Ok(Compression::Lz4),
+        _ => Err(invalid("unknown compression")),
+    }
+}
+
+fn write_option<W: Write>(writer: &mut W, value: Option<u64>) -> io::Result<()> {
+    match value {
+        None => writer.write_all(&[0]),
+        Some(value) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&value.to_be_bytes())
+        }
+    }
+}
+
+fn read_option<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    match read_flag(reader)? {
+        false => Ok(None),
+        true => read_u64(reader).map(Some),
+    }
+}
+
+pub fn write_string<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    writer.write_all(&(s.len() as u32).to_be_bytes())?;
+    writer.write_all(s.as_bytes())
+}
+
+pub fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let length = read_u32(reader)?;
+    match String::from_utf8(read_bytes(reader, length as u64)?) {
+        Err(_) => Err(invalid("string is not UTF-8")),
+        Ok(s) => Ok(s),
+    }
+}
+
+pub fn write_time<W: Write>(writer: &mut W, time: SystemTime) -> io::Result<()> {
+    let nanos = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos())
+        .unwrap_or_default();
+    writer.write_all(&nanos.to_be_bytes())
+}
+
+pub fn read_time<R: Read>(reader: &mut R) -> io::Result<SystemTime> {
+    let mut bytes = [0u8; 16];
+    reader.read_exact(&mut bytes)?;
+    let nanos = u128::from_be_bytes(bytes);
+    let secs = (nanos / 1_000_000_000).try_into().unwrap_or(u64::MAX);
+    let since = Duration::new(secs, (nanos % 1_000_000_000) as u32);
+    match UNIX_EPOCH.checked_add(since) {
+        None => Err(invalid("time out of range")),
+        Some(time) => Ok(time),
+    }
+}
+
+// Reads exactly the given number of bytes, without trusting the length for the allocation.
+pub fn read_bytes<R: Read>(reader: &mut R, length: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(length).read_to_end(&mut data)?;
+    if (data.len() as u64) < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+pub fn read_flag<R: Read>(reader: &mut R) -> io::Result<bool> {
+    match read_u8(reader)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid("invalid flag")),
+    }
+}
+
+pub fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0u8; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+pub fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn archive_should_round_trip_bucket() {
+        let mut bucket = Bucket::new();
+        bucket.set_versioning(true);
+        bucket.set_quota(Quota::new().max_objects(10));
+        bucket.set_lifecycle(vec![
+            Rule::new().prefix("tmp/").keep_versions(3),
+            Rule::new().tags(TagQuery::exists("scratch")),
+        ]);
+        let mut moon = Object::from("Luna");
+        moon.metadata_mut().content_type = Some("text/plain".into());
+        moon.metadata_mut()
+            .user
+            .insert("orbit".into(), "earth".into());
+        moon.metadata_mut()
+            .tags
+            .insert("kind".into(), "moon".into());
+        bucket.put("Moon", moon);
+        bucket.put("Moon", Object::from("Selene"));
+        bucket.remove("Moon");
+        let mut archive = Vec::new();
+        let _ = write_bucket(&mut archive, "earth", &bucket);
+
+        let restored = read_bucket(&mut Cursor::new(archive), VERSION, None);
+        let (name, restored) = match restored {
+            Err(err) => panic!("Failed to read bucket: {}", err),
+            Ok(restored) => restored,
+        };
+        assert_eq!(name, "earth");
+        assert!(restored.is_versioned());
+        assert_eq!(restored.quota(), bucket.quota());
+        assert_eq!(restored.lifecycle(), bucket.lifecycle());
+        assert_eq!(
+            restored.versions(),
+            bucket.versions(),
+            "Archive lost versions."
+        );
+        let oldest = &bucket.versions()[2].version_id;
+        assert_eq!(
+            restored.version("Moon", oldest).map(|obj| obj.metadata()),
+            bucket.version("Moon", oldest).map(|obj| obj.metadata()),
+            "Archive lost object metadata."
+        );
+    }
+
+    #[test]
+    fn read_bucket_should_read_version_1_archive() {
+        let mut archive = Vec::new();
+        let _ = write_string(&mut archive, "earth");
+        archive.extend_from_slice(&[0, 0, 0, 0, 0]);
+        archive.extend_from_slice(&1u32.to_be_bytes());
+        let _ = write_string(&mut archive, "tmp/");
+        archive.extend_from_slice(&[0, 0]);
+        archive.extend_from_slice(&1u64.to_be_bytes());
+        let _ = write_string(&mut archive, "Moon");
+        let _ = write_string(&mut archive, "null");
+        let _ = write_time(&mut archive, UNIX_EPOCH);
+        archive.extend_from_slice(&[1, 0]);
+        archive.extend_from_slice(&4u64.to_be_bytes());
+        let _ = write_time(&mut archive, UNIX_EPOCH);
+        let _ = write_time(&mut archive, UNIX_EPOCH);
+        let _ = write_string(&mut archive, "etag");
+        archive.push(0);
+        archive.extend_from_slice(&0u32.to_be_bytes());
+        archive.extend_from_slice(&4u64.to_be_bytes());
+        archive.extend_from_slice(b"Luna");
+
+        let restored = read_bucket(&mut Cursor::new(archive), 1, None);
+        let (_, restored) = match restored {
+            Err(err) => panic!("Failed to read version 1 bucket: {}", err),
+            Ok(restored) => restored,
+        };
+        assert_eq!(restored.lifecycle(), &[Rule::new().prefix("tmp/")]);
+        assert_eq!(
+            restored.head("Moon").map(|m| m.tags.is_empty()),
+            Some(true),
+            "Archive failed to read a version 1 object."
+        );
+    }
+
+    #[test]
+    fn read_header_should_reject_other_files() {
+        let result = read_header(&mut Cursor::new(b"NOTASTORE\0\0\0\x01".to_vec()));
+        assert!(result.is_err(), "Archive accepted a file of another kind.");
+    }
+}
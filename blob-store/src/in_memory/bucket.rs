@@ -1,41 +1,414 @@
+use crate::compression::Compression;
 use crate::in_memory::object::{Object, ObjectReader};
-use std::collections::HashMap;
+use crate::lifecycle;
+use crate::lifecycle::Rule;
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::quota::{Quota, Usage};
+use crate::tag::{TagQuery, Tags};
+use crate::version;
+use crate::version::{ObjectVersion, Stamp, NULL_VERSION};
+use std::collections::BTreeMap;
+use std::ops::{Bound, Range};
+use std::time::SystemTime;
 
+// A version of an object, or a delete marker when there is no object.
+struct Version {
+    id: String,
+    object: Option<Object>,
+    modified: SystemTime,
+}
+
+// A version as it is kept, for saving the bucket.
+pub struct StoredVersion<'a> {
+    pub name: &'a str,
+    pub id: &'a str,
+    pub object: Option<&'a Object>,
+    pub modified: SystemTime,
+}
+
+// Keeps the versions of every object, oldest first. Without versioning each object only has a
+// single version with the null version ID, which is replaced on write and dropped on removal.
 pub struct Bucket {
-    objects: HashMap<String, Object>,
+    objects: BTreeMap<String, Vec<Version>>,
+    versioning: bool,
+    compression: Compression,
+    quota: Quota,
+    usage: Usage,
+    rules: Vec<Rule>,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bucket {
     pub fn new() -> Self {
         Bucket {
-            objects: HashMap::<String, Object>::new(),
+            objects: BTreeMap::<String, Vec<Version>>::new(),
+            versioning: false,
+            compression: Compression::None,
+            quota: Quota::new(),
+            usage: Usage::default(),
+            rules: Vec::<Rule>::new(),
         }
     }
 
-    pub fn names(&self) -> Vec<&str> {
+    pub fn is_versioned(&self) -> bool {
+        self.versioning
+    }
+
+    // Suspending versioning keeps existing versions, new writes get the null version ID again.
+    pub fn set_versioning(&mut self, enabled: bool) {
+        self.versioning = enabled;
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    // Only applies to objects written from now on, stored objects keep their compression.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    pub fn set_quota(&mut self, quota: Quota) {
+        self.quota = quota;
+    }
+
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    // What writing the object frees, which is its null version when versioning is off.
+    pub fn reclaimable(&self, name: &str) -> Usage {
+        if self.versioning {
+            return Usage::default();
+        }
+        self.version(name, NULL_VERSION)
+            .map(|obj| Usage::of(obj.metadata().length))
+            .unwrap_or_default()
+    }
+
+    pub fn lifecycle(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    // Expired versions are hidden right away and dropped by the next sweep.
+    pub fn set_lifecycle(&mut self, rules: Vec<Rule>) {
+        self.rules = rules;
+    }
+
+    fn is_expired(&self, name: &str, versions: &[Version], i: usize, now: SystemTime) -> bool {
+        let rank = versions.len() - 1 - i;
+        let tags = versions[i].object.as_ref().map(|obj| &obj.metadata().tags);
+        lifecycle::expires(&self.rules, name, tags, rank, versions[i].modified, now)
+    }
+
+    // Drops the expired versions of an object, returning how many there were.
+    pub fn expire_object(&mut self, name: &str, now: SystemTime) -> usize {
+        self.update(name, |bucket| {
+            let rules = &bucket.rules;
+            let versions = match bucket.objects.get_mut(name) {
+                None => return 0,
+                Some(versions) => versions,
+            };
+            let len = versions.len();
+            let mut rank = len;
+            versions.retain(|version| {
+                rank -= 1;
+                let tags = version.object.as_ref().map(|obj| &obj.metadata().tags);
+                !lifecycle::expires(rules, name, tags, rank, version.modified, now)
+            });
+            let expired = len - versions.len();
+            if versions.is_empty() {
+                bucket.objects.remove(name);
+            }
+            expired
+        })
+    }
+
+    pub fn expire(&mut self, now: SystemTime) -> usize {
+        if self.rules.is_empty() {
+            return 0;
+        }
+        let names = self.objects.keys().cloned().collect::<Vec<String>>();
+        names.iter().map(|name| self.expire_object(name, now)).sum()
+    }
+
+    // Runs a change to the versions of an object and keeps the bucket usage up to date.
+    fn update<T, F: FnOnce(&mut Self) -> T>(&mut self, name: &str, change: F) -> T {
+        let before = self.name_usage(name);
+        let result = change(self);
+        self.usage = self.usage - before + self.name_usage(name);
+        result
+    }
+
+    fn name_usage(&self, name: &str) -> Usage {
         self.objects
-            .keys()
-            .map(|k| k.as_ref())
-            .collect::<Vec<&str>>()
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter_map(|version| version.object.as_ref())
+            .map(|obj| Usage::of(obj.metadata().length))
+            .fold(Usage::default(), |total, usage| total + usage)
+    }
+
+    pub fn latest(&self, name: &str) -> Option<&Object> {
+        let versions = self.objects.get(name)?;
+        let i = versions.len().checked_sub(1)?;
+        if self.is_expired(name, versions, i, SystemTime::now()) {
+            return None;
+        }
+        versions[i].object.as_ref()
+    }
+
+    pub fn version(&self, name: &str, version_id: &str) -> Option<&Object> {
+        let versions = self.objects.get(name)?;
+        let i = versions
+            .iter()
+            .position(|version| version.id == version_id)?;
+        if self.is_expired(name, versions, i, SystemTime::now()) {
+            return None;
+        }
+        versions[i].object.as_ref()
+    }
+
+    fn current_names(&self, start: Bound<&str>) -> impl Iterator<Item = &str> {
+        let now = SystemTime::now();
+        self.objects
+            .range::<str, _>((start, Bound::Unbounded))
+            .filter(
+                move |(name, versions)| match versions.len().checked_sub(1) {
+                    None => false,
+                    Some(i) => {
+                        versions[i].object.is_some() && !self.is_expired(name, versions, i, now)
+                    }
+                },
+            )
+            .map(|(k, _)| k.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.current_names(Bound::Unbounded).collect::<Vec<&str>>()
+    }
+
+    pub fn list(&self, options: &ListOptions) -> ObjectList<'_> {
+        options.list(self.current_names(options.start()))
     }
 
     pub fn exist(&self, name: &str) -> bool {
-        self.objects.contains_key(name)
+        self.latest(name).is_some()
+    }
+
+    // Names of the current objects whose tags match the query, in order.
+    pub fn find(&self, query: &TagQuery) -> Vec<&str> {
+        self.current_names(Bound::Unbounded)
+            .filter(
+                |name| matches!(self.head(name), Some(metadata) if query.matches(&metadata.tags)),
+            )
+            .collect::<Vec<&str>>()
+    }
+
+    // Replaces the tags of the latest version in place, returning whether there is one. The data
+    // and the other metadata stay as they are.
+    pub fn set_tags(&mut self, name: &str, tags: Tags) -> bool {
+        if !self.exist(name) {
+            return false;
+        }
+        let latest = self
+            .objects
+            .get_mut(name)
+            .and_then(|versions| versions.last_mut())
+            .and_then(|version| version.object.as_mut());
+        match latest {
+            None => false,
+            Some(obj) => {
+                obj.metadata_mut().tags = tags;
+                true
+            }
+        }
     }
 
     pub fn put(&mut self, name: &str, object: Object) {
-        self.objects.insert(name.into(), object);
+        self.put_as(name, object, version::new_version_id())
     }
 
-    pub fn get(&self, name: &str) -> Option<ObjectReader> {
-        match self.objects.get(name) {
-            None => None,
-            Some(obj) => Some(ObjectReader::from(obj)),
+    // Puts the object under the given version ID when versioning is enabled.
+    pub fn put_as(&mut self, name: &str, object: Object, version_id: String) {
+        self.update(name, |bucket| bucket.put_version(name, object, version_id))
+    }
+
+    fn put_version(&mut self, name: &str, mut object: Object, version_id: String) {
+        let id = if self.versioning {
+            version_id
+        } else {
+            NULL_VERSION.into()
+        };
+        object.metadata_mut().version_id = id.clone();
+        let versions = self.objects.entry(name.into()).or_default();
+        if id == NULL_VERSION {
+            versions.retain(|version| version.id != NULL_VERSION);
         }
+        versions.push(Version {
+            id,
+            modified: object.metadata().modified,
+            object: Some(object),
+        });
+    }
+
+    pub fn head(&self, name: &str) -> Option<&ObjectMetadata> {
+        self.latest(name).map(|obj| obj.metadata())
     }
 
+    pub fn get(&self, name: &str) -> Option<ObjectReader<'_>> {
+        self.latest(name).map(ObjectReader::from)
+    }
+
+    pub fn get_range(&self, name: &str, range: Range<u64>) -> Option<ObjectReader<'_>> {
+        self.latest(name)
+            .map(|obj| ObjectReader::with_range(obj, range))
+    }
+
+    pub fn get_with_metadata(&self, name: &str) -> Option<(ObjectReader<'_>, &ObjectMetadata)> {
+        self.latest(name)
+            .map(|obj| (ObjectReader::from(obj), obj.metadata()))
+    }
+
+    pub fn get_version(
+        &self,
+        name: &str,
+        version_id: &str,
+    ) -> Option<(ObjectReader<'_>, &ObjectMetadata)> {
+        self.version(name, version_id)
+            .map(|obj| (ObjectReader::from(obj), obj.metadata()))
+    }
+
+    // Without versioning the object is removed and returned. Otherwise a delete marker becomes
+    // the latest version and earlier versions are kept.
     pub fn remove(&mut self, name: &str) -> Option<Object> {
-        self.objects.remove(name)
+        self.remove_as(name, &Stamp::new())
+    }
+
+    // Removes the object like `remove`, stamping a delete marker it adds.
+    pub fn remove_as(&mut self, name: &str, stamp: &Stamp) -> Option<Object> {
+        self.update(name, |bucket| bucket.remove_latest(name, stamp))
+    }
+
+    fn remove_latest(&mut self, name: &str, stamp: &Stamp) -> Option<Object> {
+        let versioning = self.versioning;
+        let versions = self.objects.get_mut(name)?;
+        let mut removed = None;
+        if !versioning {
+            if let Some(i) = versions.iter().position(|v| v.id == NULL_VERSION) {
+                removed = versions.remove(i).object;
+            }
+        }
+        if versioning || !versions.is_empty() {
+            versions.push(Version {
+                id: if versioning {
+                    stamp.version_id.clone()
+                } else {
+                    NULL_VERSION.into()
+                },
+                object: None,
+                modified: stamp.time,
+            });
+        } else {
+            self.objects.remove(name);
+        }
+        removed
+    }
+
+    // Permanently removes a single version or delete marker, returning whether it existed.
+    pub fn remove_version(&mut self, name: &str, version_id: &str) -> bool {
+        self.update(name, |bucket| bucket.remove_version_of(name, version_id))
+    }
+
+    fn remove_version_of(&mut self, name: &str, version_id: &str) -> bool {
+        let versions = match self.objects.get_mut(name) {
+            None => return false,
+            Some(versions) => versions,
+        };
+        let len = versions.len();
+        versions.retain(|version| version.id != version_id);
+        let removed = versions.len() != len;
+        if versions.is_empty() {
+            self.objects.remove(name);
+        }
+        removed
+    }
+
+    // Every version and delete marker, expired or not, ordered by name and then oldest first.
+    pub fn stored_versions(&self) -> impl Iterator<Item = StoredVersion<'_>> {
+        self.objects.iter().flat_map(|(name, versions)| {
+            versions.iter().map(move |version| StoredVersion {
+                name,
+                id: &version.id,
+                object: version.object.as_ref(),
+                modified: version.modified,
+            })
+        })
+    }
+
+    // Adds a saved version as the newest one of the object.
+    pub fn restore_version(
+        &mut self,
+        name: &str,
+        id: &str,
+        object: Option<Object>,
+        modified: SystemTime,
+    ) {
+        self.update(name, |bucket| {
+            bucket
+                .objects
+                .entry(name.into())
+                .or_default()
+                .push(Version {
+                    id: id.into(),
+                    object,
+                    modified,
+                })
+        })
+    }
+
+    // Delete markers count as content, as they do in S3.
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn version_count(&self) -> usize {
+        self.objects.values().map(|versions| versions.len()).sum()
+    }
+
+    // Lists every version of every object, ordered by name and then newest first.
+    pub fn versions(&self) -> Vec<ObjectVersion> {
+        let now = SystemTime::now();
+        let mut list = Vec::<ObjectVersion>::new();
+        for (name, versions) in &self.objects {
+            for (i, version) in versions.iter().enumerate().rev() {
+                if self.is_expired(name, versions, i, now) {
+                    continue;
+                }
+                let metadata = version.object.as_ref().map(|obj| obj.metadata());
+                list.push(ObjectVersion {
+                    name: name.clone(),
+                    version_id: version.id.clone(),
+                    is_latest: i == versions.len() - 1,
+                    is_delete_marker: version.object.is_none(),
+                    modified: version.modified,
+                    length: metadata.map(|m| m.length).unwrap_or_default(),
+                    etag: metadata.map(|m| m.etag.clone()).unwrap_or_default(),
+                });
+            }
+        }
+        list
     }
 }
 
@@ -44,6 +417,7 @@ mod tests {
     use super::*;
     use crate::in_memory::object::tests::generate_random_byte_array;
     use std::io;
+    use std::time::Duration;
 
     fn get_names() -> Vec<&'static str> {
         vec!["Mars", "Jupiter", "Saturn", "Uranus", "Neptune", "Pluto"]
@@ -71,6 +445,17 @@ mod tests {
         assert_eq!(names, output, "Bucket did not return all object names.");
     }
 
+    #[test]
+    fn bucket_list_should_start_after_continuation_token() {
+        let bucket = get_filled_bucket();
+        let options = ListOptions::new().continuation_token("Neptune");
+        assert_eq!(
+            bucket.list(&options).objects,
+            vec!["Pluto", "Saturn", "Uranus"],
+            "Bucket did not continue listing after the token."
+        );
+    }
+
     #[test]
     fn bucket_exist_should_return_true_when_object_exist() {
         let bucket = get_filled_bucket();
@@ -108,6 +493,68 @@ mod tests {
         assert_eq!(input, output, "Bucket failed to replace existing object.");
     }
 
+    #[test]
+    fn bucket_head_should_return_object_metadata() {
+        let mut bucket = Bucket::new();
+        let name = "Earth";
+        bucket.put(name, Object::from(generate_random_byte_array(64)));
+        assert_eq!(
+            bucket.head(name).map(|m| m.length),
+            Some(64),
+            "Bucket did not return object metadata."
+        );
+    }
+
+    #[test]
+    fn bucket_put_should_keep_previous_versions_when_versioned() {
+        let mut bucket = Bucket::new();
+        bucket.set_versioning(true);
+        bucket.put("Earth", Object::from("Terra"));
+        bucket.put("Earth", Object::from("Gaia"));
+        let versions = bucket.versions();
+        assert_eq!(versions.len(), 2, "Bucket did not keep previous version.");
+        assert!(versions[0].is_latest && !versions[1].is_latest);
+        let mut output = Vec::new();
+        if let Some((mut reader, _)) = bucket.get_version("Earth", &versions[1].version_id) {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(output, b"Terra", "Bucket returned wrong version.");
+    }
+
+    #[test]
+    fn bucket_remove_should_add_delete_marker_when_versioned() {
+        let mut bucket = Bucket::new();
+        bucket.set_versioning(true);
+        bucket.put("Earth", Object::from("Terra"));
+        bucket.remove("Earth");
+        assert!(!bucket.exist("Earth"), "Bucket kept removed object.");
+        assert!(bucket.names().is_empty(), "Bucket listed removed object.");
+        let versions = bucket.versions();
+        assert!(
+            versions[0].is_delete_marker && !versions[1].is_delete_marker,
+            "Bucket did not add a delete marker."
+        );
+        bucket.remove_version("Earth", &versions[0].version_id);
+        assert!(bucket.exist("Earth"), "Bucket failed to restore object.");
+    }
+
+    #[test]
+    fn bucket_put_should_replace_null_version_when_versioning_suspended() {
+        let mut bucket = Bucket::new();
+        bucket.set_versioning(true);
+        bucket.put("Earth", Object::from("Terra"));
+        bucket.set_versioning(false);
+        bucket.put("Earth", Object::from("Gaia"));
+        bucket.put("Earth", Object::from("Tellus"));
+        let ids = bucket
+            .versions()
+            .into_iter()
+            .map(|v| v.version_id)
+            .collect::<Vec<String>>();
+        assert_eq!(ids.len(), 2, "Bucket kept more than one null version.");
+        assert_eq!(ids[0], NULL_VERSION);
+    }
+
     #[test]
     fn bucket_get_should_return_existing_object() {
         let mut bucket = Bucket::new();
@@ -138,4 +585,94 @@ mod tests {
             "Bucket failed to remove object."
         )
     }
+
+    #[test]
+    fn bucket_usage_should_follow_writes_and_removals() {
+        let mut bucket = Bucket::new();
+        bucket.put("Earth", Object::from("Terra"));
+        bucket.put("Earth", Object::from("Gaia"));
+        bucket.put("Mars", Object::from("Ares"));
+        assert_eq!(
+            bucket.usage(),
+            Usage {
+                bytes: 8,
+                objects: 2
+            }
+        );
+        bucket.remove("Earth");
+        bucket.set_versioning(true);
+        bucket.put("Mars", Object::from("Red"));
+        assert_eq!(
+            bucket.usage(),
+            Usage {
+                bytes: 7,
+                objects: 2
+            },
+            "Bucket failed to keep track of its usage."
+        );
+    }
+
+    #[test]
+    fn bucket_should_hide_expired_objects() {
+        let mut bucket = get_filled_bucket();
+        bucket.set_lifecycle(vec![Rule::new().prefix("M").expire_after(Duration::ZERO)]);
+        assert!(!bucket.exist("Mars"), "Bucket returned an expired object.");
+        assert!(bucket.exist("Jupiter"));
+        assert!(!bucket.names().contains(&"Mars"));
+        assert_eq!(bucket.expire(SystemTime::now()), 1);
+        assert_eq!(
+            bucket.usage().objects,
+            5,
+            "Bucket failed to drop an expired object."
+        );
+    }
+
+    #[test]
+    fn bucket_find_should_return_objects_with_matching_tags() {
+        let mut bucket = get_filled_bucket();
+        let moons = |count: &str| {
+            let mut tags = Tags::new();
+            tags.insert("moons".into(), count.into());
+            tags
+        };
+        bucket.set_tags("Mars", moons("2"));
+        bucket.set_tags("Neptune", moons("14"));
+        bucket.set_tags("Pluto", moons("5"));
+        let query = TagQuery::parse("moons & moons!=5").unwrap();
+        assert_eq!(
+            bucket.find(&query),
+            vec!["Mars", "Neptune"],
+            "Bucket did not find objects by their tags."
+        );
+        assert!(!bucket.set_tags("Earth", Tags::new()));
+        bucket.set_lifecycle(vec![Rule::new()
+            .tags(TagQuery::equals("moons", "2"))
+            .expire_after(Duration::ZERO)]);
+        assert!(
+            !bucket.exist("Mars"),
+            "Bucket kept an object expired by its tags."
+        );
+        assert!(bucket.exist("Neptune"));
+    }
+
+    #[test]
+    fn bucket_expire_should_keep_newest_versions() {
+        let mut bucket = Bucket::new();
+        bucket.set_versioning(true);
+        for name in &["Terra", "Gaia", "Tellus"] {
+            bucket.put("Earth", Object::from(*name));
+        }
+        bucket.set_lifecycle(vec![Rule::new().keep_versions(2)]);
+        assert_eq!(
+            bucket.versions().len(),
+            2,
+            "Bucket listed an expired version."
+        );
+        assert_eq!(bucket.expire(SystemTime::now()), 1);
+        let mut output = Vec::new();
+        if let Some(mut reader) = bucket.get("Earth") {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(output, b"Tellus", "Bucket expired the latest version.");
+    }
 }
@@ -0,0 +1,1179 @@
+use crate::bucket;
+use crate::checksum;
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::in_memory::archive::{
+    read_bytes, read_flag, read_string, read_time, read_u32, read_u64, read_u8, write_string,
+    write_time,
+};
+use crate::in_memory::object::ObjectReader;
+use crate::in_memory::store::InMemoryStore;
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::store::{
+    CopyOptions, DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions, Store,
+};
+use crate::tag;
+use crate::tag::{TagQuery, Tags};
+use crate::version::{ObjectVersion, Stamp};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_COMPACT_AFTER: u64 = 1024;
+const CHECKSUM_SIZE: usize = 8;
+
+// An InMemoryStore whose changes survive restarts. Every change is appended to a write-ahead log
+// and synced before it is applied, and the store is rebuilt on open from the latest snapshot and
+// the log written since. Once the log holds enough records it is compacted into a new snapshot.
+//
+// The directory holds one generation at a time: `snapshot-N`, an archive of the store, and
+// `log-N`, the changes made after it. A compaction writes generation N+1 in full before removing
+// generation N, so a crash at any point leaves a complete generation to open.
+//
+// Log records are framed as length:u32 checksum:[u8; 8] record, the checksum being the start of
+// the SHA-256 digest of the record. A torn record at the end of the log is dropped on open, a
+// damaged record followed by others fails it instead of losing the records after it.
+// Writes, copies, renames and removals are logged with the version IDs and times of the versions
+// and delete markers they create, under kinds 12 to 15, so replaying them recreates the versions
+// exactly. Older logs hold them as kinds 3, 4, 7, 8, 9 and 10, which are still read; changes
+// replayed from those get new version IDs and times.
+// Multipart uploads in progress are lost on restart, completing one is logged as a write of the
+// assembled object.
+pub struct DurableStore {
+    store: InMemoryStore,
+    dir: PathBuf,
+    generation: u64,
+    log: File,
+    records: u64,
+    compact_after: u64,
+}
+
+enum Record {
+    CreateBucket(String),
+    DeleteBucket(String, bool),
+    Put {
+        bucket: String,
+        name: String,
+        replace: bool,
+        content_type: Option<String>,
+        user: HashMap<String, String>,
+        tags: Tags,
+        data: Vec<u8>,
+        stamps: Vec<Stamp>,
+    },
+    RemoveObject(String, String, Vec<Stamp>),
+    RemoveVersion(String, String, String),
+    SetVersioning(String, bool),
+    Copy {
+        src_bucket: String,
+        src_name: String,
+        dst_bucket: String,
+        dst_name: String,
+        content_type: Option<String>,
+        user: HashMap<String, String>,
+        tags: Tags,
+        stamps: Vec<Stamp>,
+    },
+    Rename(String, String, String, String, Vec<Stamp>),
+    SetTags(String, String, Tags),
+}
+
+impl DurableStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let generation = latest_generation(&dir)?;
+        let mut store = match generation {
+            None => InMemoryStore::new(),
+            Some(generation) => InMemoryStore::load_from(snapshot_path(&dir, generation))?,
+        };
+        let generation = generation.unwrap_or_default();
+        let log_path = log_path(&dir, generation);
+        let mut log = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&log_path)?;
+        let (records, valid) = replay(&mut store, &mut log)?;
+        log.set_len(valid)?;
+        log.seek(SeekFrom::End(0))?;
+        log.sync_all()?;
+        remove_other_generations(&dir, generation)?;
+        Ok(DurableStore {
+            store,
+            dir,
+            generation,
+            log,
+            records,
+            compact_after: DEFAULT_COMPACT_AFTER,
+        })
+    }
+
+    // Compacts the log into a new snapshot once it holds this many records.
+    pub fn compact_after(mut self, records: u64) -> Self {
+        self.compact_after = records;
+        self
+    }
+
+    pub fn store(&self) -> &InMemoryStore {
+        &self.store
+    }
+
+    pub fn set_versioning(&mut self, bucket: &str, enabled: bool) -> Result<()> {
+        self.log_and_apply(Record::SetVersioning(bucket.into(), enabled))
+    }
+
+    pub fn compact(&mut self) -> io::Result<()> {
+        let generation = self.generation + 1;
+        let log = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(log_path(&self.dir, generation))?;
+        log.sync_all()?;
+        // The snapshot is what makes the new generation the latest one, so it comes last.
+        self.store.save_to(snapshot_path(&self.dir, generation))?;
+        sync_dir(&self.dir)?;
+        self.log = log;
+        self.generation = generation;
+        self.records = 0;
+        remove_other_generations(&self.dir, generation)
+    }
+
+    fn append(&mut self, record: &Record) -> io::Result<()> {
+        let mut payload = Vec::new();
+        encode(&mut payload, record)?;
+        let digest = Sha256::digest(&payload);
+        let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&digest[..CHECKSUM_SIZE]);
+        frame.extend_from_slice(&payload);
+        let start = self.log.seek(SeekFrom::End(0))?;
+        // A partly written record would make the records logged after it unreadable.
+        if let Err(err) = self
+            .log
+            .write_all(&frame)
+            .and_then(|_| self.log.sync_data())
+        {
+            let _ = self.log.set_len(start);
+            let _ = self.log.seek(SeekFrom::End(0));
+            return Err(err);
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    fn log(&mut self, record: &Record) -> Result<()> {
+        match self.append(record) {
+            Err(e) => {
+                let (bucket, name) = record.target();
+                Err(Error::io(e, &bucket, &name))
+            }
+            Ok(_) => Ok(()),
+        }
+    }
+
+    fn log_and_apply(&mut self, record: Record) -> Result<()> {
+        self.log(&record)?;
+        let result = apply(&mut self.store, record);
+        self.compact_if_due();
+        result
+    }
+
+    // A failed compaction leaves the current generation in place, it is retried later.
+    fn compact_if_due(&mut self) {
+        if self.records >= self.compact_after {
+            let _ = self.compact();
+        }
+    }
+
+    fn write(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+        replace: bool,
+    ) -> Result<()> {
+        tag::validate(bucket, name, options.tags())?;
+        self.require_bucket(bucket, name)?;
+        let replaced = self.store.head_object(bucket, name);
+        if !replace && replaced.is_some() {
+            return Err(Error::new(ErrorKind::ObjectAlreadyExist, bucket, name));
+        }
+        options
+            .preconditions()
+            .check(bucket, name, replaced.as_ref())?;
+        let mut data = Vec::new();
+        if let Err(e) = reader.read_to_end(&mut data) {
+            return Err(Error::io(e, bucket, name));
+        }
+        // Only writes that can succeed are logged.
+        if !options.verify(&checksum::digest(&data)) {
+            return Err(Error::new(ErrorKind::ChecksumMismatch, bucket, name));
+        }
+        let metadata = options.into_metadata(None);
+        self.log_and_apply(Record::Put {
+            bucket: bucket.into(),
+            name: name.into(),
+            replace,
+            content_type: metadata.content_type,
+            user: metadata.user,
+            tags: metadata.tags,
+            data,
+            stamps: vec![Stamp::new()],
+        })
+    }
+
+    // The metadata of an object to copy or rename, once the destination bucket is known to exist.
+    fn source(
+        &self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<ObjectMetadata> {
+        self.require_bucket(src_bucket, src_name)?;
+        let source = match self.store.head_object(src_bucket, src_name) {
+            None => return Err(Error::new(ErrorKind::ObjectNotFound, src_bucket, src_name)),
+            Some(metadata) => metadata,
+        };
+        self.require_bucket(dst_bucket, dst_name)?;
+        Ok(source)
+    }
+
+    fn require_bucket(&self, bucket: &str, name: &str) -> Result<()> {
+        match self.store.list_buckets().contains(&bucket) {
+            false => Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            true => Ok(()),
+        }
+    }
+}
+
+impl Record {
+    fn target(&self) -> (String, String) {
+        match self {
+            Record::CreateBucket(bucket)
+            | Record::DeleteBucket(bucket, _)
+            | Record::SetVersioning(bucket, _) => (bucket.clone(), String::new()),
+            Record::Put { bucket, name, .. }
+            | Record::RemoveObject(bucket, name, _)
+            | Record::RemoveVersion(bucket, name, _)
+            | Record::SetTags(bucket, name, _)
+            | Record::Copy {
+                dst_bucket: bucket,
+                dst_name: name,
+                ..
+            }
+            | Record::Rename(_, _, bucket, name, _) => (bucket.clone(), name.clone()),
+        }
+    }
+}
+
+fn apply(store: &mut InMemoryStore, record: Record) -> Result<()> {
+    let result = match record {
+        Record::CreateBucket(bucket) => store.create_bucket(&bucket),
+        Record::DeleteBucket(bucket, force) => {
+            let mut options = DeleteBucketOptions::new();
+            if force {
+                options = options.force();
+            }
+            store.delete_bucket_with(&bucket, options)
+        }
+        Record::Put {
+            bucket,
+            name,
+            replace,
+            content_type,
+            user,
+            tags,
+            data,
+            stamps,
+        } => {
+            let options = put_options(content_type, user, tags);
+            let mut reader = Box::new(Cursor::new(data)) as Box<dyn Read>;
+            store.set_stamps(stamps);
+            store.insert_or_replace_object(&bucket, &name, &mut reader, options, replace)
+        }
+        Record::RemoveObject(bucket, name, stamps) => {
+            store.set_stamps(stamps);
+            store.remove_object(&bucket, &name)
+        }
+        Record::RemoveVersion(bucket, name, version_id) => {
+            store.remove_object_version(&bucket, &name, &version_id)
+        }
+        Record::SetVersioning(bucket, enabled) => store.set_versioning(&bucket, enabled),
+        Record::Copy {
+            src_bucket,
+            src_name,
+            dst_bucket,
+            dst_name,
+            content_type,
+            user,
+            tags,
+            stamps,
+        } => {
+            let options = put_options(content_type, user, tags);
+            let options = CopyOptions::new().replace_metadata(options);
+            store.set_stamps(stamps);
+            store.copy_object_with(&src_bucket, &src_name, &dst_bucket, &dst_name, options)
+        }
+        Record::Rename(src_bucket, src_name, dst_bucket, dst_name, stamps) => {
+            store.set_stamps(stamps);
+            store.rename_object(&src_bucket, &src_name, &dst_bucket, &dst_name)
+        }
+        Record::SetTags(bucket, name, tags) => store.set_object_tags(&bucket, &name, tags),
+    };
+    // Stamps a failed change left unused don't carry over to the next one.
+    store.set_stamps(Vec::new());
+    result
+}
+
+fn put_options(
+    content_type: Option<String>,
+    user: HashMap<String, String>,
+    tags: Tags,
+) -> PutOptions {
+    let mut options = PutOptions::new();
+    if let Some(content_type) = &content_type {
+        options = options.content_type(content_type);
+    }
+    for (key, value) in &user {
+        options = options.metadata(key, value);
+    }
+    for (key, value) in &tags {
+        options = options.tag(key, value);
+    }
+    options
+}
+
+fn encode(writer: &mut Vec<u8>, record: &Record) -> io::Result<()> {
+    match record {
+        Record::CreateBucket(bucket) => {
+            writer.push(1);
+            write_string(writer, bucket)
+        }
+        Record::DeleteBucket(bucket, force) => {
+            writer.push(2);
+            write_string(writer, bucket)?;
+            writer.push(*force as u8);
+            Ok(())
+        }
+        Record::Put {
+            bucket,
+            name,
+            replace,
+            content_type,
+            user,
+            tags,
+            data,
+            stamps,
+        } => {
+            writer.push(12);
+            write_string(writer, bucket)?;
+            write_string(writer, name)?;
+            writer.push(*replace as u8);
+            write_metadata(writer, content_type, user)?;
+            write_pairs(writer, tags)?;
+            write_stamps(writer, stamps)?;
+            writer.extend_from_slice(&(data.len() as u64).to_be_bytes());
+            writer.extend_from_slice(data);
+            Ok(())
+        }
+        Record::RemoveObject(bucket, name, stamps) => {
+            writer.push(14);
+            write_string(writer, bucket)?;
+            write_string(writer, name)?;
+            write_stamps(writer, stamps)
+        }
+        Record::RemoveVersion(bucket, name, version_id) => {
+            writer.push(5);
+            write_string(writer, bucket)?;
+            write_string(writer, name)?;
+            write_string(writer, version_id)
+        }
+        Record::SetVersioning(bucket, enabled) => {
+            writer.push(6);
+            write_string(writer, bucket)?;
+            writer.push(*enabled as u8);
+            Ok(())
+        }
+        Record::Copy {
+            src_bucket,
+            src_name,
+            dst_bucket,
+            dst_name,
+            content_type,
+            user,
+            tags,
+            stamps,
+        } => {
+            writer.push(13);
+            for name in &[src_bucket, src_name, dst_bucket, dst_name] {
+                write_string(writer, name)?;
+            }
+            write_metadata(writer, content_type, user)?;
+            write_pairs(writer, tags)?;
+            write_stamps(writer, stamps)
+        }
+        Record::Rename(src_bucket, src_name, dst_bucket, dst_name, stamps) => {
+            writer.push(15);
+            for name in &[src_bucket, src_name, dst_bucket, dst_name] {
+                write_string(writer, name)?;
+            }
+            write_stamps(writer, stamps)
+        }
+        Record::SetTags(bucket, name, tags) => {
+            writer.push(11);
+            write_string(writer, bucket)?;
+            write_string(writer, name)?;
+            write_pairs(writer, tags)
+        }
+    }
+}
+
+fn write_metadata(
+    writer: &mut Vec<u8>,
+    content_type: &Option<String>,
+    user: &HashMap<String, String>,
+) -> io::Result<()> {
+    match content_type {
+        None => writer.push(0),
+        Some(content_type) => {
+            writer.push(1);
+            write_string(writer, content_type)?;
+        }
+    }
+    write_pairs(writer, user)
+}
+
+fn write_pairs(writer: &mut Vec<u8>, pairs: &HashMap<String, String>) -> io::Result<()> {
+    writer.extend_from_slice(&(pairs.len() as u32).to_be_bytes());
+    for (key, value) in pairs {
+        write_string(writer, key)?;
+        write_string(writer, value)?;
+    }
+    Ok(())
+}
+
+fn write_stamps(writer: &mut Vec<u8>, stamps: &[Stamp]) -> io::Result<()> {
+    writer.extend_from_slice(&(stamps.len() as u32).to_be_bytes());
+    for stamp in stamps {
+        write_string(writer, &stamp.version_id)?;
+        write_time(writer, stamp.time)?;
+    }
+    Ok(())
+}
+
+fn read_stamps<R: Read>(reader: &mut R) -> io::Result<Vec<Stamp>> {
+    let mut stamps = Vec::new();
+    for _ in 0..read_u32(reader)? {
+        let version_id = read_string(reader)?;
+        let time = read_time(reader)?;
+        stamps.push(Stamp { version_id, time });
+    }
+    Ok(stamps)
+}
+
+// Reads the metadata of a write or copy, and its tags when the record kind has them.
+fn read_metadata<R: Read>(
+    reader: &mut R,
+    tagged: bool,
+) -> io::Result<(Option<String>, HashMap<String, String>, Tags)> {
+    let content_type = match read_flag(reader)? {
+        false => None,
+        true => Some(read_string(reader)?),
+    };
+    let user = read_pairs(reader)?;
+    let tags = match tagged {
+        false => Tags::new(),
+        true => read_pairs(reader)?,
+    };
+    Ok((content_type, user, tags))
+}
+
+fn read_pairs<R: Read>(reader: &mut R) -> io::Result<HashMap<String, String>> {
+    let mut pairs = HashMap::<String, String>::new();
+    for _ in 0..read_u32(reader)? {
+        let key = read_string(reader)?;
+        pairs.insert(key, read_string(reader)?);
+    }
+    Ok(pairs)
+}
+
+fn decode<R: Read>(reader: &mut R) -> io::Result<Record> {
+    match read_u8(reader)? {
+        1 => Ok(Record::CreateBucket(read_string(reader)?)),
+        2 => Ok(Record::DeleteBucket(
+            read_string(reader)?,
+            read_flag(reader)?,
+        )),
+        kind @ 3 | kind @ 9 | kind @ 12 => {
+            let bucket = read_string(reader)?;
+            let name = read_string(reader)?;
+            let replace = read_flag(reader)?;
+            let (content_type, user, tags) = read_metadata(reader, kind != 3)?;
+            let stamps = match kind {
+                12 => read_stamps(reader)?,
+                _ => Vec::new(),
+            };
+            let length = read_u64(reader)?;
+            Ok(Record::Put {
+                bucket,
+                name,
+                replace,
+                content_type,
+                user,
+                tags,
+                data: read_bytes(reader, length)?,
+                stamps,
+            })
+        }
+        kind @ 4 | kind @ 14 => {
+            let (bucket, name) = (read_string(reader)?, read_string(reader)?);
+            let stamps = match kind {
+                14 => read_stamps(reader)?,
+                _ => Vec::new(),
+            };
+            Ok(Record::RemoveObject(bucket, name, stamps))
+        }
+        5 => Ok(Record::RemoveVersion(
+            read_string(reader)?,
+            read_string(reader)?,
+            read_string(reader)?,
+        )),
+        6 => Ok(Record::SetVersioning(
+            read_string(reader)?,
+            read_flag(reader)?,
+        )),
+        kind @ 7 | kind @ 10 | kind @ 13 => {
+            let (src_bucket, src_name) = (read_string(reader)?, read_string(reader)?);
+            let (dst_bucket, dst_name) = (read_string(reader)?, read_string(reader)?);
+            let (content_type, user, tags) = read_metadata(reader, kind != 7)?;
+            let stamps = match kind {
+                13 => read_stamps(reader)?,
+                _ => Vec::new(),
+            };
+            Ok(Record::Copy {
+                src_bucket,
+                src_name,
+                dst_bucket,
+                dst_name,
+                content_type,
+                user,
+                tags,
+                stamps,
+            })
+        }
+        kind @ 8 | kind @ 15 => {
+            let (src_bucket, src_name) = (read_string(reader)?, read_string(reader)?);
+            let (dst_bucket, dst_name) = (read_string(reader)?, read_string(reader)?);
+            let stamps = match kind {
+                15 => read_stamps(reader)?,
+                _ => Vec::new(),
+            };
+            Ok(Record::Rename(
+                src_bucket, src_name, dst_bucket, dst_name, stamps,
+            ))
+        }
+        11 => Ok(Record::SetTags(
+            read_string(reader)?,
+            read_string(reader)?,
+            read_pairs(reader)?,
+        )),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unknown record")),
+    }
+}
+
+// Applies every intact record of the log, returning how many there were and where they end.
+fn replay(store: &mut InMemoryStore, log: &mut File) -> io::Result<(u64, u64)> {
+    let mut reader = BufReader::new(log);
+    let (mut records, mut valid) = (0, 0);
+    loop {
+        let mut frame = [0u8; 4 + CHECKSUM_SIZE];
+        match reader.read_exact(&mut frame) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+            Ok(_) => {}
+        }
+        let length = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]);
+        let payload = match read_bytes(&mut reader, length as u64) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+            Ok(payload) => payload,
+        };
+        if Sha256::digest(&payload)[..CHECKSUM_SIZE] != frame[4..] {
+            if reader.fill_buf()?.is_empty() {
+                break;
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "damaged record in the middle of the log",
+            ));
+        }
+        let record = decode(&mut Cursor::new(payload))?;
+        // Changes that failed when they were made fail the same way again.
+        let _ = apply(store, record);
+        records += 1;
+        valid += (frame.len() + length as usize) as u64;
+    }
+    Ok((records, valid))
+}
+
+fn snapshot_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("snapshot-{}", generation))
+}
+
+fn log_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("log-{}", generation))
+}
+
+fn generation_of(path: &Path) -> Option<(&str, u64)> {
+    let name = path.file_name()?.to_str()?;
+    let (kind, generation) = name.split_at(name.rfind('-')?);
+    Some((kind, generation[1..].parse().ok()?))
+}
+
+fn latest_generation(dir: &Path) -> io::Result<Option<u64>> {
+    let mut latest = None;
+    for entry in fs::read_dir(dir)? {
+        if let Some(("snapshot", generation)) = generation_of(&entry?.path()) {
+            latest = latest.max(Some(generation));
+        }
+    }
+    Ok(latest)
+}
+
+fn remove_other_generations(dir: &Path, current: u64) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        match generation_of(&path) {
+            Some(("snapshot", generation)) | Some(("log", generation)) if generation != current => {
+                fs::remove_file(&path)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    match File::open(dir) {
+        Err(_) => Ok(()),
+        Ok(dir) => dir.sync_all().or(Ok(())),
+    }
+}
+
+impl Store for DurableStore {
+    fn list_buckets(&self) -> Vec<&str> {
+        self.store.list_buckets()
+    }
+
+    fn create_bucket(&mut self, name: &str) -> Result<()> {
+        bucket::validate_name(name)?;
+        if self.store.list_buckets().contains(&name) {
+            return Err(Error::new(ErrorKind::BucketAlreadyExists, name, ""));
+        }
+        self.log_and_apply(Record::CreateBucket(name.into()))
+    }
+
+    fn delete_bucket_with(&mut self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        let force = options.is_forced();
+        match self.store.list_object_versions(name) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, name, "")),
+            Some(versions) if !force && !versions.is_empty() => {
+                return Err(Error::new(ErrorKind::BucketNotEmpty, name, ""))
+            }
+            Some(_) => {}
+        }
+        self.log_and_apply(Record::DeleteBucket(name.into(), force))
+    }
+
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Result<ObjectList<'_>> {
+        self.store.list_objects_with(bucket, options)
+    }
+
+    fn insert_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.write(bucket, name, reader, options, false)
+    }
+
+    fn put_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.write(bucket, name, reader, options, true)
+    }
+
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        self.store.head_object(bucket, name)
+    }
+
+    fn head_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Option<ObjectMetadata> {
+        self.store.head_object_version(bucket, name, version_id)
+    }
+
+    fn set_object_tags(&mut self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        tag::validate(bucket, name, &tags)?;
+        self.require_bucket(bucket, name)?;
+        if self.store.head_object(bucket, name).is_none() {
+            return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name));
+        }
+        self.log_and_apply(Record::SetTags(bucket.into(), name.into(), tags))
+    }
+
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<&str>> {
+        self.store.find_objects(bucket, query)
+    }
+
+    fn get_object_with<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<Box<dyn Read + 'a>> {
+        self.store.get_object_with(bucket, name, options)
+    }
+
+    fn get_object_range<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + 'a>> {
+        self.store.get_object_range(bucket, name, range)
+    }
+
+    // Copies are logged by name, not with their data.
+    fn copy_object_with(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+        options: CopyOptions,
+    ) -> Result<()> {
+        let source = self.source(src_bucket, src_name, dst_bucket, dst_name)?;
+        let options = options.into_put_options(&source);
+        tag::validate(dst_bucket, dst_name, options.tags())?;
+        let metadata = options.into_metadata(None);
+        self.log_and_apply(Record::Copy {
+            src_bucket: src_bucket.into(),
+            src_name: src_name.into(),
+            dst_bucket: dst_bucket.into(),
+            dst_name: dst_name.into(),
+            content_type: metadata.content_type,
+            user: metadata.user,
+            tags: metadata.tags,
+            stamps: vec![Stamp::new()],
+        })
+    }
+
+    fn rename_object(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        self.source(src_bucket, src_name, dst_bucket, dst_name)?;
+        // The copy to the destination and the removal of the source each take a stamp.
+        self.log_and_apply(Record::Rename(
+            src_bucket.into(),
+            src_name.into(),
+            dst_bucket.into(),
+            dst_name.into(),
+            vec![Stamp::new(), Stamp::new()],
+        ))
+    }
+
+    fn remove_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        self.require_bucket(bucket, name)?;
+        options.preconditions().check(
+            bucket,
+            name,
+            self.store.head_object(bucket, name).as_ref(),
+        )?;
+        let record = Record::RemoveObject(bucket.into(), name.into(), vec![Stamp::new()]);
+        self.log_and_apply(record)
+    }
+
+    fn remove_object_version(&mut self, bucket: &str, name: &str, version_id: &str) -> Result<()> {
+        self.require_bucket(bucket, name)?;
+        let record = Record::RemoveVersion(bucket.into(), name.into(), version_id.into());
+        self.log_and_apply(record)
+    }
+
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        self.store.list_object_versions(bucket)
+    }
+
+    fn create_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        self.store.create_multipart_upload(bucket, name, options)
+    }
+
+    fn upload_part(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String> {
+        self.store
+            .upload_part(bucket, name, upload_id, part_number, reader)
+    }
+
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        self.store.list_multipart_uploads(bucket)
+    }
+
+    fn abort_multipart_upload(&mut self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        self.store.abort_multipart_upload(bucket, name, upload_id)
+    }
+
+    // Logged as a write of the assembled object before the upload is completed.
+    fn complete_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        let upload = self.store.upload(bucket, name, upload_id)?;
+        let options = upload.options.clone();
+        let mut data = Vec::new();
+        for part in upload.parts.values() {
+            if let Err(e) = io::copy(&mut ObjectReader::from(part), &mut data) {
+                return Err(Error::io(e, bucket, name));
+            }
+        }
+        tag::validate(bucket, name, options.tags())?;
+        self.require_bucket(bucket, name)?;
+        options.preconditions().check(
+            bucket,
+            name,
+            self.store.head_object(bucket, name).as_ref(),
+        )?;
+        if !options.verify(&checksum::digest(&data)) {
+            return Err(Error::new(ErrorKind::ChecksumMismatch, bucket, name));
+        }
+        let stamp = Stamp::new();
+        let metadata = options.into_metadata(None);
+        self.log(&Record::Put {
+            bucket: bucket.into(),
+            name: name.into(),
+            replace: true,
+            content_type: metadata.content_type,
+            user: metadata.user,
+            tags: metadata.tags,
+            data,
+            stamps: vec![stamp.clone()],
+        })?;
+        self.store.set_stamps(vec![stamp]);
+        let result = self
+            .store
+            .complete_multipart_upload(bucket, name, upload_id);
+        self.store.set_stamps(Vec::new());
+        self.compact_if_due();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::{get_data_reader, store_tests};
+    use rand::distributions::Alphanumeric;
+    use rand::{thread_rng, Rng};
+    use std::env;
+
+    fn temp_dir() -> PathBuf {
+        let suffix: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        env::temp_dir().join(format!("blob-store-durable-{}", suffix))
+    }
+
+    fn with_store(test: fn(DurableStore)) {
+        let dir = temp_dir();
+        match DurableStore::open(&dir) {
+            Err(err) => panic!("DurableStore failed to open: {}", err),
+            Ok(store) => test(store.compact_after(4)),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    store_tests!(with_store);
+
+    fn read_all(store: &DurableStore, bucket: &str, name: &str) -> Vec<u8> {
+        let mut output = Vec::new();
+        if let Some(mut reader) = store.get_object(bucket, name) {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        output
+    }
+
+    fn reopen(dir: &Path) -> DurableStore {
+        match DurableStore::open(dir) {
+            Err(err) => panic!("DurableStore failed to reopen: {}", err),
+            Ok(store) => store,
+        }
+    }
+
+    #[test]
+    fn durable_store_should_replay_log_on_open() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir);
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("mars", "Phobos", &mut get_data_reader(b"Fear"));
+        let _ = store.remove_object("mars", "Phobos");
+        let _ = store.delete_bucket("mars");
+        drop(store);
+
+        let store = reopen(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            read_all(&store, "earth", "Moon"),
+            b"Luna",
+            "DurableStore lost a write."
+        );
+        assert_eq!(
+            store.list_buckets(),
+            vec!["earth"],
+            "DurableStore lost a removal."
+        );
+    }
+
+    #[test]
+    fn durable_store_should_replay_version_ids_and_times() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir);
+        let _ = store.create_bucket("earth");
+        let _ = store.set_versioning("earth", true);
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let _ = store.copy_object("earth", "Moon", "earth", "Selene");
+        let _ = store.rename_object("earth", "Selene", "earth", "Phoebe");
+        let _ = store.remove_object("earth", "Moon");
+        let versions = store.list_object_versions("earth").unwrap_or_default();
+        let metadata = store.head_object("earth", "Phoebe");
+        drop(store);
+
+        let mut store = reopen(&dir);
+        assert_eq!(
+            store.list_object_versions("earth").unwrap_or_default(),
+            versions,
+            "DurableStore changed versions when replaying."
+        );
+        assert_eq!(store.head_object("earth", "Phoebe"), metadata);
+        let oldest = versions
+            .iter()
+            .rev()
+            .find(|v| v.name == "Moon")
+            .map(|v| v.version_id.clone())
+            .unwrap_or_default();
+        assert!(store
+            .remove_object_version("earth", "Moon", &oldest)
+            .is_ok());
+        drop(store);
+
+        let store = reopen(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        let removed = store
+            .list_object_versions("earth")
+            .unwrap_or_default()
+            .into_iter()
+            .any(|v| v.version_id == oldest);
+        assert!(!removed, "DurableStore restored a removed version.");
+    }
+
+    #[test]
+    fn durable_store_should_replay_completed_multipart_upload() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir);
+        let _ = store.create_bucket("earth");
+        let _ = store.set_versioning("earth", true);
+        let options = PutOptions::new().content_type("text/plain");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", options)
+            .unwrap_or_default();
+        let _ = store.upload_part("earth", "Moon", &upload_id, 2, &mut get_data_reader(b"na"));
+        let _ = store.upload_part("earth", "Moon", &upload_id, 1, &mut get_data_reader(b"Lu"));
+        assert!(store
+            .complete_multipart_upload("earth", "Moon", &upload_id)
+            .is_ok());
+        let metadata = store.head_object("earth", "Moon");
+        drop(store);
+
+        let store = reopen(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(read_all(&store, "earth", "Moon"), b"Luna");
+        assert_eq!(
+            store.head_object("earth", "Moon"),
+            metadata,
+            "DurableStore replayed a multipart upload differently."
+        );
+    }
+
+    #[test]
+    fn durable_store_should_replay_copies_and_renames() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir);
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let options = CopyOptions::new().replace_metadata(PutOptions::new().content_type("a/b"));
+        let _ = store.copy_object_with("earth", "Moon", "mars", "Phobos", options);
+        let _ = store.rename_object("earth", "Moon", "mars", "Deimos");
+        drop(store);
+
+        let store = reopen(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(store.list_objects("earth"), Some(vec![]));
+        assert_eq!(read_all(&store, "mars", "Deimos"), b"Luna");
+        assert_eq!(
+            store
+                .head_object("mars", "Phobos")
+                .and_then(|m| m.content_type),
+            Some("a/b".into()),
+            "DurableStore lost a copy."
+        );
+    }
+
+    #[test]
+    fn durable_store_should_replay_tags() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir);
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().tag("kind", "moon");
+        let _ = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        let _ = store.copy_object("earth", "Moon", "earth", "Selene");
+        let mut tags = Tags::new();
+        tags.insert("kind".into(), "goddess".into());
+        let _ = store.set_object_tags("earth", "Selene", tags);
+        drop(store);
+
+        let store = reopen(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            store.find_objects("earth", &TagQuery::equals("kind", "moon")),
+            Some(vec!["Moon"])
+        );
+        assert_eq!(
+            store.find_objects("earth", &TagQuery::equals("kind", "goddess")),
+            Some(vec!["Selene"]),
+            "DurableStore lost a change of tags."
+        );
+    }
+
+    #[test]
+    fn durable_store_should_compact_log_into_snapshot() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir).compact_after(3);
+        let _ = store.create_bucket("earth");
+        let _ = store.set_versioning("earth", true);
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let versions = store.list_object_versions("earth");
+        drop(store);
+
+        assert!(
+            snapshot_path(&dir, 1).exists(),
+            "DurableStore did not compact."
+        );
+        assert!(
+            !log_path(&dir, 0).exists(),
+            "DurableStore kept compacted log."
+        );
+        let store = reopen(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(read_all(&store, "earth", "Moon"), b"Luna");
+        assert_eq!(
+            store.list_object_versions("earth"),
+            versions,
+            "DurableStore changed versions when compacting."
+        );
+    }
+
+    #[test]
+    fn durable_store_should_drop_torn_record_at_end_of_log() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir);
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        drop(store);
+        let torn = OpenOptions::new().append(true).open(log_path(&dir, 0));
+        let _ = torn.and_then(|mut log| log.write_all(&[0, 0, 1, 0, 7]));
+
+        let mut store = reopen(&dir);
+        let _ = store.put_object("earth", "Io", &mut get_data_reader(b"Io"));
+        drop(store);
+        let store = reopen(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(read_all(&store, "earth", "Moon"), b"Luna");
+        assert_eq!(
+            read_all(&store, "earth", "Io"),
+            b"Io",
+            "DurableStore lost a write made after a torn record."
+        );
+    }
+
+    #[test]
+    fn durable_store_should_fail_to_open_log_damaged_before_its_end() {
+        let dir = temp_dir();
+        let mut store = reopen(&dir);
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        drop(store);
+        let damaged = OpenOptions::new().write(true).open(log_path(&dir, 0));
+        let _ = damaged.and_then(|mut log| {
+            log.seek(SeekFrom::Start(4 + CHECKSUM_SIZE as u64))?;
+            log.write_all(&[0xff])
+        });
+
+        let opened = DurableStore::open(&dir);
+        let length = fs::metadata(log_path(&dir, 0)).map(|m| m.len()).ok();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            opened.err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidData),
+            "DurableStore did not fail to open a log damaged before its end."
+        );
+        assert!(
+            length.is_some_and(|length| length > 0),
+            "DurableStore truncated a log damaged before its end."
+        );
+    }
+}
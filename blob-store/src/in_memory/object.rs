@@ -1,27 +1,143 @@
+use crate::checksum;
+use crate::compression::{Compression, RangeDecoder};
+use crate::metadata::ObjectMetadata;
 use std::io::{Read, Write};
+use std::ops::Range;
+use std::sync::{Arc, Weak};
 use std::{cmp, io};
 
+// The data is shared between clones of an object and its snapshot readers, so reading an object
+// doesn't have to borrow the bucket it lives in. The metadata keeps the original length when the
+// data is compressed.
+#[derive(Clone)]
 pub struct Object {
-    data: Vec<u8>,
+    data: Arc<Vec<u8>>,
+    metadata: ObjectMetadata,
+    compression: Compression,
 }
 
 impl Object {
     pub fn new(size: usize) -> Self {
         Object {
-            data: Vec::with_capacity(size),
+            data: Arc::new(Vec::with_capacity(size)),
+            metadata: ObjectMetadata::new(),
+            compression: Compression::None,
+        }
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    // Stores the data with the given compression once it has all been written. Data compressed
+    // another way is decompressed first.
+    pub fn compress(&mut self, compression: Compression) -> io::Result<()> {
+        if compression == self.compression {
+            return Ok(());
+        }
+        let data = match self.compression {
+            Compression::None => compression.compress(&self.data)?,
+            stored => {
+                let mut data = Vec::with_capacity(self.metadata.length as usize);
+                stored.decoder(&self.data[..])?.read_to_end(&mut data)?;
+                compression.compress(&data)?
+            }
+        };
+        self.data = Arc::new(data);
+        self.compression = compression;
+        Ok(())
+    }
+
+    pub fn stored_length(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    // Rebuilds an object from its data as stored, compressed or not.
+    pub fn from_stored(data: Vec<u8>, metadata: ObjectMetadata, compression: Compression) -> Self {
+        Object {
+            data: Arc::new(data),
+            metadata,
+            compression,
+        }
+    }
+
+    pub fn stored_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn metadata(&self) -> &ObjectMetadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut ObjectMetadata {
+        &mut self.metadata
+    }
+
+    // Makes the object use the given data, which must be identical to its own.
+    pub fn share(&mut self, data: Arc<Vec<u8>>) {
+        self.data = data;
+    }
+
+    pub fn downgrade(&self) -> Weak<Vec<u8>> {
+        Arc::downgrade(&self.data)
+    }
+
+    // Returns the data as stored, compressed when the object is.
+    pub fn into_data(self) -> Vec<u8> {
+        Arc::try_unwrap(self.data).unwrap_or_else(|data| (*data).clone())
+    }
+
+    pub fn snapshot(&self) -> SnapshotReader {
+        self.snapshot_range(0..self.metadata.length)
+    }
+
+    pub fn snapshot_range(&self, range: Range<u64>) -> SnapshotReader {
+        if self.compression == Compression::None {
+            return SnapshotReader {
+                read: range.start as usize,
+                end: range.end as usize,
+                data: self.data.clone(),
+                decoder: None,
+            };
+        }
+        let stored = SnapshotReader {
+            read: 0,
+            end: self.data.len(),
+            data: self.data.clone(),
+            decoder: None,
+        };
+        SnapshotReader {
+            read: 0,
+            end: 0,
+            data: self.data.clone(),
+            decoder: Some(RangeDecoder::new(
+                self.compression,
+                stored,
+                range.start,
+                range.end,
+            )),
         }
     }
 }
 
 impl<T: Into<Vec<u8>>> From<T> for Object {
     fn from(data: T) -> Self {
-        Object { data: data.into() }
+        let data = data.into();
+        let mut metadata = ObjectMetadata::new();
+        metadata.length = data.len() as u64;
+        metadata.etag = checksum::digest(&data);
+        Object {
+            data: Arc::new(data),
+            metadata,
+            compression: Compression::None,
+        }
     }
 }
 
 impl Write for Object {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.data.extend_from_slice(buf);
+        Arc::make_mut(&mut self.data).extend_from_slice(buf);
+        self.metadata.length = self.data.len() as u64;
         Ok(buf.len())
     }
 
@@ -30,37 +146,56 @@ impl Write for Object {
     }
 }
 
+// Reads the original data of an object, decompressing it on the way when it is compressed.
 pub struct ObjectReader<'a> {
     read: usize,
     data: &'a [u8],
+    decoder: Option<RangeDecoder<'a>>,
 }
 
 impl<'a> ObjectReader<'a> {
     pub fn new(object: &'a Object) -> Self {
+        Self::with_range(object, 0..object.metadata.length)
+    }
+}
+
+impl<'a> ObjectReader<'a> {
+    // Reads only the given part of the object, without copying it.
+    pub fn with_range(object: &'a Object, range: Range<u64>) -> Self {
+        if object.compression == Compression::None {
+            return ObjectReader::from(&object.data[range.start as usize..range.end as usize]);
+        }
+        let decoder =
+            RangeDecoder::new(object.compression, &object.data[..], range.start, range.end);
         ObjectReader {
             read: 0,
-            data: &object.data[..],
+            data: &[],
+            decoder: Some(decoder),
         }
     }
 }
 
 impl<'a> From<&'a [u8]> for ObjectReader<'a> {
     fn from(data: &'a [u8]) -> Self {
-        ObjectReader { read: 0, data }
+        ObjectReader {
+            read: 0,
+            data,
+            decoder: None,
+        }
     }
 }
 
 impl<'a> From<&'a Object> for ObjectReader<'a> {
     fn from(object: &'a Object) -> Self {
-        ObjectReader {
-            read: 0,
-            data: &object.data[..],
-        }
+        ObjectReader::new(object)
     }
 }
 
 impl<'a> Read for ObjectReader<'a> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(decoder) = self.decoder.as_mut() {
+            return decoder.read(buf);
+        }
         if self.data.len() <= self.read {
             return Ok(0);
         }
@@ -76,6 +211,27 @@ impl<'a> Read for ObjectReader<'a> {
     }
 }
 
+// Reads an object from its own reference to the data, which stays valid when the object is
+// replaced or removed.
+pub struct SnapshotReader {
+    read: usize,
+    end: usize,
+    data: Arc<Vec<u8>>,
+    decoder: Option<RangeDecoder<'static>>,
+}
+
+impl Read for SnapshotReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(decoder) = self.decoder.as_mut() {
+            return decoder.read(buf);
+        }
+        let mut data = ObjectReader::from(&self.data[self.read.min(self.end)..self.end]);
+        let n = data.read(buf)?;
+        self.read += n;
+        Ok(n)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -83,7 +239,7 @@ pub mod tests {
 
     pub fn generate_random_byte_array(size: usize) -> Vec<u8> {
         let mut rng = thread_rng();
-        let mut r = vec![0 as u8; size];
+        let mut r = vec![0u8; size];
         r.as_mut_slice().try_fill(&mut rng).unwrap_or_default();
         r
     }
@@ -99,11 +255,25 @@ pub mod tests {
         let mut dst = Object::new(size);
         let _ = io::copy(&mut src.as_slice(), &mut dst).unwrap_or_default();
         assert_eq!(
-            &src, &dst.data,
+            &src,
+            dst.data.as_ref(),
             "Write to Object failed to produce identical data."
         );
     }
 
+    #[test]
+    fn object_write_should_update_metadata_length() {
+        let size: usize = 32 * 1024;
+        let src = generate_random_byte_array(size);
+        let mut dst = Object::new(size);
+        let _ = io::copy(&mut src.as_slice(), &mut dst).unwrap_or_default();
+        assert_eq!(
+            size as u64,
+            dst.metadata().length,
+            "Write to Object failed to update metadata length."
+        );
+    }
+
     #[test]
     fn object_reader_read_should_output_all_data() {
         let size: usize = 32 * 1024;
@@ -118,6 +288,19 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn object_reader_with_range_should_output_part_of_data() {
+        let src = generate_random_object(1024);
+        let mut reader = ObjectReader::with_range(&src, 100..200);
+        let mut dst = Vec::new();
+        let _ = io::copy(&mut reader, &mut dst).unwrap_or_default();
+        assert_eq!(
+            &src.data[100..200],
+            &dst[..],
+            "ObjectReader failed to output the requested range."
+        );
+    }
+
     #[test]
     fn object_reader_read_should_output_identical_data() {
         let size: usize = 32 * 1024;
@@ -126,8 +309,69 @@ pub mod tests {
         let mut dst = Vec::with_capacity(size);
         let _ = io::copy(&mut reader, &mut dst).unwrap_or_default();
         assert_eq!(
-            &src.data, &dst,
+            src.data.as_ref(),
+            &dst,
             "Read for ObjectReader failed to produce identical data."
         );
     }
+
+    #[test]
+    fn snapshot_reader_should_outlive_object() {
+        let src = generate_random_object(1024);
+        let expected = src.data.as_ref().clone();
+        let mut reader = src.snapshot();
+        drop(src);
+        let mut dst = Vec::new();
+        let _ = io::copy(&mut reader, &mut dst).unwrap_or_default();
+        assert_eq!(
+            expected, dst,
+            "SnapshotReader failed to read data of dropped object."
+        );
+    }
+
+    #[test]
+    fn snapshot_range_should_output_part_of_data() {
+        let src = generate_random_object(1024);
+        let mut reader = src.snapshot_range(100..200);
+        let mut dst = Vec::new();
+        let _ = io::copy(&mut reader, &mut dst).unwrap_or_default();
+        assert_eq!(
+            &src.data[100..200],
+            &dst[..],
+            "SnapshotReader failed to output the requested range."
+        );
+    }
+
+    #[test]
+    fn compressed_object_readers_should_output_original_data() {
+        let text = "Mercury Venus Earth Mars ".repeat(100);
+        let mut src = Object::from(text.as_str());
+        let _ = src.compress(Compression::Lz4);
+        assert!(src.stored_length() < src.metadata().length);
+        let mut dst = Vec::new();
+        let _ = io::copy(&mut ObjectReader::new(&src), &mut dst).unwrap_or_default();
+        assert_eq!(
+            dst,
+            text.as_bytes(),
+            "ObjectReader failed to decompress data."
+        );
+        let mut dst = Vec::new();
+        let _ = io::copy(&mut src.snapshot_range(8..13), &mut dst).unwrap_or_default();
+        assert_eq!(dst, b"Venus", "SnapshotReader failed to decompress range.");
+    }
+
+    #[test]
+    fn compress_should_recompress_data_compressed_another_way() {
+        let text = "Mercury Venus Earth Mars ".repeat(100);
+        let mut src = Object::from(text.as_str());
+        let _ = src.compress(Compression::Gzip);
+        let _ = src.compress(Compression::Zstd);
+        assert_eq!(src.compression(), Compression::Zstd);
+        let _ = src.compress(Compression::None);
+        assert_eq!(
+            src.stored_data(),
+            text.as_bytes(),
+            "Object failed to decompress data."
+        );
+    }
 }
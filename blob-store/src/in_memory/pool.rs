@@ -0,0 +1,112 @@
+use crate::compression::Compression;
+use crate::in_memory::object::Object;
+use std::collections::HashMap;
+use std::sync::Weak;
+
+// Keeps a single copy of every distinct object data, keyed by its SHA-256 digest and how it is
+// compressed, since the same data compressed differently can't be shared. The pool only
+// holds weak references, the versions in the buckets and the open readers are what keeps data
+// alive, so it is freed as soon as the last of them is dropped.
+#[derive(Default)]
+pub struct DataPool {
+    blobs: HashMap<(String, Compression), Weak<Vec<u8>>>,
+}
+
+// Bytes as written to the buckets against bytes actually held once identical data is shared.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DedupUsage {
+    pub logical_bytes: u64,
+    pub physical_bytes: u64,
+    pub blobs: u64,
+}
+
+impl DataPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Points the object at the stored copy of its data, or stores its data when there is no
+    // copy yet. The object's ETag must be the digest of its data. Returns whether it was shared.
+    pub fn intern(&mut self, obj: &mut Object) -> bool {
+        let key = (obj.metadata().etag.clone(), obj.compression());
+        match self.blobs.get(&key).and_then(Weak::upgrade) {
+            Some(data) if data.len() as u64 == obj.stored_length() => {
+                obj.share(data);
+                true
+            }
+            _ => {
+                self.blobs.insert(key, obj.downgrade());
+                false
+            }
+        }
+    }
+
+    // How many objects and readers use the data with the given digest.
+    pub fn references(&self, digest: &str) -> usize {
+        self.blobs
+            .iter()
+            .filter(|((etag, _), _)| etag == digest)
+            .map(|(_, data)| data.strong_count())
+            .sum()
+    }
+
+    // Forgets the data that has already been freed.
+    pub fn prune(&mut self) {
+        self.blobs.retain(|_, data| data.strong_count() > 0);
+    }
+
+    pub fn usage(&self, logical_bytes: u64) -> DedupUsage {
+        let live = self.blobs.values().filter_map(Weak::upgrade);
+        let (physical_bytes, blobs) = live.fold((0, 0), |(bytes, blobs), data| {
+            (bytes + data.len() as u64, blobs + 1)
+        });
+        DedupUsage {
+            logical_bytes,
+            physical_bytes,
+            blobs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_intern_should_share_identical_data() {
+        let mut pool = DataPool::new();
+        let mut first = Object::from("Luna");
+        let mut second = Object::from("Luna");
+        assert!(!pool.intern(&mut first));
+        assert!(
+            pool.intern(&mut second),
+            "DataPool did not share identical data."
+        );
+        let digest = first.metadata().etag.clone();
+        assert_eq!(pool.references(&digest), 2);
+        assert_eq!(pool.usage(8).physical_bytes, 4);
+    }
+
+    #[test]
+    fn pool_should_free_data_with_last_reference() {
+        let mut pool = DataPool::new();
+        let mut first = Object::from("Luna");
+        let mut second = Object::from("Luna");
+        pool.intern(&mut first);
+        pool.intern(&mut second);
+        let digest = first.metadata().etag.clone();
+        drop(first);
+        assert_eq!(
+            pool.references(&digest),
+            1,
+            "DataPool freed data still in use."
+        );
+        drop(second);
+        pool.prune();
+        assert_eq!(
+            pool.usage(0),
+            DedupUsage::default(),
+            "DataPool kept freed data."
+        );
+    }
+}
@@ -0,0 +1,634 @@
+use crate::bucket;
+use crate::checksum::{ChecksumReader, VerifyingReader};
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::in_memory::bucket::Bucket;
+use crate::in_memory::object::Object;
+use crate::lifecycle::Rule;
+use crate::list::{ListOptions, OwnedObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart;
+use crate::multipart::{MultipartUpload, PartsReader, Upload};
+use crate::range::ByteRange;
+use crate::shared::{SharedReader, SharedStore};
+use crate::store::{DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions};
+use crate::tag;
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+// An in-memory store with a lock per bucket. Reads share the bucket lock and only hold it long
+// enough to take a snapshot of the object, writes only hold it to swap the finished object in.
+pub struct SharedInMemoryStore {
+    buckets: RwLock<HashMap<String, Arc<RwLock<Bucket>>>>,
+    uploads: Mutex<HashMap<String, Upload<Object>>>,
+}
+
+impl Default for SharedInMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedInMemoryStore {
+    pub fn new() -> Self {
+        SharedInMemoryStore {
+            buckets: RwLock::new(HashMap::<String, Arc<RwLock<Bucket>>>::new()),
+            uploads: Mutex::new(HashMap::<String, Upload<Object>>::new()),
+        }
+    }
+
+    pub fn set_versioning(&self, bucket: &str, enabled: bool) -> Result<()> {
+        match self.bucket(bucket) {
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(container) => {
+                write(&container).set_versioning(enabled);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_lifecycle(&self, bucket: &str, rules: Vec<Rule>) -> Result<()> {
+        match self.bucket(bucket) {
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(container) => {
+                write(&container).set_lifecycle(rules);
+                Ok(())
+            }
+        }
+    }
+
+    // Drops every expired version, taking one bucket lock at a time.
+    pub fn expire_objects(&self) -> usize {
+        let now = SystemTime::now();
+        let buckets = read(&self.buckets)
+            .values()
+            .cloned()
+            .collect::<Vec<Arc<RwLock<Bucket>>>>();
+        buckets
+            .iter()
+            .map(|container| write(container).expire(now))
+            .sum()
+    }
+
+    // Sweeps expired objects in the background every interval. The sweeper only keeps a weak
+    // reference, so it stops once the store is dropped.
+    pub fn start_sweeper(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let store: Weak<Self> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match store.upgrade() {
+                None => break,
+                Some(store) => store.expire_objects(),
+            };
+        })
+    }
+
+    fn bucket(&self, name: &str) -> Option<Arc<RwLock<Bucket>>> {
+        read(&self.buckets).get(name).cloned()
+    }
+
+    pub fn insert_or_replace_object(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+        replace: bool,
+    ) -> Result<()> {
+        tag::validate(bucket, name, options.tags())?;
+        let container = match self.bucket(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        if !replace && read(&container).exist(name) {
+            return Err(Error::new(ErrorKind::ObjectAlreadyExist, bucket, name));
+        }
+        options
+            .preconditions()
+            .check(bucket, name, read(&container).head(name))?;
+        // The data is read before taking the lock so a slow writer doesn't hold up the bucket.
+        let mut reader = ChecksumReader::new(reader);
+        let mut obj = Object::new(0);
+        if let Err(e) = io::copy(&mut reader, &mut obj) {
+            return Err(Error::io(e, bucket, name));
+        }
+        let etag = reader.finish();
+        if !options.verify(&etag) {
+            return Err(Error::new(ErrorKind::ChecksumMismatch, bucket, name));
+        }
+        // The map stays locked while the object goes in, so the bucket can't be deleted in between
+        // and the object left in a container no one can reach.
+        let buckets = read(&self.buckets);
+        let current = buckets.get(bucket);
+        if !current.is_some_and(|current| Arc::ptr_eq(current, &container)) {
+            return Err(Error::new(ErrorKind::BucketNotFound, bucket, name));
+        }
+        let mut container = write(&container);
+        if !replace && container.exist(name) {
+            return Err(Error::new(ErrorKind::ObjectAlreadyExist, bucket, name));
+        }
+        container.expire_object(name, SystemTime::now());
+        // Checked again under the lock, another writer may have replaced the object meanwhile.
+        options
+            .preconditions()
+            .check(bucket, name, container.head(name))?;
+        let mut metadata = options.into_metadata(container.head(name));
+        metadata.length = obj.metadata().length;
+        metadata.etag = etag;
+        *obj.metadata_mut() = metadata;
+        container.put(name, obj);
+        Ok(())
+    }
+}
+
+impl SharedStore for SharedInMemoryStore {
+    fn list_buckets(&self) -> Vec<String> {
+        read(&self.buckets).keys().cloned().collect::<Vec<String>>()
+    }
+
+    fn create_bucket(&self, name: &str) -> Result<()> {
+        bucket::validate_name(name)?;
+        let mut buckets = write(&self.buckets);
+        if buckets.contains_key(name) {
+            return Err(Error::new(ErrorKind::BucketAlreadyExists, name, ""));
+        }
+        buckets.insert(name.into(), Arc::new(RwLock::new(Bucket::new())));
+        Ok(())
+    }
+
+    fn delete_bucket_with(&self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        let mut buckets = write(&self.buckets);
+        let container = match buckets.get(name) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, name, "")),
+            Some(bucket) => bucket,
+        };
+        let mut container = write(container);
+        container.expire(SystemTime::now());
+        if !options.is_forced() && !container.is_empty() {
+            return Err(Error::new(ErrorKind::BucketNotEmpty, name, ""));
+        }
+        drop(container);
+        buckets.remove(name);
+        drop(buckets);
+        lock(&self.uploads).retain(|_, upload| upload.bucket != name);
+        Ok(())
+    }
+
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Option<OwnedObjectList> {
+        let container = self.bucket(bucket)?;
+        let container = read(&container);
+        Some(container.list(&options).into())
+    }
+
+    fn insert_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.insert_or_replace_object(bucket, name, reader, options, false)
+    }
+
+    fn put_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.insert_or_replace_object(bucket, name, reader, options, true)
+    }
+
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        let container = self.bucket(bucket)?;
+        let metadata = read(&container).head(name).cloned();
+        metadata
+    }
+
+    fn set_object_tags(&self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        tag::validate(bucket, name, &tags)?;
+        let container = match self.bucket(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        let found = write(&container).set_tags(name, tags);
+        match found {
+            false => Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            true => Ok(()),
+        }
+    }
+
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<String>> {
+        let container = self.bucket(bucket)?;
+        let container = read(&container);
+        let names = container.find(query);
+        Some(names.into_iter().map(String::from).collect())
+    }
+
+    fn get_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<SharedReader> {
+        let container = match self.bucket(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        let container = read(&container);
+        let found = match options.version() {
+            None => container.latest(name),
+            Some(version_id) => match container.version(name, version_id) {
+                None => return Err(Error::new(ErrorKind::VersionNotFound, bucket, name)),
+                found => found,
+            },
+        };
+        let obj = match found {
+            None => return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            Some(obj) => obj,
+        };
+        options
+            .preconditions()
+            .check(bucket, name, Some(obj.metadata()))?;
+        if options.verifies_checksum() {
+            let etag = &obj.metadata().etag;
+            Ok(Box::new(VerifyingReader::new(obj.snapshot(), etag)))
+        } else {
+            Ok(Box::new(obj.snapshot()))
+        }
+    }
+
+    fn get_object_range(&self, bucket: &str, name: &str, range: ByteRange) -> Result<SharedReader> {
+        let container = match self.bucket(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        let container = read(&container);
+        let obj = match container.latest(name) {
+            None => return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            Some(obj) => obj,
+        };
+        match range.resolve(obj.metadata().length) {
+            None => Err(Error::new(ErrorKind::InvalidRange, bucket, name)),
+            Some(range) => Ok(Box::new(obj.snapshot_range(range))),
+        }
+    }
+
+    fn remove_object_with(&self, bucket: &str, name: &str, options: RemoveOptions) -> Result<()> {
+        let container = match self.bucket(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        let mut container = write(&container);
+        options
+            .preconditions()
+            .check(bucket, name, container.head(name))?;
+        container.remove(name);
+        Ok(())
+    }
+
+    fn remove_object_version(&self, bucket: &str, name: &str, version_id: &str) -> Result<()> {
+        let container = match self.bucket(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        let removed = write(&container).remove_version(name, version_id);
+        if removed {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::VersionNotFound, bucket, name))
+        }
+    }
+
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        let container = self.bucket(bucket)?;
+        let versions = read(&container).versions();
+        Some(versions)
+    }
+
+    fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        if self.bucket(bucket).is_none() {
+            return Err(Error::new(ErrorKind::BucketNotFound, bucket, name));
+        }
+        let upload_id = multipart::new_upload_id();
+        lock(&self.uploads).insert(upload_id.clone(), Upload::new(bucket, name, options));
+        Ok(upload_id)
+    }
+
+    fn upload_part(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String> {
+        match lock(&self.uploads).get(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) => {}
+            _ => return Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        }
+        if !multipart::is_valid_part_number(part_number) {
+            return Err(Error::new(ErrorKind::InvalidPart, bucket, name));
+        }
+        let mut reader = ChecksumReader::new(reader);
+        let mut part = Object::new(0);
+        if let Err(e) = io::copy(&mut reader, &mut part) {
+            return Err(Error::io(e, bucket, name));
+        }
+        let etag = reader.finish();
+        part.metadata_mut().etag = etag.clone();
+        // The upload may have been aborted or completed while the part was read.
+        match lock(&self.uploads).get_mut(upload_id) {
+            Some(upload) => {
+                upload.parts.insert(part_number, part);
+                Ok(etag)
+            }
+            None => Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        }
+    }
+
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        self.bucket(bucket)?;
+        Some(multipart::list(lock(&self.uploads).iter(), bucket))
+    }
+
+    fn abort_multipart_upload(&self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        let mut uploads = lock(&self.uploads);
+        match uploads.get(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) => {
+                uploads.remove(upload_id);
+                Ok(())
+            }
+            _ => Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        }
+    }
+
+    fn complete_multipart_upload(&self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        let (options, parts) = {
+            let uploads = lock(&self.uploads);
+            match uploads.get(upload_id) {
+                Some(upload) if upload.is_for(bucket, name) && upload.parts.is_empty() => {
+                    return Err(Error::new(ErrorKind::InvalidPart, bucket, name))
+                }
+                Some(upload) if upload.is_for(bucket, name) => {
+                    let parts = upload.parts.values().map(|part| part.snapshot());
+                    (upload.options.clone(), parts.collect::<Vec<_>>())
+                }
+                _ => return Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+            }
+        };
+        let mut reader = Box::new(PartsReader::new(parts)) as Box<dyn Read>;
+        self.insert_or_replace_object(bucket, name, &mut reader, options, true)?;
+        lock(&self.uploads).remove(upload_id);
+        Ok(())
+    }
+}
+
+// A panic while a lock is held can't leave a bucket half updated, since every change is a single
+// insertion or removal, so poisoned locks are used as they are.
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+fn lock<T>(lock: &Mutex<T>) -> MutexGuard<'_, T> {
+    lock.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::store::tests::get_data_reader;
+    use std::thread;
+
+    fn read_all(mut reader: SharedReader) -> Vec<u8> {
+        let mut output = Vec::new();
+        let _ = io::copy(&mut reader, &mut output);
+        output
+    }
+
+    #[test]
+    fn shared_store_should_be_send_and_sync() {
+        fn assert_shared<S: Send + Sync>() {}
+        assert_shared::<SharedInMemoryStore>();
+    }
+
+    #[test]
+    fn shared_store_put_then_get_should_return_identical_data() {
+        let store = SharedInMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let output = store.get_object("earth", "Moon").map(read_all);
+        assert_eq!(
+            output,
+            Some(b"Luna".to_vec()),
+            "SharedInMemoryStore failed to return object data."
+        );
+        assert_eq!(
+            store.head_object("earth", "Moon").map(|m| m.etag),
+            Some(checksum::digest(b"Luna"))
+        );
+    }
+
+    #[test]
+    fn shared_store_reader_should_keep_snapshot_when_object_is_replaced() {
+        let store = SharedInMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let reader = store.get_object("earth", "Moon");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let _ = store.remove_object("earth", "Moon");
+        assert_eq!(
+            reader.map(read_all),
+            Some(b"Luna".to_vec()),
+            "SharedInMemoryStore reader did not keep its snapshot."
+        );
+    }
+
+    #[test]
+    fn shared_store_should_accept_concurrent_writes() {
+        let store = Arc::new(SharedInMemoryStore::new());
+        let _ = store.create_bucket("bucket0");
+        let _ = store.create_bucket("bucket1");
+        let handles = (0..8)
+            .map(|i| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    let bucket = format!("bucket{}", i % 2);
+                    for j in 0..50 {
+                        let name = format!("Object{}-{}", i, j);
+                        let _ = store.put_object(&bucket, &name, &mut get_data_reader(b"Luna"));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let _ = handle.join();
+        }
+        let count = ["bucket0", "bucket1"]
+            .iter()
+            .filter_map(|bucket| store.list_objects(bucket))
+            .map(|objects| objects.len())
+            .sum::<usize>();
+        assert_eq!(count, 400, "SharedInMemoryStore lost concurrent writes.");
+    }
+
+    #[test]
+    fn shared_store_reader_should_move_to_another_thread() {
+        let store = SharedInMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let reader = store.get_object_range("earth", "Moon", ByteRange::from(1..3));
+        let output = thread::spawn(move || reader.map(read_all).unwrap_or_default()).join();
+        assert_eq!(output.unwrap_or_default(), b"un");
+    }
+
+    #[test]
+    fn shared_store_insert_object_should_not_replace_existing_object() {
+        let store = SharedInMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let result = store.insert_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ObjectAlreadyExist)),
+            "SharedInMemoryStore replaced existing object."
+        );
+    }
+
+    #[test]
+    fn shared_store_should_keep_versions_when_versioning_enabled() {
+        let store = SharedInMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.set_versioning("earth", true);
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let versions = store.list_object_versions("earth").unwrap_or_default();
+        let options = GetOptions::new()
+            .version_id(&versions[1].version_id)
+            .verify_checksum();
+        let output = store
+            .get_object_with("earth", "Moon", options)
+            .map(read_all);
+        assert_eq!(
+            output.unwrap_or_default(),
+            b"Luna",
+            "SharedInMemoryStore failed to read older version."
+        );
+    }
+
+    #[test]
+    fn shared_store_complete_multipart_upload_should_assemble_parts_in_order() {
+        let store = SharedInMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let _ = store.upload_part("earth", "Moon", &upload_id, 2, &mut get_data_reader(b"na"));
+        let _ = store.upload_part("earth", "Moon", &upload_id, 1, &mut get_data_reader(b"Lu"));
+        let _ = store.complete_multipart_upload("earth", "Moon", &upload_id);
+        assert_eq!(
+            store.get_object("earth", "Moon").map(read_all),
+            Some(b"Luna".to_vec()),
+            "SharedInMemoryStore failed to assemble multipart upload."
+        );
+        assert_eq!(store.list_multipart_uploads("earth"), Some(vec![]));
+    }
+
+    #[test]
+    fn shared_store_complete_multipart_upload_should_keep_upload_when_it_failed() {
+        let store = SharedInMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().checksum(&checksum::digest(b"Selene"));
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", options)
+            .unwrap_or_default();
+        let _ = store.upload_part(
+            "earth",
+            "Moon",
+            &upload_id,
+            1,
+            &mut get_data_reader(b"Luna"),
+        );
+        let result = store.complete_multipart_upload("earth", "Moon", &upload_id);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ChecksumMismatch)),
+            "SharedInMemoryStore completed upload with mismatched checksum."
+        );
+        assert_eq!(
+            store
+                .list_multipart_uploads("earth")
+                .map(|uploads| uploads.len()),
+            Some(1),
+            "SharedInMemoryStore dropped upload after failed completion."
+        );
+    }
+
+    // Deletes the bucket while the object is being read.
+    struct DeletingReader {
+        store: Arc<SharedInMemoryStore>,
+        data: &'static [u8],
+    }
+
+    impl Read for DeletingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let _ = self
+                .store
+                .delete_bucket_with("earth", DeleteBucketOptions::new().force());
+            self.data.read(buf)
+        }
+    }
+
+    #[test]
+    fn shared_store_insert_object_should_fail_when_bucket_was_deleted_meanwhile() {
+        let store = Arc::new(SharedInMemoryStore::new());
+        let _ = store.create_bucket("earth");
+        let reader = DeletingReader {
+            store: store.clone(),
+            data: b"Luna",
+        };
+        let mut reader = Box::new(reader) as Box<dyn Read>;
+        let result = store.put_object_with("earth", "Moon", &mut reader, PutOptions::new());
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotFound)),
+            "SharedInMemoryStore wrote object to a deleted bucket."
+        );
+    }
+
+    #[test]
+    fn shared_store_sweeper_should_drop_expired_objects() {
+        let store = Arc::new(SharedInMemoryStore::new());
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let rules = vec![Rule::new().expire_after(Duration::ZERO)];
+        let _ = store.set_lifecycle("earth", rules);
+        let sweeper = store.start_sweeper(Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(100));
+        // Without the rules an object that was only hidden would show up again.
+        let _ = store.set_lifecycle("earth", vec![]);
+        assert!(
+            store.head_object("earth", "Moon").is_none(),
+            "SharedInMemoryStore sweeper failed to drop expired object."
+        );
+        drop(store);
+        assert!(
+            sweeper.join().is_ok(),
+            "Sweeper did not stop with the store."
+        );
+    }
+}
@@ -1,19 +1,231 @@
+use crate::bucket;
+use crate::checksum::ChecksumReader;
+use crate::compression::Compression;
 use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::in_memory::archive;
+use crate::in_memory::archive::Header;
 use crate::in_memory::bucket::Bucket;
 use crate::in_memory::object::Object;
-use crate::store::Store;
-use std::collections::HashMap;
+use crate::in_memory::pool::{DataPool, DedupUsage};
+use crate::lifecycle::Rule;
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart;
+use crate::multipart::{MultipartUpload, PartsReader, Upload};
+use crate::quota;
+use crate::quota::{LimitedReader, Quota, Usage};
+use crate::range::ByteRange;
+use crate::store::{
+    CopyOptions, DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions, Store,
+};
+use crate::tag;
+use crate::tag::{TagQuery, Tags};
+use crate::version::{ObjectVersion, Stamp};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
 
 pub struct InMemoryStore {
     buckets: HashMap<String, Bucket>,
+    uploads: HashMap<String, Upload<Object>>,
+    quota: Quota,
+    pool: Option<DataPool>,
+    stamps: VecDeque<Stamp>,
+}
+
+impl Default for InMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InMemoryStore {
     pub fn new() -> Self {
         InMemoryStore {
             buckets: HashMap::<String, Bucket>::new(),
+            uploads: HashMap::<String, Upload<Object>>::new(),
+            quota: Quota::new(),
+            pool: None,
+            stamps: VecDeque::new(),
+        }
+    }
+
+    // A store that keeps identical object data only once, however many buckets, names or
+    // versions hold it. Quotas still count every object at its full length.
+    pub fn with_dedup() -> Self {
+        InMemoryStore {
+            pool: Some(DataPool::new()),
+            ..Self::new()
+        }
+    }
+
+    // Turns versioning of a bucket on or off. Objects written while it is on keep all of their
+    // versions until they are removed with `remove_object_version`.
+    pub fn set_versioning(&mut self, bucket: &str, enabled: bool) -> Result<()> {
+        match self.buckets.get_mut(bucket) {
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(container) => {
+                container.set_versioning(enabled);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_compression(&mut self, bucket: &str, compression: Compression) -> Result<()> {
+        match self.buckets.get_mut(bucket) {
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(container) => {
+                container.set_compression(compression);
+                Ok(())
+            }
+        }
+    }
+
+    // The length of the object's data as stored, which differs from its length when compressed.
+    pub fn stored_length(&self, bucket: &str, name: &str) -> Option<u64> {
+        let container = self.buckets.get(bucket)?;
+        container.latest(name).map(|obj| obj.stored_length())
+    }
+
+    // Limits the whole store. Objects already stored are kept when a new quota is exceeded, only
+    // further writes are rejected.
+    pub fn set_quota(&mut self, quota: Quota) {
+        self.quota = quota;
+    }
+
+    pub fn set_bucket_quota(&mut self, bucket: &str, quota: Quota) -> Result<()> {
+        match self.buckets.get_mut(bucket) {
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(container) => {
+                container.set_quota(quota);
+                Ok(())
+            }
+        }
+    }
+
+    // Objects matching the rules stop being listed or returned as soon as they expire, their
+    // data is freed when the object is written again or by `expire_objects`.
+    pub fn set_lifecycle(&mut self, bucket: &str, rules: Vec<Rule>) -> Result<()> {
+        match self.buckets.get_mut(bucket) {
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(container) => {
+                container.set_lifecycle(rules);
+                Ok(())
+            }
+        }
+    }
+
+    // Drops every expired version, returning how many were dropped.
+    pub fn expire_objects(&mut self) -> usize {
+        let now = SystemTime::now();
+        let expired = self
+            .buckets
+            .values_mut()
+            .map(|bucket| bucket.expire(now))
+            .sum();
+        self.prune();
+        expired
+    }
+
+    pub fn usage(&self) -> Usage {
+        self.buckets
+            .values()
+            .map(|bucket| bucket.usage())
+            .fold(Usage::default(), |total, usage| total + usage)
+    }
+
+    pub fn bucket_usage(&self, bucket: &str) -> Option<Usage> {
+        self.buckets.get(bucket).map(|bucket| bucket.usage())
+    }
+
+    // Logical and physical bytes stored, or None when the store doesn't deduplicate.
+    pub fn dedup_usage(&self) -> Option<DedupUsage> {
+        let logical_bytes = self.usage().bytes;
+        self.pool.as_ref().map(|pool| pool.usage(logical_bytes))
+    }
+
+    // How many objects and readers share the data with the given digest.
+    pub fn references(&self, digest: &str) -> usize {
+        self.pool
+            .as_ref()
+            .map(|pool| pool.references(digest))
+            .unwrap_or_default()
+    }
+
+    // Saves every bucket and object to an archive file, see the archive module for its format.
+    // The archive is written next to the file and moved in place once complete, so a failed save
+    // leaves the previous archive intact.
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        let header = Header {
+            version: archive::VERSION,
+            dedup: self.pool.is_some(),
+            quota: self.quota,
+            buckets: self.buckets.len() as u64,
+        };
+        archive::write_header(&mut writer, &header)?;
+        for (name, bucket) in &self.buckets {
+            archive::write_bucket(&mut writer, name, bucket)?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&partial, path)
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = archive::read_header(&mut reader)?;
+        let mut store = match header.dedup {
+            false => InMemoryStore::new(),
+            true => InMemoryStore::with_dedup(),
+        };
+        store.quota = header.quota;
+        for _ in 0..header.buckets {
+            let (name, bucket) =
+                archive::read_bucket(&mut reader, header.version, store.pool.as_mut())?;
+            store.buckets.insert(name, bucket);
+        }
+        Ok(store)
+    }
+
+    // Versions and delete markers created from now on take these stamps in order, instead of a
+    // new version ID and the current time, until they run out. Lets a log replay its changes
+    // exactly as they were made.
+    pub(crate) fn set_stamps(&mut self, stamps: Vec<Stamp>) {
+        self.stamps = stamps.into();
+    }
+
+    // The upload as completing it would find it, failing the way completing it would when it is
+    // missing or has no parts.
+    pub(crate) fn upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<&Upload<Object>> {
+        match self.uploads.get(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) && upload.parts.is_empty() => {
+                Err(Error::new(ErrorKind::InvalidPart, bucket, name))
+            }
+            Some(upload) if upload.is_for(bucket, name) => Ok(upload),
+            _ => Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        }
+    }
+
+    fn next_stamp(&mut self) -> Stamp {
+        self.stamps.pop_front().unwrap_or_default()
+    }
+
+    fn prune(&mut self) {
+        if let Some(pool) = self.pool.as_mut() {
+            pool.prune();
         }
     }
 
@@ -22,22 +234,85 @@ impl InMemoryStore {
         bucket: &str,
         name: &str,
         reader: &mut Box<dyn Read>,
+        options: PutOptions,
         replace: bool,
     ) -> Result<()> {
-        self.create_bucket(bucket);
-        let container = match self.buckets.get_mut(bucket) {
+        tag::validate(bucket, name, options.tags())?;
+        let limit = self.admit(bucket, name, replace)?;
+        options
+            .preconditions()
+            .check(bucket, name, self.head(bucket, name))?;
+        let mut limited = LimitedReader::new(reader, limit);
+        let mut reader = ChecksumReader::new(&mut limited);
+        let mut obj = Object::new(0);
+        let copied = io::copy(&mut reader, &mut obj);
+        let etag = reader.finish();
+        match copied {
+            Err(_) if limited.is_exceeded() => {
+                return Err(Error::new(ErrorKind::QuotaExceeded, bucket, name))
+            }
+            Err(e) => return Err(Error::io(e, bucket, name)),
+            Ok(_) => {}
+        }
+        if !options.verify(&etag) {
+            return Err(Error::new(ErrorKind::ChecksumMismatch, bucket, name));
+        }
+        let mut metadata = options.into_metadata(self.head(bucket, name));
+        metadata.length = obj.metadata().length;
+        metadata.etag = etag;
+        *obj.metadata_mut() = metadata;
+        self.store_object(bucket, name, obj)
+    }
+
+    // Checks that an object may be written, returning how many bytes it may hold. Expired
+    // versions of the object don't count against the quotas.
+    fn admit(&mut self, bucket: &str, name: &str, replace: bool) -> Result<Option<u64>> {
+        if let Some(container) = self.buckets.get_mut(bucket) {
+            container.expire_object(name, SystemTime::now());
+        }
+        let usage = self.usage();
+        let container = match self.buckets.get(bucket) {
             None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
             Some(bucket) => bucket,
         };
         if !replace && container.exist(name) {
             return Err(Error::new(ErrorKind::ObjectAlreadyExist, bucket, name));
         }
-        let mut obj = Object::new(0);
-        match io::copy(reader, &mut obj) {
-            Err(_) => return Err(Error::new(ErrorKind::IO, bucket, name)),
-            Ok(_) => {}
+        let freed = container.reclaimable(name);
+        let (bucket_quota, bucket_usage) = (container.quota(), container.usage());
+        if !self.quota.admits_object(usage, freed)
+            || !bucket_quota.admits_object(bucket_usage, freed)
+        {
+            return Err(Error::new(ErrorKind::QuotaExceeded, bucket, name));
+        }
+        Ok(quota::min_limit(
+            self.quota.byte_limit(usage, freed),
+            bucket_quota.byte_limit(bucket_usage, freed),
+        ))
+    }
+
+    fn head(&self, bucket: &str, name: &str) -> Option<&ObjectMetadata> {
+        self.buckets.get(bucket)?.head(name)
+    }
+
+    // Compresses a new object as its bucket asks and shares its data when it can.
+    fn store_object(&mut self, bucket: &str, name: &str, mut obj: Object) -> Result<()> {
+        let stamp = self.next_stamp();
+        let container = match self.buckets.get_mut(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        let created = container.head(name).map(|m| m.created);
+        let metadata = obj.metadata_mut();
+        metadata.created = created.unwrap_or(stamp.time);
+        metadata.modified = stamp.time;
+        if let Err(e) = obj.compress(container.compression()) {
+            return Err(Error::io(e, bucket, name));
+        }
+        if let Some(pool) = self.pool.as_mut() {
+            pool.intern(&mut obj);
         }
-        container.put(name.into(), obj);
+        container.put_as(name, obj, stamp.version_id);
         Ok(())
     }
 }
@@ -50,54 +325,294 @@ impl Store for InMemoryStore {
             .collect::<Vec<&str>>()
     }
 
-    fn create_bucket(&mut self, name: &str) {
-        if !self.buckets.contains_key(name) {
-            self.buckets.insert(name.into(), Bucket::new());
+    fn create_bucket(&mut self, name: &str) -> Result<()> {
+        bucket::validate_name(name)?;
+        if self.buckets.contains_key(name) {
+            return Err(Error::new(ErrorKind::BucketAlreadyExists, name, ""));
         }
+        self.buckets.insert(name.into(), Bucket::new());
+        Ok(())
     }
 
-    fn delete_bucket(&mut self, name: &str) -> Result<()> {
-        self.buckets.remove(name.into());
+    fn delete_bucket_with(&mut self, name: &str, options: DeleteBucketOptions) -> Result<()> {
+        let container = match self.buckets.get_mut(name) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, name, "")),
+            Some(bucket) => bucket,
+        };
+        container.expire(SystemTime::now());
+        if !options.is_forced() && !container.is_empty() {
+            return Err(Error::new(ErrorKind::BucketNotEmpty, name, ""));
+        }
+        self.buckets.remove(name);
+        self.uploads.retain(|_, upload| upload.bucket != name);
+        self.prune();
         Ok(())
     }
 
-    fn list_objects(&self, bucket: &str) -> Option<Vec<&str>> {
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Result<ObjectList<'_>> {
         match self.buckets.get(bucket) {
-            None => None,
-            Some(bucket) => Some(bucket.names()),
+            None => Err(Error::new(ErrorKind::BucketNotFound, bucket, "")),
+            Some(container) => Ok(container.list(&options)),
         }
     }
 
-    fn insert_object(
+    fn insert_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()> {
+        self.insert_or_replace_object(bucket, name, reader, options, false)
+    }
+
+    fn put_object_with(
         &mut self,
         bucket: &str,
         name: &str,
         reader: &mut Box<dyn Read>,
+        options: PutOptions,
     ) -> Result<()> {
-        self.insert_or_replace_object(bucket, name, reader, false)
+        self.insert_or_replace_object(bucket, name, reader, options, true)
+    }
+
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata> {
+        self.buckets.get(bucket)?.head(name).cloned()
+    }
+
+    fn head_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Option<ObjectMetadata> {
+        let obj = self.buckets.get(bucket)?.version(name, version_id)?;
+        Some(obj.metadata().clone())
+    }
+
+    fn set_object_tags(&mut self, bucket: &str, name: &str, tags: Tags) -> Result<()> {
+        tag::validate(bucket, name, &tags)?;
+        let container = match self.buckets.get_mut(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        match container.set_tags(name, tags) {
+            false => Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            true => Ok(()),
+        }
     }
 
-    fn put_object(&mut self, bucket: &str, name: &str, reader: &mut Box<dyn Read>) -> Result<()> {
-        self.insert_or_replace_object(bucket, name, reader, true)
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<&str>> {
+        self.buckets.get(bucket).map(|bucket| bucket.find(query))
     }
 
-    fn get_object<'a>(&'a self, bucket: &str, name: &str) -> Option<Box<dyn Read + 'a>> {
-        let bucket = match self.buckets.get(bucket) {
-            None => return None,
+    fn get_object_with<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<Box<dyn Read + 'a>> {
+        let container = match self.buckets.get(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        let found = match options.version() {
+            None => container.get_with_metadata(name),
+            Some(version_id) => match container.get_version(name, version_id) {
+                None => return Err(Error::new(ErrorKind::VersionNotFound, bucket, name)),
+                found => found,
+            },
+        };
+        match found {
+            None => Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            Some((obj, metadata)) => {
+                options
+                    .preconditions()
+                    .check(bucket, name, Some(metadata))?;
+                Ok(options.reader(Box::new(obj), metadata))
+            }
+        }
+    }
+
+    fn get_object_range<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + 'a>> {
+        let container = match self.buckets.get(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
             Some(bucket) => bucket,
         };
-        match bucket.get(name) {
-            None => None,
-            Some(obj) => Some(Box::new(obj)),
+        let length = match container.head(name) {
+            None => return Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            Some(metadata) => metadata.length,
+        };
+        let range = match range.resolve(length) {
+            None => return Err(Error::new(ErrorKind::InvalidRange, bucket, name)),
+            Some(range) => range,
+        };
+        match container.get_range(name, range) {
+            None => Err(Error::new(ErrorKind::ObjectNotFound, bucket, name)),
+            Some(obj) => Ok(Box::new(obj)),
         }
     }
 
-    fn remove_object(&mut self, bucket: &str, name: &str) -> Result<()> {
-        let bucket = match self.buckets.get_mut(bucket) {
+    // The copy shares the data of its source, only data the destination bucket compresses
+    // differently is written again.
+    fn copy_object_with(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+        options: CopyOptions,
+    ) -> Result<()> {
+        let mut obj = match self.buckets.get(src_bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, src_bucket, src_name)),
+            Some(container) => match container.latest(src_name) {
+                None => return Err(Error::new(ErrorKind::ObjectNotFound, src_bucket, src_name)),
+                Some(obj) => obj.clone(),
+            },
+        };
+        let length = obj.metadata().length;
+        if matches!(self.admit(dst_bucket, dst_name, true)?, Some(limit) if length > limit) {
+            return Err(Error::new(ErrorKind::QuotaExceeded, dst_bucket, dst_name));
+        }
+        let source = obj.metadata();
+        let options = options.into_put_options(source);
+        tag::validate(dst_bucket, dst_name, options.tags())?;
+        let mut metadata = options.into_metadata(self.head(dst_bucket, dst_name));
+        metadata.length = length;
+        metadata.etag = source.etag.clone();
+        *obj.metadata_mut() = metadata;
+        self.store_object(dst_bucket, dst_name, obj)
+    }
+
+    fn remove_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()> {
+        let stamp = self.next_stamp();
+        let container = match self.buckets.get_mut(bucket) {
+            None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
+            Some(bucket) => bucket,
+        };
+        options
+            .preconditions()
+            .check(bucket, name, container.head(name))?;
+        container.remove_as(name, &stamp);
+        self.prune();
+        Ok(())
+    }
+
+    fn remove_object_version(&mut self, bucket: &str, name: &str, version_id: &str) -> Result<()> {
+        let container = match self.buckets.get_mut(bucket) {
             None => return Err(Error::new(ErrorKind::BucketNotFound, bucket, name)),
             Some(bucket) => bucket,
         };
-        bucket.remove(name);
+        if container.remove_version(name, version_id) {
+            self.prune();
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::VersionNotFound, bucket, name))
+        }
+    }
+
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>> {
+        self.buckets.get(bucket).map(|bucket| bucket.versions())
+    }
+
+    fn create_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String> {
+        if !self.buckets.contains_key(bucket) {
+            return Err(Error::new(ErrorKind::BucketNotFound, bucket, name));
+        }
+        let upload_id = multipart::new_upload_id();
+        self.uploads
+            .insert(upload_id.clone(), Upload::new(bucket, name, options));
+        Ok(upload_id)
+    }
+
+    fn upload_part(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String> {
+        let upload = match self.uploads.get_mut(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) => upload,
+            _ => return Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        };
+        if !multipart::is_valid_part_number(part_number) {
+            return Err(Error::new(ErrorKind::InvalidPart, bucket, name));
+        }
+        // Parts can't be larger than the object they make up. The quotas on the whole object are
+        // enforced when the upload is completed.
+        let limit = quota::min_limit(
+            self.quota.part_limit(),
+            self.buckets
+                .get(bucket)
+                .and_then(|b| b.quota().part_limit()),
+        );
+        let mut limited = LimitedReader::new(reader, limit);
+        let mut reader = ChecksumReader::new(&mut limited);
+        let mut part = Object::new(0);
+        let copied = io::copy(&mut reader, &mut part);
+        let etag = reader.finish();
+        match copied {
+            Err(_) if limited.is_exceeded() => {
+                return Err(Error::new(ErrorKind::QuotaExceeded, bucket, name))
+            }
+            Err(e) => return Err(Error::io(e, bucket, name)),
+            Ok(_) => {}
+        }
+        part.metadata_mut().etag = etag.clone();
+        upload.parts.insert(part_number, part);
+        Ok(etag)
+    }
+
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>> {
+        if !self.buckets.contains_key(bucket) {
+            return None;
+        }
+        Some(multipart::list(self.uploads.iter(), bucket))
+    }
+
+    fn abort_multipart_upload(&mut self, bucket: &str, name: &str, upload_id: &str) -> Result<()> {
+        match self.uploads.get(upload_id) {
+            Some(upload) if upload.is_for(bucket, name) => {
+                self.uploads.remove(upload_id);
+                Ok(())
+            }
+            _ => Err(Error::new(ErrorKind::UploadNotFound, bucket, name)),
+        }
+    }
+
+    fn complete_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()> {
+        let upload = self.upload(bucket, name, upload_id)?;
+        let options = upload.options.clone();
+        let parts = upload
+            .parts
+            .values()
+            .map(|part| part.snapshot())
+            .collect::<Vec<_>>();
+        let mut reader = Box::new(PartsReader::new(parts)) as Box<dyn Read>;
+        self.insert_or_replace_object(bucket, name, &mut reader, options, true)?;
+        self.uploads.remove(upload_id);
         Ok(())
     }
 }
@@ -105,146 +620,318 @@ impl Store for InMemoryStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::checksum;
+    use crate::store::tests::{get_data_reader, store_tests};
+    use std::time::Duration;
 
-    const EMPTY: &'static [u8] = b"";
-
-    fn get_reader() -> Box<dyn Read> {
-        Box::new(EMPTY)
+    fn with_store(test: fn(InMemoryStore)) {
+        test(InMemoryStore::new());
     }
 
-    fn get_bucket_names() -> Vec<&'static str> {
-        vec!["Mars", "Jupiter", "Saturn", "Uranus", "Neptune", "Pluto"]
-    }
+    store_tests!(with_store);
 
-    fn get_object_names() -> Vec<&'static str> {
-        vec!["Io", "Europa", "Ganymede", "Callisto"]
+    #[test]
+    fn store_should_keep_versions_when_versioning_enabled() {
+        let mut store = InMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.set_versioning("earth", true);
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let _ = store.remove_object("earth", "Moon");
+        assert!(
+            store.get_object("earth", "Moon").is_none(),
+            "InMemoryStore returned removed object."
+        );
+        let versions = store.list_object_versions("earth").unwrap_or_default();
+        assert_eq!(
+            versions.len(),
+            3,
+            "InMemoryStore did not keep all versions."
+        );
+        let options = GetOptions::new().version_id(&versions[2].version_id);
+        let mut output = Vec::new();
+        if let Ok(mut reader) = store.get_object_with("earth", "Moon", options) {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(output, b"Luna", "InMemoryStore failed to roll back object.");
     }
 
-    fn get_store(buckets: &Vec<&str>) -> InMemoryStore {
+    #[test]
+    fn store_complete_multipart_upload_should_keep_upload_over_quota() {
         let mut store = InMemoryStore::new();
-        for bucket in buckets {
-            store.create_bucket(bucket);
-        }
-        store
+        let _ = store.create_bucket("earth");
+        let _ = store.set_bucket_quota("earth", Quota::new().max_bytes(3));
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let _ = store.upload_part(
+            "earth",
+            "Moon",
+            &upload_id,
+            1,
+            &mut get_data_reader(b"Luna"),
+        );
+        let result = store.complete_multipart_upload("earth", "Moon", &upload_id);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::QuotaExceeded)),
+            "InMemoryStore completed upload over bucket quota."
+        );
+        let _ = store.set_bucket_quota("earth", Quota::new());
+        assert!(
+            store
+                .complete_multipart_upload("earth", "Moon", &upload_id)
+                .is_ok(),
+            "InMemoryStore failed to complete upload after a failed attempt."
+        );
     }
 
-    fn get_populated_store(bucket: &str, objects: &Vec<&str>) -> InMemoryStore {
+    #[test]
+    fn store_put_object_should_reject_object_over_bucket_quota() {
         let mut store = InMemoryStore::new();
-        for object in objects {
-            let _ = store.insert_object(bucket, object, &mut get_reader());
-        }
-        store
+        let _ = store.create_bucket("earth");
+        let _ = store.set_bucket_quota("earth", Quota::new().max_bytes(6));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let result = store.put_object("earth", "Moon2", &mut get_data_reader(b"Selene"));
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::QuotaExceeded)),
+            "InMemoryStore accepted object over bucket quota."
+        );
+        assert!(
+            store.head_object("earth", "Moon2").is_none(),
+            "InMemoryStore kept partial object."
+        );
+        assert_eq!(store.bucket_usage("earth"), Some(Usage::of(4)));
     }
 
     #[test]
-    fn store_list_buckets_should_return_all_bucket_names() {
-        let mut buckets = get_bucket_names();
-        let store = get_store(&buckets);
-        let mut output = store.list_buckets();
-        buckets.sort();
-        output.sort();
-        assert_eq!(
-            buckets, output,
-            "InMemoryStore failed to list all bucket names."
+    fn store_put_object_should_free_quota_of_replaced_object() {
+        let mut store = InMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        store.set_quota(Quota::new().max_bytes(6).max_objects(1));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let result = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        assert!(
+            result.is_ok(),
+            "InMemoryStore did not count space freed by replaced object."
+        );
+        let result = store.put_object("mars", "Phobos", &mut get_data_reader(b""));
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::QuotaExceeded)),
+            "InMemoryStore accepted object over store object count."
         );
     }
 
     #[test]
-    fn store_create_bucket_should_add_bucket_when_bucket_did_not_exist() {
+    fn store_upload_part_should_reject_part_over_max_object_size() {
         let mut store = InMemoryStore::new();
-        let bucket = "Earth";
-        store.create_bucket(bucket);
+        let _ = store.create_bucket("earth");
+        store.set_quota(Quota::new().max_object_size(4));
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let result = store.upload_part(
+            "earth",
+            "Moon",
+            &upload_id,
+            1,
+            &mut get_data_reader(b"Selene"),
+        );
         assert!(
-            store.list_buckets().contains(&bucket),
-            "InMemoryStore failed to create new bucket."
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::QuotaExceeded)),
+            "InMemoryStore accepted part over max object size."
         );
     }
 
     #[test]
-    fn store_delete_bucket_should_delete_existing_bucket() {
-        let mut store = get_store(&get_bucket_names());
-        let bucket = "Pluto";
-        let _ = store.delete_bucket(bucket);
+    fn store_should_hide_and_expire_objects_past_lifecycle() {
+        let mut store = InMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "cache/Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let rules = vec![Rule::new().prefix("cache/").expire_after(Duration::ZERO)];
+        assert!(store.set_lifecycle("earth", rules).is_ok());
         assert!(
-            !store.list_buckets().contains(&bucket),
-            "InMemoryStore failed to delete bucket."
+            store.get_object("earth", "cache/Moon").is_none(),
+            "InMemoryStore returned an expired object."
         );
+        assert_eq!(
+            store.list_objects("earth"),
+            Some(vec!["Moon"]),
+            "InMemoryStore listed an expired object."
+        );
+        assert_eq!(store.expire_objects(), 1);
+        assert_eq!(store.bucket_usage("earth"), Some(Usage::of(4)));
     }
 
     #[test]
-    fn store_list_objects_should_list_all_object_names_in_bucket() {
-        let bucket = "Jupiter";
-        let mut objects = get_object_names();
-        let store = get_populated_store(bucket, &objects);
-        let mut output = store.list_objects(bucket).unwrap_or_default();
-        objects.sort();
-        output.sort();
+    fn store_with_dedup_should_store_identical_data_once() {
+        let mut store = InMemoryStore::with_dedup();
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("mars", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("mars", "Phobos", &mut get_data_reader(b"Fear"));
         assert_eq!(
-            objects, output,
-            "InMemoryStore failed to list all object names in a bucket."
+            store.dedup_usage(),
+            Some(DedupUsage {
+                logical_bytes: 12,
+                physical_bytes: 8,
+                blobs: 2
+            }),
+            "InMemoryStore did not share identical data."
         );
+        assert_eq!(store.references(&checksum::digest(b"Luna")), 2);
     }
 
     #[test]
-    fn store_insert_object_should_add_object_when_object_did_not_exist() {
-        let bucket = "Earth";
-        let object = "Moon";
+    fn store_copy_object_should_share_data_of_source() {
         let mut store = InMemoryStore::new();
-        assert!(
-            store
-                .insert_object(bucket, object, &mut get_reader())
-                .is_ok(),
-            "InMemoryStore failed to insert new object."
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.copy_object("earth", "Moon", "mars", "Phobos");
+        let data = |bucket: &str, name: &str| {
+            store.buckets[bucket]
+                .latest(name)
+                .map(|obj| obj.stored_data().as_ptr())
+        };
+        assert!(data("mars", "Phobos").is_some());
+        assert_eq!(
+            data("mars", "Phobos"),
+            data("earth", "Moon"),
+            "InMemoryStore copied data of an object."
         );
     }
 
     #[test]
-    fn store_insert_object_should_not_add_object_when_object_already_exist() {
-        let bucket = "Jupiter";
-        let objects = get_object_names();
-        let object = objects[0].clone();
-        let mut store = get_populated_store(bucket, &objects);
-        assert!(
-            store
-                .insert_object(bucket, object, &mut get_reader())
-                .is_err(),
-            "InMemoryStore insert duplicated object."
+    fn store_with_dedup_should_free_data_with_last_reference() {
+        let mut store = InMemoryStore::with_dedup();
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("mars", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.delete_bucket_with("earth", DeleteBucketOptions::new().force());
+        let mut output = Vec::new();
+        if let Some(mut reader) = store.get_object("mars", "Moon") {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(output, b"Luna", "InMemoryStore freed data still in use.");
+        let _ = store.remove_object("mars", "Moon");
+        assert_eq!(
+            store.dedup_usage().map(|usage| usage.physical_bytes),
+            Some(0),
+            "InMemoryStore kept data without references."
         );
     }
 
     #[test]
-    fn store_put_object_should_replace_existing_object() {
-        let bucket = "Jupiter";
-        let objects = get_object_names();
-        let object = objects[0].clone();
-        let mut store = get_populated_store(bucket, &objects);
+    fn store_should_compress_objects_of_bucket() {
+        let mut store = InMemoryStore::new();
+        let _ = store.create_bucket("earth");
+        let _ = store.set_compression("earth", Compression::Gzip);
+        let text = "Mercury Venus Earth Mars ".repeat(100);
+        let mut reader = Box::new(io::Cursor::new(text.clone())) as Box<dyn Read>;
+        let _ = store.put_object("earth", "Planets", &mut reader);
+        let length = store.head_object("earth", "Planets").map(|m| m.length);
+        assert_eq!(
+            length,
+            Some(text.len() as u64),
+            "InMemoryStore reported stored length."
+        );
         assert!(
-            store.put_object(bucket, object, &mut get_reader()).is_ok(),
-            "InMemoryStore failed to replace an existing object."
+            store.stored_length("earth", "Planets") < length,
+            "InMemoryStore did not compress object."
+        );
+        let mut output = Vec::new();
+        if let Ok(mut reader) = store.get_object_range("earth", "Planets", ByteRange::Suffix(5)) {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(
+            output, b"Mars ",
+            "InMemoryStore failed to read compressed range."
         );
     }
 
     #[test]
-    fn store_get_object_should_return_existing_object() {
-        let bucket = "Earth";
-        let object = "Moon";
+    fn store_copy_object_should_compress_copy_as_destination_bucket() {
         let mut store = InMemoryStore::new();
-        let _ = store.put_object(bucket, object, &mut get_reader());
-        assert!(
-            store.get_object(bucket, object).is_some(),
-            "InMemoryStore failed to return existing object."
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let _ = store.set_compression("earth", Compression::Gzip);
+        let _ = store.set_compression("mars", Compression::Lz4);
+        let text = "Mercury Venus Earth Mars ".repeat(100);
+        let mut reader = Box::new(io::Cursor::new(text.clone())) as Box<dyn Read>;
+        let _ = store.put_object("earth", "Planets", &mut reader);
+        let _ = store.copy_object("earth", "Planets", "mars", "Planets");
+        let compression = store
+            .buckets
+            .get("mars")
+            .and_then(|container| container.latest("Planets"))
+            .map(|obj| obj.compression());
+        assert_eq!(
+            compression,
+            Some(Compression::Lz4),
+            "InMemoryStore kept the compression of the source bucket."
+        );
+        let mut output = Vec::new();
+        if let Some(mut reader) = store.get_object("mars", "Planets") {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(
+            output,
+            text.as_bytes(),
+            "InMemoryStore failed to read recompressed copy."
         );
     }
 
     #[test]
-    fn store_remove_object_should_delete_existing_object() {
-        let bucket = "Jupiter";
-        let object = "Callisto";
-        let mut store = get_populated_store(bucket, &get_object_names());
-        let _ = store.remove_object(bucket, object);
+    fn store_load_from_should_restore_saved_store() {
+        let mut store = InMemoryStore::with_dedup();
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        store.set_quota(Quota::new().max_objects(10));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("mars", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.set_compression("mars", Compression::Zstd);
+        let _ = store.put_object("mars", "Phobos", &mut get_data_reader(b"Fear"));
+        let path = std::env::temp_dir().join(format!("blob-store-{}.archive", std::process::id()));
         assert!(
-            store.get_object(bucket, object).is_none(),
-            "InMemoryStore failed to remove existing object."
+            store.save_to(&path).is_ok(),
+            "InMemoryStore failed to save."
         );
+
+        let loaded = InMemoryStore::load_from(&path);
+        let _ = fs::remove_file(&path);
+        let loaded = match loaded {
+            Err(err) => panic!("InMemoryStore failed to load: {}", err),
+            Ok(loaded) => loaded,
+        };
+        for (bucket, name) in &[("earth", "Moon"), ("mars", "Moon"), ("mars", "Phobos")] {
+            assert_eq!(
+                loaded.head_object(bucket, name),
+                store.head_object(bucket, name),
+                "InMemoryStore lost metadata of {}/{}.",
+                bucket,
+                name
+            );
+        }
+        let mut output = Vec::new();
+        if let Some(mut reader) = loaded.get_object("mars", "Phobos") {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(output, b"Fear", "InMemoryStore lost compressed data.");
+        assert_eq!(loaded.quota, store.quota);
+        assert_eq!(loaded.dedup_usage(), store.dedup_usage());
+    }
+
+    mod dedup {
+        use super::*;
+
+        fn with_store(test: fn(InMemoryStore)) {
+            test(InMemoryStore::with_dedup());
+        }
+
+        store_tests!(with_store);
     }
 }
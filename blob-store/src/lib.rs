@@ -1,3 +1,22 @@
+pub mod access;
+pub mod asynchronous;
+pub mod bucket;
+pub mod checksum;
+pub mod compression;
+pub mod condition;
+pub mod encryption;
 pub mod error;
+pub mod event;
+pub mod fs;
 pub mod in_memory;
+pub mod lifecycle;
+pub mod list;
+pub mod metadata;
+pub mod multipart;
+pub mod quota;
+pub mod range;
+pub mod server;
+pub mod shared;
 pub mod store;
+pub mod tag;
+pub mod version;
@@ -0,0 +1,131 @@
+use crate::tag::{TagQuery, Tags};
+use std::time::{Duration, SystemTime};
+
+// A lifecycle rule of a bucket. It applies to the objects whose names start with its prefix and,
+// when it has a tag query, whose tags match the query. It expires their versions once they are
+// older than the rule allows, or once there are more newer versions than it keeps. An expired
+// version is no longer visible, and is dropped the next time the bucket is swept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rule {
+    pub(crate) prefix: String,
+    pub(crate) expire_after: Option<Duration>,
+    pub(crate) keep_versions: Option<usize>,
+    pub(crate) tags: Option<TagQuery>,
+}
+
+impl Rule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    // Only applies to versions whose tags match the query, which delete markers never do.
+    pub fn tags(mut self, query: TagQuery) -> Self {
+        self.tags = Some(query);
+        self
+    }
+
+    // Expires versions this long after they were written.
+    pub fn expire_after(mut self, ttl: Duration) -> Self {
+        self.expire_after = Some(ttl);
+        self
+    }
+
+    // Keeps the newest N versions of an object, delete markers included.
+    pub fn keep_versions(mut self, count: usize) -> Self {
+        self.keep_versions = Some(count);
+        self
+    }
+
+    // Whether a version expires, where `rank` counts the newer versions of the same object and
+    // `tags` are those of the version, `None` for a delete marker.
+    pub fn expires(
+        &self,
+        name: &str,
+        tags: Option<&Tags>,
+        rank: usize,
+        written: SystemTime,
+        now: SystemTime,
+    ) -> bool {
+        if !name.starts_with(&self.prefix) {
+            return false;
+        }
+        if let Some(query) = &self.tags {
+            if !tags.map(|tags| query.matches(tags)).unwrap_or(false) {
+                return false;
+            }
+        }
+        let too_old = match (self.expire_after, now.duration_since(written)) {
+            (Some(ttl), Ok(age)) => age >= ttl,
+            _ => false,
+        };
+        let too_many = matches!(self.keep_versions, Some(count) if rank >= count);
+        too_old || too_many
+    }
+}
+
+pub fn expires(
+    rules: &[Rule],
+    name: &str,
+    tags: Option<&Tags>,
+    rank: usize,
+    written: SystemTime,
+    now: SystemTime,
+) -> bool {
+    rules
+        .iter()
+        .any(|rule| rule.expires(name, tags, rank, written, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_should_apply_ttl_to_matching_prefix_only() {
+        let rule = Rule::new()
+            .prefix("cache/")
+            .expire_after(Duration::from_secs(60));
+        let now = SystemTime::now();
+        let written = now - Duration::from_secs(120);
+        assert!(rule.expires("cache/build", None, 0, written, now));
+        assert!(!rule.expires("release/build", None, 0, written, now));
+        assert!(!rule.expires("cache/build", None, 0, now, now));
+    }
+
+    #[test]
+    fn expires_should_keep_newest_versions() {
+        let rule = Rule::new().keep_versions(2);
+        let now = SystemTime::now();
+        assert!(!rule.expires("Moon", None, 1, now, now));
+        assert!(rule.expires("Moon", None, 2, now, now));
+    }
+
+    #[test]
+    fn expires_should_not_expire_without_limits() {
+        let rule = Rule::new().prefix("cache/");
+        let now = SystemTime::now();
+        let written = now - Duration::from_secs(3600);
+        assert!(!rule.expires("cache/build", None, 10, written, now));
+    }
+
+    #[test]
+    fn expires_should_only_apply_tag_filter_to_matching_tags() {
+        let rule = Rule::new()
+            .tags(TagQuery::equals("env", "test"))
+            .expire_after(Duration::ZERO);
+        let now = SystemTime::now();
+        let mut tags = Tags::new();
+        assert!(!rule.expires("Moon", Some(&tags), 0, now, now));
+        tags.insert("env".into(), "test".into());
+        assert!(rule.expires("Moon", Some(&tags), 0, now, now));
+        assert!(
+            !rule.expires("Moon", None, 0, now, now),
+            "Rule expired a delete marker by its tags."
+        );
+    }
+}
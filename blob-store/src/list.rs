@@ -0,0 +1,194 @@
+use std::ops::Bound;
+
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    prefix: Option<String>,
+    delimiter: Option<String>,
+    max_keys: Option<usize>,
+    continuation_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectList<'a> {
+    pub objects: Vec<&'a str>,
+    pub common_prefixes: Vec<&'a str>,
+    pub is_truncated: bool,
+    pub next_continuation_token: Option<String>,
+}
+
+// An `ObjectList` that owns its names, for stores that can't lend them out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OwnedObjectList {
+    pub objects: Vec<String>,
+    pub common_prefixes: Vec<String>,
+    pub is_truncated: bool,
+    pub next_continuation_token: Option<String>,
+}
+
+impl From<ObjectList<'_>> for OwnedObjectList {
+    fn from(list: ObjectList<'_>) -> Self {
+        OwnedObjectList {
+            objects: list.objects.into_iter().map(String::from).collect(),
+            common_prefixes: list.common_prefixes.into_iter().map(String::from).collect(),
+            is_truncated: list.is_truncated,
+            next_continuation_token: list.next_continuation_token,
+        }
+    }
+}
+
+impl ListOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    // Names containing the delimiter after the prefix are rolled up into a single common prefix
+    // that ends with the first occurrence of the delimiter.
+    pub fn delimiter(mut self, delimiter: &str) -> Self {
+        self.delimiter = Some(delimiter.into()).filter(|d: &String| !d.is_empty());
+        self
+    }
+
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+
+    // Continues a truncated listing from `ObjectList::next_continuation_token`.
+    pub fn continuation_token(mut self, token: &str) -> Self {
+        self.continuation_token = Some(token.into());
+        self
+    }
+
+    pub fn key_prefix(&self) -> &str {
+        self.prefix.as_deref().unwrap_or("")
+    }
+
+    // Lower bound of the names that need to be visited, for use with the `range` of an ordered
+    // collection.
+    pub fn start(&self) -> Bound<&str> {
+        match (&self.continuation_token, &self.prefix) {
+            (Some(token), _) => Bound::Excluded(token),
+            (None, Some(prefix)) => Bound::Included(prefix),
+            (None, None) => Bound::Unbounded,
+        }
+    }
+
+    // Builds a page of the listing out of sorted `names` starting at `self.start()`.
+    pub fn list<'a, I: Iterator<Item = &'a str>>(&self, names: I) -> ObjectList<'a> {
+        let prefix = self.key_prefix();
+        let token = self.continuation_token.as_deref();
+        let mut list = ObjectList::default();
+        let mut last: Option<&'a str> = None;
+        for name in names {
+            if !name.starts_with(prefix) {
+                if name > prefix {
+                    break;
+                }
+                continue;
+            }
+            let common_prefix = self.delimiter.as_deref().and_then(|delimiter| {
+                name[prefix.len()..]
+                    .find(delimiter)
+                    .map(|i| &name[..prefix.len() + i + delimiter.len()])
+            });
+            let entry = common_prefix.unwrap_or(name);
+            if last == Some(entry) || (common_prefix.is_some() && token == Some(entry)) {
+                continue;
+            }
+            if Some(list.objects.len() + list.common_prefixes.len()) == self.max_keys {
+                list.is_truncated = true;
+                list.next_continuation_token = last.or(token).map(|s| s.into());
+                break;
+            }
+            match common_prefix {
+                Some(common_prefix) => list.common_prefixes.push(common_prefix),
+                None => list.objects.push(name),
+            }
+            last = Some(entry);
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_names() -> Vec<&'static str> {
+        vec![
+            "docs/guide.md",
+            "docs/img/logo.png",
+            "docs/readme.md",
+            "src/lib.rs",
+            "src/main.rs",
+            "test.rs",
+        ]
+    }
+
+    fn list<'a>(names: &[&'a str], options: &ListOptions) -> ObjectList<'a> {
+        let start = options.start();
+        options.list(names.iter().copied().filter(|name| match start {
+            Bound::Included(s) => *name >= s,
+            Bound::Excluded(s) => *name > s,
+            Bound::Unbounded => true,
+        }))
+    }
+
+    #[test]
+    fn list_should_return_all_names_without_options() {
+        let names = get_names();
+        let output = list(&names, &ListOptions::new());
+        assert_eq!(output.objects, names, "Listing did not return all names.");
+        assert!(!output.is_truncated, "Listing without limit is truncated.");
+    }
+
+    #[test]
+    fn list_should_filter_by_prefix() {
+        let output = list(&get_names(), &ListOptions::new().prefix("src/"));
+        assert_eq!(output.objects, vec!["src/lib.rs", "src/main.rs"]);
+    }
+
+    #[test]
+    fn list_should_group_common_prefixes_by_delimiter() {
+        let output = list(&get_names(), &ListOptions::new().delimiter("/"));
+        assert_eq!(output.objects, vec!["test.rs"]);
+        assert_eq!(output.common_prefixes, vec!["docs/", "src/"]);
+    }
+
+    #[test]
+    fn list_should_group_common_prefixes_after_prefix() {
+        let options = ListOptions::new().prefix("docs/").delimiter("/");
+        let output = list(&get_names(), &options);
+        assert_eq!(output.objects, vec!["docs/guide.md", "docs/readme.md"]);
+        assert_eq!(output.common_prefixes, vec!["docs/img/"]);
+    }
+
+    #[test]
+    fn list_should_paginate_with_continuation_token() {
+        let names = get_names();
+        let mut options = ListOptions::new().delimiter("/").max_keys(1);
+        let mut pages = Vec::new();
+        loop {
+            let output = list(&names, &options);
+            pages.push((output.objects, output.common_prefixes));
+            match output.next_continuation_token {
+                None => break,
+                Some(token) => options = options.continuation_token(&token),
+            }
+        }
+        assert_eq!(
+            pages,
+            vec![
+                (vec![], vec!["docs/"]),
+                (vec![], vec!["src/"]),
+                (vec!["test.rs"], vec![]),
+            ],
+            "Listing returned wrong pages."
+        );
+    }
+}
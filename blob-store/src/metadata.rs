@@ -0,0 +1,38 @@
+use crate::tag::Tags;
+use crate::version::NULL_VERSION;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectMetadata {
+    pub length: u64,
+    pub created: SystemTime,
+    pub modified: SystemTime,
+    pub etag: String,
+    pub version_id: String,
+    pub content_type: Option<String>,
+    pub user: HashMap<String, String>,
+    pub tags: Tags,
+}
+
+impl ObjectMetadata {
+    pub fn new() -> Self {
+        let now = SystemTime::now();
+        ObjectMetadata {
+            length: 0,
+            created: now,
+            modified: now,
+            etag: String::new(),
+            version_id: NULL_VERSION.into(),
+            content_type: None,
+            user: HashMap::<String, String>::new(),
+            tags: Tags::new(),
+        }
+    }
+}
+
+impl Default for ObjectMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
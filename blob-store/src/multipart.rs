@@ -0,0 +1,145 @@
+use crate::store::PutOptions;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::io::Read;
+use std::time::SystemTime;
+
+pub const MAX_PART_NUMBER: u32 = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipartUpload {
+    pub name: String,
+    pub upload_id: String,
+    pub initiated: SystemTime,
+}
+
+// An upload in progress. Parts are kept by part number and assembled in ascending order once the
+// upload is completed.
+pub struct Upload<P> {
+    pub bucket: String,
+    pub name: String,
+    pub options: PutOptions,
+    pub initiated: SystemTime,
+    pub parts: BTreeMap<u32, P>,
+}
+
+impl<P> Upload<P> {
+    pub fn new(bucket: &str, name: &str, options: PutOptions) -> Self {
+        Upload {
+            bucket: bucket.into(),
+            name: name.into(),
+            options,
+            initiated: SystemTime::now(),
+            parts: BTreeMap::<u32, P>::new(),
+        }
+    }
+
+    pub fn is_for(&self, bucket: &str, name: &str) -> bool {
+        self.bucket == bucket && self.name == name
+    }
+
+    pub fn info(&self, upload_id: &str) -> MultipartUpload {
+        MultipartUpload {
+            name: self.name.clone(),
+            upload_id: upload_id.into(),
+            initiated: self.initiated,
+        }
+    }
+}
+
+pub fn new_upload_id() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+pub fn is_valid_part_number(part_number: u32) -> bool {
+    (1..=MAX_PART_NUMBER).contains(&part_number)
+}
+
+// Lists the uploads in progress for a bucket, ordered by object name and initiation time.
+pub fn list<'a, P, I>(uploads: I, bucket: &str) -> Vec<MultipartUpload>
+where
+    P: 'a,
+    I: Iterator<Item = (&'a String, &'a Upload<P>)>,
+{
+    let mut list = uploads
+        .filter(|(_, upload)| upload.bucket == bucket)
+        .map(|(id, upload)| upload.info(id))
+        .collect::<Vec<MultipartUpload>>();
+    list.sort_by(|a, b| (&a.name, a.initiated).cmp(&(&b.name, b.initiated)));
+    list
+}
+
+// Reads the parts of an upload one after another.
+pub struct PartsReader<R> {
+    parts: VecDeque<R>,
+}
+
+impl<R: Read> PartsReader<R> {
+    pub fn new<I: IntoIterator<Item = R>>(parts: I) -> Self {
+        PartsReader {
+            parts: parts.into_iter().collect(),
+        }
+    }
+}
+
+impl<R: Read> Read for PartsReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(part) = self.parts.front_mut() {
+            let n = part.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.parts.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_reader_should_read_parts_in_order() {
+        let mut reader = PartsReader::new(vec![&b"Lu"[..], &b""[..], &b"na"[..]]);
+        let mut output = Vec::new();
+        let _ = io::copy(&mut reader, &mut output);
+        assert_eq!(
+            output, b"Luna",
+            "PartsReader did not read all parts in order."
+        );
+    }
+
+    #[test]
+    fn is_valid_part_number_should_accept_range_from_one_to_max() {
+        assert!(!is_valid_part_number(0));
+        assert!(is_valid_part_number(1));
+        assert!(is_valid_part_number(MAX_PART_NUMBER));
+        assert!(!is_valid_part_number(MAX_PART_NUMBER + 1));
+    }
+
+    #[test]
+    fn list_should_return_uploads_of_bucket_sorted_by_name() {
+        let mut uploads = BTreeMap::<String, Upload<()>>::new();
+        uploads.insert("b".into(), Upload::new("jupiter", "Io", PutOptions::new()));
+        uploads.insert(
+            "a".into(),
+            Upload::new("jupiter", "Europa", PutOptions::new()),
+        );
+        uploads.insert(
+            "c".into(),
+            Upload::new("Saturn", "Titan", PutOptions::new()),
+        );
+        let names = list(uploads.iter(), "jupiter")
+            .into_iter()
+            .map(|upload| upload.name)
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["Europa", "Io"]);
+    }
+}
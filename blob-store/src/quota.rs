@@ -0,0 +1,186 @@
+use std::io;
+use std::io::Read;
+use std::ops::{Add, Sub};
+
+// Limits on what a store or a bucket may hold. Every limit is off unless it is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Quota {
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) max_objects: Option<u64>,
+    pub(crate) max_object_size: Option<u64>,
+}
+
+// Bytes and objects held, counting every stored version of an object.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub bytes: u64,
+    pub objects: u64,
+}
+
+impl Quota {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn max_objects(mut self, max_objects: u64) -> Self {
+        self.max_objects = Some(max_objects);
+        self
+    }
+
+    pub fn max_object_size(mut self, max_object_size: u64) -> Self {
+        self.max_object_size = Some(max_object_size);
+        self
+    }
+
+    // Whether one more object fits, given the current usage and what the write frees by
+    // replacing an existing object.
+    pub fn admits_object(&self, usage: Usage, freed: Usage) -> bool {
+        match self.max_objects {
+            None => true,
+            Some(max) => usage.objects.saturating_sub(freed.objects) < max,
+        }
+    }
+
+    // The most bytes a new object may hold, or None when it isn't limited.
+    pub fn byte_limit(&self, usage: Usage, freed: Usage) -> Option<u64> {
+        let available = self
+            .max_bytes
+            .map(|max| max.saturating_sub(usage.bytes.saturating_sub(freed.bytes)));
+        min_limit(available, self.max_object_size)
+    }
+
+    pub fn part_limit(&self) -> Option<u64> {
+        self.max_object_size
+    }
+}
+
+impl Usage {
+    pub fn of(length: u64) -> Self {
+        Usage {
+            bytes: length,
+            objects: 1,
+        }
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            bytes: self.bytes + other.bytes,
+            objects: self.objects + other.objects,
+        }
+    }
+}
+
+impl Sub for Usage {
+    type Output = Usage;
+
+    fn sub(self, other: Usage) -> Usage {
+        Usage {
+            bytes: self.bytes.saturating_sub(other.bytes),
+            objects: self.objects.saturating_sub(other.objects),
+        }
+    }
+}
+
+pub fn min_limit(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+// Fails the read that goes past the limit, so an oversized upload is stopped as soon as it is
+// detected rather than after all of it has been buffered.
+pub struct LimitedReader<R> {
+    inner: R,
+    remaining: Option<u64>,
+    exceeded: bool,
+}
+
+impl<R: Read> LimitedReader<R> {
+    pub fn new(inner: R, limit: Option<u64>) -> Self {
+        LimitedReader {
+            inner,
+            remaining: limit,
+            exceeded: false,
+        }
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = match self.remaining {
+            None => return self.inner.read(buf),
+            Some(remaining) => remaining,
+        };
+        // Asking for one byte more than allowed tells a full upload from an oversized one.
+        let max = remaining.saturating_add(1).min(buf.len() as u64) as usize;
+        let n = self.inner.read(&mut buf[..max])?;
+        if n as u64 > remaining {
+            self.exceeded = true;
+            return Err(io::Error::other("quota exceeded"));
+        }
+        self.remaining = Some(remaining - n as u64);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_limit_should_take_smallest_limit() {
+        let quota = Quota::new().max_bytes(100).max_object_size(30);
+        let usage = Usage {
+            bytes: 80,
+            objects: 2,
+        };
+        assert_eq!(quota.byte_limit(usage, Usage::default()), Some(20));
+        assert_eq!(quota.byte_limit(usage, Usage::of(40)), Some(30));
+        assert_eq!(Quota::new().byte_limit(usage, Usage::default()), None);
+    }
+
+    #[test]
+    fn admits_object_should_count_replaced_object() {
+        let quota = Quota::new().max_objects(2);
+        let usage = Usage {
+            bytes: 0,
+            objects: 2,
+        };
+        assert!(!quota.admits_object(usage, Usage::default()));
+        assert!(quota.admits_object(usage, Usage::of(0)));
+    }
+
+    #[test]
+    fn limited_reader_should_read_data_within_limit() {
+        let mut reader = LimitedReader::new(&b"Luna"[..], Some(4));
+        let mut output = Vec::new();
+        let result = io::copy(&mut reader, &mut output);
+        assert!(result.is_ok(), "LimitedReader rejected data within limit.");
+        assert_eq!(output, b"Luna");
+    }
+
+    #[test]
+    fn limited_reader_should_fail_when_data_exceeds_limit() {
+        let mut reader = LimitedReader::new(&b"Selene"[..], Some(4));
+        let result = io::copy(&mut reader, &mut io::sink());
+        assert!(
+            result.is_err() && reader.is_exceeded(),
+            "LimitedReader accepted data over the limit."
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::ops::{Range, RangeFrom};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteRange {
+    // Bytes from the first offset up to, but excluding, the second one.
+    Bounded(u64, u64),
+    From(u64),
+    // The last N bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    pub fn suffix(length: u64) -> Self {
+        ByteRange::Suffix(length)
+    }
+
+    // Resolves the range against an object of the given length. A range that ends past the end
+    // of the object is cut short, a range that doesn't cover any byte of it is unsatisfiable.
+    pub fn resolve(&self, length: u64) -> Option<Range<u64>> {
+        let range = match *self {
+            ByteRange::Bounded(start, end) if start < end => start..end.min(length),
+            ByteRange::Bounded(_, _) => return None,
+            ByteRange::From(start) => start..length,
+            ByteRange::Suffix(0) => return None,
+            ByteRange::Suffix(n) => length.saturating_sub(n)..length,
+        };
+        if range.start < range.end {
+            Some(range)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<Range<u64>> for ByteRange {
+    fn from(range: Range<u64>) -> Self {
+        ByteRange::Bounded(range.start, range.end)
+    }
+}
+
+impl From<RangeFrom<u64>> for ByteRange {
+    fn from(range: RangeFrom<u64>) -> Self {
+        ByteRange::From(range.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_should_cut_bounded_range_at_object_end() {
+        assert_eq!(ByteRange::from(2..100).resolve(10), Some(2..10));
+    }
+
+    #[test]
+    fn resolve_should_return_rest_of_object_for_open_range() {
+        assert_eq!(ByteRange::from(4..).resolve(10), Some(4..10));
+    }
+
+    #[test]
+    fn resolve_should_return_last_bytes_for_suffix_range() {
+        assert_eq!(ByteRange::suffix(3).resolve(10), Some(7..10));
+        assert_eq!(ByteRange::suffix(30).resolve(10), Some(0..10));
+    }
+
+    #[test]
+    fn resolve_should_reject_unsatisfiable_ranges() {
+        assert_eq!(ByteRange::from(10..).resolve(10), None);
+        assert_eq!(ByteRange::from(5..5).resolve(10), None);
+        assert_eq!(ByteRange::Bounded(6, 5).resolve(10), None);
+        assert_eq!(ByteRange::suffix(0).resolve(10), None);
+        assert_eq!(ByteRange::suffix(1).resolve(0), None);
+    }
+}
@@ -0,0 +1,1224 @@
+use crate::access;
+use crate::access::AccessControlledStore;
+use crate::condition::Conditions;
+use crate::error::{Error, Kind as ErrorKind};
+use crate::event::{Event, NotifyingStore};
+use crate::list::ListOptions;
+use crate::metadata::ObjectMetadata;
+use crate::range::ByteRange;
+use crate::store::{CopyOptions, GetOptions, PutOptions, RemoveOptions, Store};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::http::request::Parts;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Method, Request, Response, StatusCode, Uri};
+use percent_encoding::percent_decode_str;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+const S3_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+const META_PREFIX: &str = "x-amz-meta-";
+const DEFAULT_MAX_KEYS: usize = 1000;
+// Request bodies are read into memory, so larger objects must be sent as multipart uploads.
+const MAX_BODY_SIZE: usize = 256 * 1024 * 1024;
+
+// Serves the store over HTTP with path-style S3 routes. Store calls block, so they are made on
+// tokio's blocking thread pool while holding the store's lock.
+pub async fn serve<S: Store + Send + 'static>(store: S, addr: SocketAddr) -> hyper::Result<()> {
+    let store = Arc::new(Mutex::new(store));
+    let make_service = make_service_fn(move |_| {
+        let store = store.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(store.clone(), req))) }
+    });
+    hyper::Server::bind(&addr).serve(make_service).await
+}
+
+// Serves the store like `serve`, posting every change made to it to a webhook.
+pub async fn serve_with_webhook<S: Store + Send + 'static>(
+    store: S,
+    addr: SocketAddr,
+    url: Uri,
+) -> hyper::Result<()> {
+    let mut store = NotifyingStore::new(store);
+    forward_events(&mut store, url);
+    serve(store, addr).await
+}
+
+// Posts every event of the store to a webhook as a JSON object with `event`, `bucket`, `key`,
+// `size` and `etag` fields, one request per event in the order they happen. Delivery is best
+// effort: events wait in memory for their turn and failed requests aren't retried. Must be
+// called within a tokio runtime.
+pub fn forward_events<S: Store>(store: &mut NotifyingStore<S>, url: Uri) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Event>();
+    store.subscribe(move |event| {
+        let _ = sender.send(event.clone());
+    });
+    tokio::spawn(async move {
+        let client = Client::new();
+        while let Some(event) = receiver.recv().await {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(url.clone())
+                .header("content-type", "application/json")
+                .body(Body::from(event_json(&event)));
+            if let Ok(request) = request {
+                let _ = client.request(request).await;
+            }
+        }
+    });
+}
+
+// Serves the store like `serve`, enforcing its policies. Requests act as the access key of a
+// presigned URL they were made with, and as anonymous otherwise. A presigned URL that has expired
+// or doesn't match the request is denied.
+pub async fn serve_with_access<S: Store + Send + 'static>(
+    store: AccessControlledStore<S>,
+    addr: SocketAddr,
+) -> hyper::Result<()> {
+    let store = Arc::new(Mutex::new(store));
+    let make_service = make_service_fn(move |_| {
+        let store = store.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle_with_access(store.clone(), req)
+            }))
+        }
+    });
+    hyper::Server::bind(&addr).serve(make_service).await
+}
+
+pub async fn handle<S: Store + Send + 'static>(
+    store: Arc<Mutex<S>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    dispatch(store, req, |store, parts, body| {
+        route(store, &parts.method, &parts.uri, &parts.headers, body)
+    })
+    .await
+}
+
+pub async fn handle_with_access<S: Store + Send + 'static>(
+    store: Arc<Mutex<AccessControlledStore<S>>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    dispatch(store, req, |store, parts, body| {
+        let principal = match authenticate(store, &parts.method, &parts.uri) {
+            Err(err) => return error_response(&err, parts.uri.path()),
+            Ok(principal) => principal,
+        };
+        let mut store = store.as_principal(principal.as_deref());
+        route(&mut store, &parts.method, &parts.uri, &parts.headers, body)
+    })
+    .await
+}
+
+// Reads the request body, of at most `MAX_BODY_SIZE` bytes, then answers the request on tokio's
+// blocking thread pool while holding the store's lock.
+async fn dispatch<T, F>(
+    store: Arc<Mutex<T>>,
+    req: Request<Body>,
+    answer: F,
+) -> Result<Response<Body>, Infallible>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut T, &Parts, Bytes) -> Response<Body> + Send + 'static,
+{
+    let (parts, body) = req.into_parts();
+    let too_large = header(&parts.headers, "content-length")
+        .and_then(|length| length.parse::<usize>().ok())
+        .is_some_and(|length| length > MAX_BODY_SIZE);
+    if too_large {
+        return Ok(status(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let body = match read_body(body).await {
+        Err(_) => return Ok(status(StatusCode::BAD_REQUEST)),
+        Ok(None) => return Ok(status(StatusCode::PAYLOAD_TOO_LARGE)),
+        Ok(Some(body)) => body,
+    };
+    let response = tokio::task::spawn_blocking(move || {
+        let mut store = match store.lock() {
+            Err(_) => return status(StatusCode::INTERNAL_SERVER_ERROR),
+            Ok(store) => store,
+        };
+        answer(&mut *store, &parts, body)
+    })
+    .await;
+    Ok(response.unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR)))
+}
+
+// Returns the access key of a presigned request, or `None` for an anonymous one.
+fn authenticate<S: Store>(
+    store: &AccessControlledStore<S>,
+    method: &Method,
+    uri: &Uri,
+) -> Result<Option<String>, Error> {
+    let query = query(uri);
+    if !query.contains_key(access::SIGNATURE_PARAM) {
+        return Ok(None);
+    }
+    let (bucket, key) = split_path(uri.path()).unwrap_or_default();
+    let bucket = bucket.unwrap_or_default();
+    let key = key.unwrap_or_default();
+    match store.verify_presigned(method.as_str(), &bucket, &key, &query, SystemTime::now()) {
+        None => Err(Error::new(ErrorKind::AccessDenied, &bucket, &key)),
+        Some(access_key) => Ok(Some(access_key.into())),
+    }
+}
+
+fn route<S: Store>(
+    store: &mut S,
+    method: &Method,
+    uri: &hyper::Uri,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let (bucket, key) = match split_path(uri.path()) {
+        None => return status(StatusCode::BAD_REQUEST),
+        Some(path) => path,
+    };
+    let query = query(uri);
+    let result = match (method, bucket.as_deref(), key.as_deref()) {
+        (&Method::GET, None, None) => Ok(list_buckets(store)),
+        (&Method::PUT, Some(bucket), None) => create_bucket(store, bucket),
+        (&Method::DELETE, Some(bucket), None) => delete_bucket(store, bucket),
+        (&Method::HEAD, Some(bucket), None) => head_bucket(store, bucket),
+        (&Method::GET, Some(bucket), None) => list_objects(store, bucket, &query),
+        (&Method::PUT, Some(bucket), Some(key)) => put_object(store, bucket, key, headers, body),
+        (&Method::GET, Some(bucket), Some(key)) => get_object(store, bucket, key, &query, headers),
+        (&Method::HEAD, Some(bucket), Some(key)) => head_object(store, bucket, key, headers),
+        (&Method::DELETE, Some(bucket), Some(key)) => delete_object(store, bucket, key, headers),
+        _ => return status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+    result.unwrap_or_else(|err| error_response(&err, uri.path()))
+}
+
+fn list_buckets<S: Store>(store: &S) -> Response<Body> {
+    let mut buckets = store.list_buckets();
+    buckets.sort_unstable();
+    let mut xml = format!(
+        "<ListAllMyBucketsResult xmlns=\"{}\"><Buckets>",
+        S3_NAMESPACE
+    );
+    for bucket in buckets {
+        xml.push_str(&format!("<Bucket><Name>{}</Name></Bucket>", escape(bucket)));
+    }
+    xml.push_str("</Buckets></ListAllMyBucketsResult>");
+    xml_response(StatusCode::OK, xml)
+}
+
+fn create_bucket<S: Store>(store: &mut S, bucket: &str) -> Result<Response<Body>, Error> {
+    store.create_bucket(bucket)?;
+    Ok(status(StatusCode::OK))
+}
+
+fn delete_bucket<S: Store>(store: &mut S, bucket: &str) -> Result<Response<Body>, Error> {
+    store.delete_bucket(bucket)?;
+    Ok(status(StatusCode::NO_CONTENT))
+}
+
+fn head_bucket<S: Store>(store: &S, bucket: &str) -> Result<Response<Body>, Error> {
+    if !store.list_buckets().contains(&bucket) {
+        return Err(Error::new(ErrorKind::BucketNotFound, bucket, ""));
+    }
+    Ok(status(StatusCode::OK))
+}
+
+fn list_objects<S: Store>(
+    store: &S,
+    bucket: &str,
+    query: &HashMap<String, String>,
+) -> Result<Response<Body>, Error> {
+    let max_keys = query
+        .get("max-keys")
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_KEYS);
+    let mut options = ListOptions::new().max_keys(max_keys);
+    if let Some(prefix) = query.get("prefix") {
+        options = options.prefix(prefix);
+    }
+    if let Some(delimiter) = query.get("delimiter") {
+        options = options.delimiter(delimiter);
+    }
+    if let Some(token) = query.get("continuation-token") {
+        options = options.continuation_token(token);
+    }
+    let list = store.list_objects_with(bucket, options)?;
+    let mut xml = format!(
+        "<ListBucketResult xmlns=\"{}\"><Name>{}</Name><Prefix>{}</Prefix>",
+        S3_NAMESPACE,
+        escape(bucket),
+        escape(query.get("prefix").map(|s| s.as_str()).unwrap_or(""))
+    );
+    if let Some(delimiter) = query.get("delimiter") {
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", escape(delimiter)));
+    }
+    xml.push_str(&format!(
+        "<MaxKeys>{}</MaxKeys><KeyCount>{}</KeyCount><IsTruncated>{}</IsTruncated>",
+        max_keys,
+        list.objects.len() + list.common_prefixes.len(),
+        list.is_truncated
+    ));
+    if let Some(token) = query.get("continuation-token") {
+        xml.push_str(&format!(
+            "<ContinuationToken>{}</ContinuationToken>",
+            escape(token)
+        ));
+    }
+    if let Some(token) = &list.next_continuation_token {
+        xml.push_str(&format!(
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            escape(token)
+        ));
+    }
+    for name in &list.objects {
+        let metadata = store.head_object(bucket, name).unwrap_or_default();
+        xml.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>\"{}\"</ETag>\
+             <Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            escape(name),
+            iso8601(metadata.modified),
+            metadata.etag,
+            metadata.length
+        ));
+    }
+    for prefix in &list.common_prefixes {
+        xml.push_str(&format!(
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            escape(prefix)
+        ));
+    }
+    xml.push_str("</ListBucketResult>");
+    Ok(xml_response(StatusCode::OK, xml))
+}
+
+fn put_object<S: Store>(
+    store: &mut S,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response<Body>, Error> {
+    let mut options = PutOptions::new().conditions(conditions(headers));
+    if let Some(content_type) = header(headers, "content-type") {
+        options = options.content_type(content_type);
+    }
+    // Signed requests carry the SHA-256 of the payload in hex, other values mark unsigned or
+    // streaming payloads.
+    if let Some(checksum) = header(headers, "x-amz-content-sha256") {
+        if checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            options = options.checksum(checksum);
+        }
+    }
+    for (name, value) in headers {
+        if let (Some(key), Ok(value)) = (name.as_str().strip_prefix(META_PREFIX), value.to_str()) {
+            options = options.metadata(key, value);
+        }
+    }
+    // Tags come URL-encoded like a query string, as in `env=prod&team=infra`.
+    if let Some(tagging) = header(headers, "x-amz-tagging") {
+        for (key, value) in form_urlencoded::parse(tagging.as_bytes()) {
+            options = options.tag(&key, &value);
+        }
+    }
+    if let Some(source) = header(headers, "x-amz-copy-source") {
+        return copy_object(store, bucket, key, source, headers, options);
+    }
+    let mut reader = Box::new(std::io::Cursor::new(body)) as Box<dyn Read>;
+    store.put_object_with(bucket, key, &mut reader, options)?;
+    let mut response = status(StatusCode::OK);
+    if let Some(metadata) = store.head_object(bucket, key) {
+        insert_header(&mut response, "etag", &format!("\"{}\"", metadata.etag));
+        insert_header(&mut response, "x-amz-version-id", &metadata.version_id);
+    }
+    Ok(response)
+}
+
+// Copies the object named by `x-amz-copy-source`, as in `/bucket/key`. The copy keeps the
+// metadata of the source unless `x-amz-metadata-directive` is `REPLACE`. Copying a version other
+// than the current one isn't supported.
+fn copy_object<S: Store>(
+    store: &mut S,
+    bucket: &str,
+    key: &str,
+    source: &str,
+    headers: &HeaderMap,
+    options: PutOptions,
+) -> Result<Response<Body>, Error> {
+    if source.contains("?versionId=") {
+        return Ok(status(StatusCode::NOT_IMPLEMENTED));
+    }
+    let (src_bucket, src_key) = match split_path(source) {
+        Some((Some(src_bucket), Some(src_key))) => (src_bucket, src_key),
+        _ => return Err(Error::new(ErrorKind::InvalidObjectName, bucket, key)),
+    };
+    let mut copy_options = CopyOptions::new();
+    if header(headers, "x-amz-metadata-directive") == Some("REPLACE") {
+        copy_options = copy_options.replace_metadata(options);
+    }
+    store.copy_object_with(&src_bucket, &src_key, bucket, key, copy_options)?;
+    let metadata = head(store, bucket, key)?;
+    let xml = format!(
+        "<CopyObjectResult><LastModified>{}</LastModified><ETag>&quot;{}&quot;</ETag></CopyObjectResult>",
+        iso8601(metadata.modified),
+        metadata.etag
+    );
+    let mut response = xml_response(StatusCode::OK, xml);
+    insert_header(&mut response, "x-amz-version-id", &metadata.version_id);
+    Ok(response)
+}
+
+fn get_object<S: Store>(
+    store: &S,
+    bucket: &str,
+    key: &str,
+    query: &HashMap<String, String>,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, Error> {
+    if !query.contains_key("versionId") {
+        if let Some(response) = check_read(store, bucket, key, headers)? {
+            return Ok(response);
+        }
+    }
+    let range = header(headers, "range").and_then(parse_range);
+    let mut data = Vec::<u8>::new();
+    let (mut response, metadata) = match (range, query.get("versionId")) {
+        (Some(range), None) => {
+            let metadata = head(store, bucket, key)?;
+            let resolved = match range.resolve(metadata.length) {
+                None => return Err(Error::new(ErrorKind::InvalidRange, bucket, key)),
+                Some(resolved) => resolved,
+            };
+            read_all(
+                store.get_object_range(bucket, key, range)?,
+                &mut data,
+                bucket,
+                key,
+            )?;
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let content_range = format!(
+                "bytes {}-{}/{}",
+                resolved.start,
+                resolved.end - 1,
+                metadata.length
+            );
+            insert_header(&mut response, "content-range", &content_range);
+            (response, metadata)
+        }
+        (range, Some(version_id)) => {
+            let options = GetOptions::new()
+                .version_id(version_id)
+                .conditions(conditions(headers));
+            let reader = store.get_object_with(bucket, key, options)?;
+            let metadata = store
+                .head_object_version(bucket, key, version_id)
+                .unwrap_or_default();
+            read_all(reader, &mut data, bucket, key)?;
+            let mut response = Response::new(Body::empty());
+            // The version is read whole, so the range is cut from it here.
+            if let Some(range) = range {
+                let length = data.len() as u64;
+                let resolved = match range.resolve(length) {
+                    None => return Err(Error::new(ErrorKind::InvalidRange, bucket, key)),
+                    Some(resolved) => resolved,
+                };
+                data = data[resolved.start as usize..resolved.end as usize].to_vec();
+                *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+                let content_range =
+                    format!("bytes {}-{}/{}", resolved.start, resolved.end - 1, length);
+                insert_header(&mut response, "content-range", &content_range);
+            }
+            (response, metadata)
+        }
+        (None, None) => {
+            let reader = store.get_object_with(bucket, key, GetOptions::new())?;
+            let metadata = store.head_object(bucket, key).unwrap_or_default();
+            read_all(reader, &mut data, bucket, key)?;
+            (Response::new(Body::empty()), metadata)
+        }
+    };
+    insert_object_headers(&mut response, &metadata);
+    insert_header(&mut response, "content-length", &data.len().to_string());
+    *response.body_mut() = Body::from(data);
+    Ok(response)
+}
+
+fn head_object<S: Store>(
+    store: &S,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, Error> {
+    if let Some(response) = check_read(store, bucket, key, headers)? {
+        return Ok(response);
+    }
+    let metadata = head(store, bucket, key)?;
+    let mut response = status(StatusCode::OK);
+    insert_object_headers(&mut response, &metadata);
+    insert_header(
+        &mut response,
+        "content-length",
+        &metadata.length.to_string(),
+    );
+    Ok(response)
+}
+
+fn delete_object<S: Store>(
+    store: &mut S,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, Error> {
+    let options = RemoveOptions::new().conditions(conditions(headers));
+    store.remove_object_with(bucket, key, options)?;
+    Ok(status(StatusCode::NO_CONTENT))
+}
+
+fn head<S: Store>(store: &S, bucket: &str, key: &str) -> Result<ObjectMetadata, Error> {
+    match store.head_object(bucket, key) {
+        Some(metadata) => Ok(metadata),
+        None if store.list_buckets().contains(&bucket) => {
+            Err(Error::new(ErrorKind::ObjectNotFound, bucket, key))
+        }
+        None => Err(Error::new(ErrorKind::BucketNotFound, bucket, key)),
+    }
+}
+
+// Checks the conditions of a read of the latest version. An unchanged object gets a response
+// without a body when the client asked for it only if it changed.
+fn check_read<S: Store>(
+    store: &S,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+) -> Result<Option<Response<Body>>, Error> {
+    let conditions = conditions(headers);
+    if conditions.is_empty() {
+        return Ok(None);
+    }
+    let metadata = head(store, bucket, key)?;
+    if conditions.is_not_modified(Some(&metadata)) {
+        let mut response = status(StatusCode::NOT_MODIFIED);
+        insert_header(&mut response, "etag", &format!("\"{}\"", metadata.etag));
+        insert_header(
+            &mut response,
+            "last-modified",
+            &httpdate::fmt_http_date(metadata.modified),
+        );
+        return Ok(Some(response));
+    }
+    conditions.check(bucket, key, Some(&metadata))?;
+    Ok(None)
+}
+
+fn read_all(
+    mut reader: Box<dyn Read + '_>,
+    data: &mut Vec<u8>,
+    bucket: &str,
+    key: &str,
+) -> Result<(), Error> {
+    match reader.read_to_end(data) {
+        Err(e) => Err(Error::io(e, bucket, key)),
+        Ok(_) => Ok(()),
+    }
+}
+
+// Maps store errors to the status codes and error codes S3 uses for them.
+fn error_code(kind: &ErrorKind) -> (StatusCode, &'static str) {
+    match kind {
+        ErrorKind::BucketNotFound => (StatusCode::NOT_FOUND, "NoSuchBucket"),
+        ErrorKind::BucketAlreadyExists => (StatusCode::CONFLICT, "BucketAlreadyOwnedByYou"),
+        ErrorKind::BucketNotEmpty => (StatusCode::CONFLICT, "BucketNotEmpty"),
+        ErrorKind::InvalidBucketName => (StatusCode::BAD_REQUEST, "InvalidBucketName"),
+        ErrorKind::InvalidObjectName => (StatusCode::BAD_REQUEST, "InvalidArgument"),
+        ErrorKind::InvalidTag => (StatusCode::BAD_REQUEST, "InvalidTag"),
+        ErrorKind::ObjectNotFound => (StatusCode::NOT_FOUND, "NoSuchKey"),
+        ErrorKind::VersionNotFound => (StatusCode::NOT_FOUND, "NoSuchVersion"),
+        ErrorKind::UploadNotFound => (StatusCode::NOT_FOUND, "NoSuchUpload"),
+        ErrorKind::ObjectAlreadyExist => (StatusCode::PRECONDITION_FAILED, "PreconditionFailed"),
+        ErrorKind::ChecksumMismatch => (StatusCode::BAD_REQUEST, "XAmzContentSHA256Mismatch"),
+        ErrorKind::InvalidRange => (StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange"),
+        ErrorKind::InvalidPart => (StatusCode::BAD_REQUEST, "InvalidPart"),
+        ErrorKind::QuotaExceeded => (StatusCode::INSUFFICIENT_STORAGE, "QuotaExceeded"),
+        ErrorKind::PreconditionFailed => (StatusCode::PRECONDITION_FAILED, "PreconditionFailed"),
+        ErrorKind::AuthenticationFailed => (StatusCode::FORBIDDEN, "AccessDenied"),
+        ErrorKind::AccessDenied => (StatusCode::FORBIDDEN, "AccessDenied"),
+        ErrorKind::IO => (StatusCode::INTERNAL_SERVER_ERROR, "InternalError"),
+    }
+}
+
+fn error_response(err: &Error, resource: &str) -> Response<Body> {
+    let (status, code) = error_code(err.kind());
+    let xml = format!(
+        "<Error><Code>{}</Code><Message>{}</Message><Resource>{}</Resource></Error>",
+        code,
+        escape(&err.to_string()),
+        escape(resource)
+    );
+    xml_response(status, xml)
+}
+
+// Reads a body chunk by chunk, none when it grows past `MAX_BODY_SIZE`.
+async fn read_body(mut body: Body) -> Result<Option<Bytes>, hyper::Error> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > MAX_BODY_SIZE {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(Bytes::from(data)))
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn xml_response(status: StatusCode, xml: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}",
+        xml
+    )));
+    *response.status_mut() = status;
+    insert_header(&mut response, "content-type", "application/xml");
+    response
+}
+
+fn insert_object_headers(response: &mut Response<Body>, metadata: &ObjectMetadata) {
+    insert_header(response, "etag", &format!("\"{}\"", metadata.etag));
+    insert_header(
+        response,
+        "last-modified",
+        &httpdate::fmt_http_date(metadata.modified),
+    );
+    insert_header(response, "x-amz-version-id", &metadata.version_id);
+    insert_header(response, "accept-ranges", "bytes");
+    if let Some(content_type) = &metadata.content_type {
+        insert_header(response, "content-type", content_type);
+    }
+    for (key, value) in &metadata.user {
+        insert_header(response, &format!("{}{}", META_PREFIX, key), value);
+    }
+    if !metadata.tags.is_empty() {
+        insert_header(
+            response,
+            "x-amz-tagging-count",
+            &metadata.tags.len().to_string(),
+        );
+    }
+}
+
+// Headers that can't be represented in HTTP are left out.
+fn insert_header(response: &mut Response<Body>, name: &str, value: &str) {
+    if let (Ok(name), Ok(value)) = (
+        HeaderName::from_bytes(name.as_bytes()),
+        HeaderValue::from_str(value),
+    ) {
+        response.headers_mut().insert(name, value);
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+// Reads the HTTP conditional headers. Dates that can't be parsed are ignored, as HTTP asks.
+fn conditions(headers: &HeaderMap) -> Conditions {
+    let mut conditions = Conditions::new();
+    if let Some(etag) = header(headers, "if-match") {
+        conditions = conditions.if_match(unquote(etag));
+    }
+    if let Some(etag) = header(headers, "if-none-match") {
+        conditions = conditions.if_none_match(unquote(etag));
+    }
+    let date = |name| header(headers, name).and_then(|date| httpdate::parse_http_date(date).ok());
+    if let Some(time) = date("if-modified-since") {
+        conditions = conditions.if_modified_since(time);
+    }
+    if let Some(time) = date("if-unmodified-since") {
+        conditions = conditions.if_unmodified_since(time);
+    }
+    conditions
+}
+
+// ETags are sent quoted, weak ones with a W/ prefix. Only a single ETag is supported.
+fn unquote(etag: &str) -> &str {
+    let etag = etag.trim();
+    let etag = etag.strip_prefix("W/").unwrap_or(etag);
+    etag.trim_matches('"')
+}
+
+fn query(uri: &Uri) -> HashMap<String, String> {
+    uri.query()
+        .map(|q| form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+        .unwrap_or_default()
+}
+
+// Splits a path-style request path into its bucket and key.
+fn split_path(path: &str) -> Option<(Option<String>, Option<String>)> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let (bucket, key) = match path.find('/') {
+        None => (path, ""),
+        Some(i) => (&path[..i], &path[i + 1..]),
+    };
+    let decode = |s: &str| match s {
+        "" => Some(None),
+        s => percent_decode_str(s)
+            .decode_utf8()
+            .ok()
+            .map(|s| Some(s.into_owned())),
+    };
+    Some((decode(bucket)?, decode(key)?))
+}
+
+// Parses a single range of a `Range` header, e.g. `bytes=0-99`, `bytes=100-` or `bytes=-100`.
+fn parse_range(value: &str) -> Option<ByteRange> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    match (start, end) {
+        ("", n) => n.parse::<u64>().ok().map(ByteRange::suffix),
+        (start, "") => start
+            .parse::<u64>()
+            .ok()
+            .map(|start| ByteRange::from(start..)),
+        (start, end) => {
+            let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+            Some(ByteRange::Bounded(start, end.checked_add(1)?))
+        }
+    }
+}
+
+fn event_json(event: &Event) -> String {
+    format!(
+        "{{\"event\":\"{}\",\"bucket\":{},\"key\":{},\"size\":{},\"etag\":{}}}",
+        event.kind.name(),
+        json_string(&event.bucket),
+        json_string(&event.key),
+        event.size,
+        json_string(&event.etag)
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Formats a time as ISO 8601 in UTC with millisecond precision, as used in S3 listings.
+fn iso8601(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = duration.as_secs();
+    let days = (secs / 86_400) as i64;
+    let (hour, minute, second) = (secs % 86_400 / 3600, secs % 3600 / 60, secs % 60);
+    // Converts days since epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        duration.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::{Action, Policy};
+    use crate::checksum;
+    use crate::in_memory::store::InMemoryStore;
+    use std::time::Duration;
+
+    fn request(method: Method, uri: &str, body: &'static [u8]) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn send(store: &Arc<Mutex<InMemoryStore>>, req: Request<Body>) -> (StatusCode, String) {
+        let response = handle(store.clone(), req).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    fn get_store() -> Arc<Mutex<InMemoryStore>> {
+        Arc::new(Mutex::new(InMemoryStore::new()))
+    }
+
+    #[tokio::test]
+    async fn put_then_get_object_should_return_identical_data() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        let (status, _) = send(
+            &store,
+            request(Method::PUT, "/earth/moon%20phases", b"Luna"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = send(&store, request(Method::GET, "/earth/moon%20phases", b"")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Luna", "Server returned wrong object data.");
+    }
+
+    #[tokio::test]
+    async fn get_object_should_return_s3_error_when_object_did_not_exist() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        let (status, body) = send(&store, request(Method::GET, "/earth/moon", b"")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(
+            body.contains("<Code>NoSuchKey</Code>"),
+            "Server returned wrong error body: {}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn get_object_with_range_should_return_partial_content() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        send(&store, request(Method::PUT, "/earth/moon", b"Selene")).await;
+        let req = Request::builder()
+            .uri("/earth/moon")
+            .header("range", "bytes=1-2")
+            .body(Body::empty())
+            .unwrap();
+        let response = handle(store.clone(), req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 1-2/6");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"el");
+    }
+
+    #[tokio::test]
+    async fn get_object_version_should_return_metadata_and_range_of_version() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        store.lock().unwrap().set_versioning("earth", true).unwrap();
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("/earth/moon")
+            .header("content-type", "text/plain")
+            .body(Body::from("Selene"))
+            .unwrap();
+        send(&store, req).await;
+        let version_id = store
+            .lock()
+            .unwrap()
+            .head_object("earth", "moon")
+            .unwrap()
+            .version_id;
+        send(&store, request(Method::PUT, "/earth/moon", b"Luna")).await;
+        let req = Request::builder()
+            .uri(format!("/earth/moon?versionId={}", version_id))
+            .header("range", "bytes=1-2")
+            .body(Body::empty())
+            .unwrap();
+        let response = handle(store.clone(), req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 1-2/6");
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        assert_eq!(response.headers()["x-amz-version-id"], version_id.as_str());
+        assert_eq!(
+            response.headers()["etag"],
+            format!("\"{}\"", checksum::digest(b"Selene")).as_str()
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"el");
+    }
+
+    #[tokio::test]
+    async fn conditional_requests_should_check_current_etag() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        send(&store, request(Method::PUT, "/earth/moon", b"Luna")).await;
+        let etag = format!("\"{}\"", checksum::digest(b"Luna"));
+        let conditional = |method: Method, name: &str, value: &str, body: &'static [u8]| {
+            Request::builder()
+                .method(method)
+                .uri("/earth/moon")
+                .header(name, value)
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let req = conditional(Method::PUT, "if-none-match", "*", b"Lune");
+        assert_eq!(send(&store, req).await.0, StatusCode::PRECONDITION_FAILED);
+        let req = conditional(Method::GET, "if-none-match", &etag, b"");
+        assert_eq!(send(&store, req).await.0, StatusCode::NOT_MODIFIED);
+        let req = conditional(Method::GET, "if-match", "\"0\"", b"");
+        let (status, body) = send(&store, req).await;
+        assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+        assert!(
+            body.contains("<Code>PreconditionFailed</Code>"),
+            "Server returned wrong error body: {}",
+            body
+        );
+        let req = conditional(Method::DELETE, "if-match", "\"0\"", b"");
+        assert_eq!(send(&store, req).await.0, StatusCode::PRECONDITION_FAILED);
+        let req = conditional(Method::DELETE, "if-match", &etag, b"");
+        assert_eq!(send(&store, req).await.0, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn put_object_should_keep_content_type_and_user_metadata() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("/earth/moon")
+            .header("content-type", "text/plain")
+            .header("x-amz-meta-discovered-by", "Galileo")
+            .body(Body::from("Luna"))
+            .unwrap();
+        send(&store, req).await;
+        let response = handle(store.clone(), request(Method::HEAD, "/earth/moon", b""))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        assert_eq!(response.headers()["x-amz-meta-discovered-by"], "Galileo");
+        assert_eq!(response.headers()["content-length"], "4");
+        assert_eq!(
+            response.headers()["etag"],
+            format!("\"{}\"", checksum::digest(b"Luna")).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn put_object_should_reject_body_larger_than_limit() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("/earth/moon")
+            .header("content-length", (MAX_BODY_SIZE + 1).to_string())
+            .body(Body::from("Luna"))
+            .unwrap();
+        assert_eq!(send(&store, req).await.0, StatusCode::PAYLOAD_TOO_LARGE);
+        let (status, _) = send(&store, request(Method::HEAD, "/earth/moon", b"")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn put_object_with_copy_source_should_copy_object() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        send(&store, request(Method::PUT, "/mars", b"")).await;
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("/earth/moon")
+            .header("content-type", "text/plain")
+            .body(Body::from("Luna"))
+            .unwrap();
+        send(&store, req).await;
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("/mars/moon")
+            .header("x-amz-copy-source", "/earth/moon")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&store, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            body.contains("<CopyObjectResult>"),
+            "Server did not return a copy result."
+        );
+        let response = handle(store.clone(), request(Method::GET, "/mars/moon", b""))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"Luna", "Server did not copy object data.");
+    }
+
+    #[tokio::test]
+    async fn put_object_with_versioned_copy_source_should_not_be_implemented() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        send(&store, request(Method::PUT, "/earth/moon", b"Luna")).await;
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("/earth/luna")
+            .header("x-amz-copy-source", "/earth/moon?versionId=null")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&store, req).await.0, StatusCode::NOT_IMPLEMENTED);
+    }
+
+    #[tokio::test]
+    async fn put_object_should_keep_tags() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        let tagged = |tagging| {
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/earth/moon")
+                .header("x-amz-tagging", tagging)
+                .body(Body::from("Luna"))
+                .unwrap()
+        };
+        let (status, body) = send(&store, tagged("kind=moon&orbit=Earth%20Sun")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(
+            body.contains("<Code>InvalidTag</Code>"),
+            "Server returned wrong error body: {}",
+            body
+        );
+        assert_eq!(
+            send(&store, tagged("kind=moon&orbit=Earth")).await.0,
+            StatusCode::OK
+        );
+        let response = handle(store.clone(), request(Method::HEAD, "/earth/moon", b""))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["x-amz-tagging-count"],
+            "2",
+            "Server did not keep tags."
+        );
+    }
+
+    #[tokio::test]
+    async fn put_object_should_reject_mismatched_content_sha256() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        let req = Request::builder()
+            .method(Method::PUT)
+            .uri("/earth/moon")
+            .header("x-amz-content-sha256", checksum::digest(b"Luna"))
+            .body(Body::from("Lune"))
+            .unwrap();
+        let (status, body) = send(&store, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("<Code>XAmzContentSHA256Mismatch</Code>"));
+    }
+
+    #[tokio::test]
+    async fn list_objects_should_return_list_bucket_result() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/jupiter", b"")).await;
+        for uri in &[
+            "/jupiter/moons/io",
+            "/jupiter/moons/europa",
+            "/jupiter/rings",
+        ] {
+            send(&store, request(Method::PUT, uri, b"")).await;
+        }
+        let (status, body) = send(
+            &store,
+            request(Method::GET, "/jupiter?list-type=2&delimiter=%2F", b""),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<Contents><Key>rings</Key>"), "{}", body);
+        assert!(body.contains("<CommonPrefixes><Prefix>moons/</Prefix></CommonPrefixes>"));
+        assert!(body.contains("<KeyCount>2</KeyCount>"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn delete_bucket_should_return_no_such_bucket_when_bucket_did_not_exist() {
+        let store = get_store();
+        let (status, body) = send(&store, request(Method::DELETE, "/pluto", b"")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body.contains("<Code>NoSuchBucket</Code>"));
+    }
+
+    #[tokio::test]
+    async fn create_bucket_should_return_conflict_when_bucket_already_exists() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        let (status, body) = send(&store, request(Method::PUT, "/earth", b"")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains("<Code>BucketAlreadyOwnedByYou</Code>"));
+        let (status, body) = send(&store, request(Method::PUT, "/e", b"")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("<Code>InvalidBucketName</Code>"));
+    }
+
+    #[tokio::test]
+    async fn delete_bucket_should_return_conflict_when_bucket_is_not_empty() {
+        let store = get_store();
+        send(&store, request(Method::PUT, "/earth", b"")).await;
+        send(&store, request(Method::PUT, "/earth/moon", b"Luna")).await;
+        let (status, body) = send(&store, request(Method::DELETE, "/earth", b"")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains("<Code>BucketNotEmpty</Code>"));
+        send(&store, request(Method::DELETE, "/earth/moon", b"")).await;
+        let (status, _) = send(&store, request(Method::DELETE, "/earth", b"")).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn serve_should_answer_requests_on_localhost() {
+        let addr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        tokio::spawn(serve(InMemoryStore::new(), addr));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let client = hyper::Client::new();
+        let req = Request::put(format!("http://{}/earth", addr).as_str());
+        let response = client.request(req.body(Body::empty()).unwrap()).await;
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+        let uri = format!("http://{}/earth/moon", addr);
+        let req = Request::put(uri.as_str()).body(Body::from("Luna")).unwrap();
+        assert_eq!(client.request(req).await.unwrap().status(), StatusCode::OK);
+        let response = client.get(uri.parse().unwrap()).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"Luna");
+    }
+
+    #[tokio::test]
+    async fn handle_with_access_should_accept_presigned_requests() {
+        let mut store = AccessControlledStore::new(InMemoryStore::new());
+        store.add_key("alice", "wonderland");
+        store.set_policy("earth", Policy::new().allow("alice", "", &Action::ALL));
+        let store = Arc::new(Mutex::new(store));
+        let send = |req| {
+            let store = store.clone();
+            async move {
+                let response = handle_with_access(store, req).await.unwrap();
+                let status = response.status();
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                (status, String::from_utf8_lossy(&body).into_owned())
+            }
+        };
+        let presign = |method: &str, key: &str| {
+            let store = store.lock().unwrap();
+            let expires = Duration::from_secs(60);
+            store
+                .presign("alice", method, "earth", key, expires)
+                .unwrap()
+        };
+        let (status, body) = send(request(Method::PUT, "/earth", b"")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("<Code>AccessDenied</Code>"));
+        let (status, _) = send(request(Method::PUT, &presign("PUT", ""), b"")).await;
+        assert_eq!(status, StatusCode::OK);
+        let uri = presign("PUT", "moon phases");
+        let (status, _) = send(request(Method::PUT, &uri, b"Luna")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(request(Method::DELETE, &uri, b"")).await;
+        assert_eq!(
+            status,
+            StatusCode::FORBIDDEN,
+            "Server accepted a presigned URL for another method."
+        );
+        let (status, body) = send(request(Method::GET, &presign("GET", "moon phases"), b"")).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "Luna"));
+        let (status, _) = send(request(Method::GET, "/earth/moon%20phases", b"")).await;
+        assert_eq!(
+            status,
+            StatusCode::FORBIDDEN,
+            "Server kept the principal of a previous request."
+        );
+        let (status, body) = send(request(Method::GET, "/earth", b"")).await;
+        assert_eq!(
+            status,
+            StatusCode::FORBIDDEN,
+            "Server did not deny an anonymous listing."
+        );
+        assert!(body.contains("<Code>AccessDenied</Code>"));
+        let (status, _) = send(request(Method::GET, &presign("GET", ""), b"")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn forward_events_should_post_changes_to_webhook() {
+        let (sender, mut received) = mpsc::unbounded_channel::<String>();
+        let make_service = make_service_fn(move |_| {
+            let sender = sender.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let sender = sender.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(req.into_body()).await?;
+                        let _ = sender.send(String::from_utf8_lossy(&body).into_owned());
+                        Ok::<_, hyper::Error>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let webhook = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}/events", webhook.local_addr());
+        tokio::spawn(webhook);
+
+        let mut store = NotifyingStore::new(InMemoryStore::new());
+        forward_events(&mut store, url.parse().unwrap());
+        let store = Arc::new(Mutex::new(store));
+        let _ = handle(store.clone(), request(Method::PUT, "/earth", b"")).await;
+        let _ = handle(store.clone(), request(Method::PUT, "/earth/moon", b"Luna")).await;
+        let timeout = Duration::from_secs(5);
+        assert!(
+            matches!(tokio::time::timeout(timeout, received.recv()).await, Ok(Some(body)) if body.contains("\"BucketCreated\""))
+        );
+        assert_eq!(
+            tokio::time::timeout(timeout, received.recv())
+                .await
+                .ok()
+                .flatten(),
+            Some(format!(
+                "{{\"event\":\"ObjectPut\",\"bucket\":\"earth\",\"key\":\"moon\",\"size\":4,\"etag\":\"{}\"}}",
+                checksum::digest(b"Luna")
+            )),
+            "Server did not post the write to the webhook."
+        );
+    }
+
+    #[test]
+    fn json_string_should_escape_quotes_and_control_characters() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+    }
+
+    #[test]
+    fn parse_range_should_parse_http_byte_ranges() {
+        assert_eq!(parse_range("bytes=0-99"), Some(ByteRange::from(0..100)));
+        assert_eq!(parse_range("bytes=100-"), Some(ByteRange::from(100..)));
+        assert_eq!(parse_range("bytes=-100"), Some(ByteRange::suffix(100)));
+        assert_eq!(parse_range("items=0-1"), None);
+    }
+
+    #[test]
+    fn iso8601_should_format_utc_time() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_255_369_830_123);
+        assert_eq!(iso8601(time), "2009-10-12T17:50:30.123Z");
+    }
+}
@@ -0,0 +1,95 @@
+use crate::error::Result;
+use crate::list::{ListOptions, OwnedObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::store::{DeleteBucketOptions, GetOptions, PutOptions, RemoveOptions};
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use std::io::Read;
+
+// Readers returned by a shared store own what they read, so they can outlive the call that
+// produced them and be moved to another thread.
+pub type SharedReader = Box<dyn Read + Send>;
+
+// A `Store` that can be shared between threads. Every method takes `&self` and returns owned
+// values, so concurrent readers and writers only contend for as long as each call needs.
+pub trait SharedStore: Send + Sync {
+    fn list_buckets(&self) -> Vec<String>;
+    fn create_bucket(&self, name: &str) -> Result<()>;
+    fn delete_bucket(&self, name: &str) -> Result<()> {
+        self.delete_bucket_with(name, DeleteBucketOptions::new())
+    }
+    fn delete_bucket_with(&self, name: &str, options: DeleteBucketOptions) -> Result<()>;
+    fn list_objects(&self, bucket: &str) -> Option<Vec<String>> {
+        self.list_objects_with(bucket, ListOptions::new())
+            .map(|list| list.objects)
+    }
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Option<OwnedObjectList>;
+    fn insert_object(&self, bucket: &str, name: &str, reader: &mut Box<dyn Read>) -> Result<()> {
+        self.insert_object_with(bucket, name, reader, PutOptions::new())
+    }
+    fn insert_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()>;
+    fn put_object(&self, bucket: &str, name: &str, reader: &mut Box<dyn Read>) -> Result<()> {
+        self.put_object_with(bucket, name, reader, PutOptions::new())
+    }
+    fn put_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()>;
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata>;
+    fn set_object_tags(&self, bucket: &str, name: &str, tags: Tags) -> Result<()>;
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<String>> {
+        let names = self.list_objects(bucket)?;
+        Some(
+            names
+                .into_iter()
+                .filter(|name| {
+                    matches!(self.head_object(bucket, name), Some(metadata) if query.matches(&metadata.tags))
+                })
+                .collect(),
+        )
+    }
+    fn get_object(&self, bucket: &str, name: &str) -> Option<SharedReader> {
+        self.get_object_with(bucket, name, GetOptions::new()).ok()
+    }
+    fn get_object_with(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<SharedReader>;
+    fn get_object_range(&self, bucket: &str, name: &str, range: ByteRange) -> Result<SharedReader>;
+    fn remove_object(&self, bucket: &str, name: &str) -> Result<()> {
+        self.remove_object_with(bucket, name, RemoveOptions::new())
+    }
+    fn remove_object_with(&self, bucket: &str, name: &str, options: RemoveOptions) -> Result<()>;
+    fn remove_object_version(&self, bucket: &str, name: &str, version_id: &str) -> Result<()>;
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>>;
+    fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String>;
+    fn upload_part(
+        &self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String>;
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>>;
+    fn abort_multipart_upload(&self, bucket: &str, name: &str, upload_id: &str) -> Result<()>;
+    fn complete_multipart_upload(&self, bucket: &str, name: &str, upload_id: &str) -> Result<()>;
+}
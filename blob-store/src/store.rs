@@ -1,14 +1,1470 @@
-use crate::error::Result;
-use std::io::Read;
+use crate::checksum::VerifyingReader;
+use crate::condition::Conditions;
+use crate::error::{Error, Kind as ErrorKind, Result};
+use crate::list::{ListOptions, ObjectList};
+use crate::metadata::ObjectMetadata;
+use crate::multipart::MultipartUpload;
+use crate::range::ByteRange;
+use crate::tag::{TagQuery, Tags};
+use crate::version::ObjectVersion;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    content_type: Option<String>,
+    metadata: HashMap<String, String>,
+    checksum: Option<String>,
+    conditions: Conditions,
+    tags: Tags,
+}
+
+impl PutOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    // Expected SHA-256 digest of the uploaded data, in hex. The upload is rejected if the data
+    // doesn't match it.
+    pub fn checksum(mut self, checksum: &str) -> Self {
+        self.checksum = Some(checksum.to_lowercase());
+        self
+    }
+
+    // Drops the expected digest, for stores that verify the data themselves before passing it on
+    // in another form.
+    pub fn without_checksum(mut self) -> Self {
+        self.checksum = None;
+        self
+    }
+
+    // Only writes the object when the object it replaces meets the conditions.
+    pub fn conditions(mut self, conditions: Conditions) -> Self {
+        self.conditions = conditions;
+        self
+    }
+
+    pub fn preconditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    pub fn verify(&self, etag: &str) -> bool {
+        match &self.checksum {
+            None => true,
+            Some(checksum) => checksum == etag,
+        }
+    }
+
+    // Builds the metadata of a new write. The creation time is carried over from the object
+    // being replaced, if there is one.
+    pub fn into_metadata(self, replaced: Option<&ObjectMetadata>) -> ObjectMetadata {
+        let mut metadata = ObjectMetadata::new();
+        if let Some(replaced) = replaced {
+            metadata.created = replaced.created;
+        }
+        metadata.content_type = self.content_type;
+        metadata.user = self.metadata;
+        metadata.tags = self.tags;
+        metadata
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GetOptions {
+    verify_checksum: bool,
+    version_id: Option<String>,
+    conditions: Conditions,
+}
+
+impl GetOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Verifies the data against the object's ETag while it is read. A mismatch fails the read
+    // that reaches the end of the data with an `InvalidData` error.
+    pub fn verify_checksum(mut self) -> Self {
+        self.verify_checksum = true;
+        self
+    }
+
+    // Reads the given version instead of the latest one.
+    pub fn version_id(mut self, version_id: &str) -> Self {
+        self.version_id = Some(version_id.into());
+        self
+    }
+
+    // Only reads the object when the version read meets the conditions.
+    pub fn conditions(mut self, conditions: Conditions) -> Self {
+        self.conditions = conditions;
+        self
+    }
+
+    pub fn preconditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version_id.as_deref()
+    }
+
+    pub fn verifies_checksum(&self) -> bool {
+        self.verify_checksum
+    }
+
+    pub fn reader<'a>(
+        &self,
+        reader: Box<dyn Read + 'a>,
+        metadata: &ObjectMetadata,
+    ) -> Box<dyn Read + 'a> {
+        if self.verify_checksum {
+            Box::new(VerifyingReader::new(reader, &metadata.etag))
+        } else {
+            reader
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CopyOptions {
+    replace: Option<PutOptions>,
+}
+
+impl CopyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Gives the copy the content type, user metadata and tags of these options instead of those
+    // of the source. An expected checksum and conditions in them are ignored.
+    pub fn replace_metadata(mut self, options: PutOptions) -> Self {
+        self.replace = Some(PutOptions {
+            conditions: Conditions::new(),
+            ..options.without_checksum()
+        });
+        self
+    }
+
+    // The options to write the copy of an object with the given metadata.
+    pub fn into_put_options(self, source: &ObjectMetadata) -> PutOptions {
+        self.replace.unwrap_or_else(|| PutOptions {
+            content_type: source.content_type.clone(),
+            metadata: source.user.clone(),
+            checksum: None,
+            conditions: Conditions::new(),
+            tags: source.tags.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RemoveOptions {
+    conditions: Conditions,
+}
+
+impl RemoveOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Only removes the object when its latest version meets the conditions.
+    pub fn conditions(mut self, conditions: Conditions) -> Self {
+        self.conditions = conditions;
+        self
+    }
+
+    pub fn preconditions(&self) -> &Conditions {
+        &self.conditions
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeleteBucketOptions {
+    force: bool,
+}
+
+impl DeleteBucketOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Deletes the bucket along with its objects, their versions and the uploads into it, instead
+    // of failing when the bucket isn't empty.
+    pub fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    pub fn is_forced(&self) -> bool {
+        self.force
+    }
+}
 
 pub trait Store {
     fn list_buckets(&self) -> Vec<&str>;
-    fn create_bucket(&mut self, name: &str);
-    fn delete_bucket(&mut self, name: &str) -> Result<()>;
-    fn list_objects(&self, bucket: &str) -> Option<Vec<&str>>;
-    fn insert_object(&mut self, bucket: &str, name: &str, reader: &mut Box<dyn Read>)
-        -> Result<()>;
-    fn put_object(&mut self, bucket: &str, name: &str, reader: &mut Box<dyn Read>) -> Result<()>;
-    fn get_object<'a>(&'a self, bucket: &str, name: &str) -> Option<Box<dyn Read + 'a>>;
-    fn remove_object(&mut self, bucket: &str, name: &str) -> Result<()>;
+    fn create_bucket(&mut self, name: &str) -> Result<()>;
+    fn delete_bucket(&mut self, name: &str) -> Result<()> {
+        self.delete_bucket_with(name, DeleteBucketOptions::new())
+    }
+    fn delete_bucket_with(&mut self, name: &str, options: DeleteBucketOptions) -> Result<()>;
+    fn list_objects(&self, bucket: &str) -> Option<Vec<&str>> {
+        self.list_objects_with(bucket, ListOptions::new())
+            .ok()
+            .map(|list| list.objects)
+    }
+    fn list_objects_with(&self, bucket: &str, options: ListOptions) -> Result<ObjectList<'_>>;
+    fn insert_object(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<()> {
+        self.insert_object_with(bucket, name, reader, PutOptions::new())
+    }
+    fn insert_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()>;
+    fn put_object(&mut self, bucket: &str, name: &str, reader: &mut Box<dyn Read>) -> Result<()> {
+        self.put_object_with(bucket, name, reader, PutOptions::new())
+    }
+    fn put_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        reader: &mut Box<dyn Read>,
+        options: PutOptions,
+    ) -> Result<()>;
+    fn head_object(&self, bucket: &str, name: &str) -> Option<ObjectMetadata>;
+    // The metadata of a version of an object, none for a delete marker. Only finds the current
+    // version, stores that keep older ones do better.
+    fn head_object_version(
+        &self,
+        bucket: &str,
+        name: &str,
+        version_id: &str,
+    ) -> Option<ObjectMetadata> {
+        self.head_object(bucket, name)
+            .filter(|metadata| metadata.version_id == version_id)
+    }
+    // Replaces the tags of the current version of an object without writing its data again.
+    fn set_object_tags(&mut self, bucket: &str, name: &str, tags: Tags) -> Result<()>;
+    // Names of the objects whose tags match the query. Goes through the metadata of every object,
+    // stores that keep it at hand do better.
+    fn find_objects(&self, bucket: &str, query: &TagQuery) -> Option<Vec<&str>> {
+        let names = self.list_objects(bucket)?;
+        Some(
+            names
+                .into_iter()
+                .filter(|name| {
+                    matches!(self.head_object(bucket, name), Some(metadata) if query.matches(&metadata.tags))
+                })
+                .collect(),
+        )
+    }
+    fn get_object<'a>(&'a self, bucket: &str, name: &str) -> Option<Box<dyn Read + 'a>> {
+        self.get_object_with(bucket, name, GetOptions::new()).ok()
+    }
+    fn get_object_with<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        options: GetOptions,
+    ) -> Result<Box<dyn Read + 'a>>;
+    fn get_object_range<'a>(
+        &'a self,
+        bucket: &str,
+        name: &str,
+        range: ByteRange,
+    ) -> Result<Box<dyn Read + 'a>>;
+    fn copy_object(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        self.copy_object_with(
+            src_bucket,
+            src_name,
+            dst_bucket,
+            dst_name,
+            CopyOptions::new(),
+        )
+    }
+    // Reads the source into memory and writes it again. Stores that can share the data of the
+    // source with its copy do so instead.
+    fn copy_object_with(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+        options: CopyOptions,
+    ) -> Result<()> {
+        let mut data = Vec::new();
+        let read = self
+            .get_object_with(src_bucket, src_name, GetOptions::new())?
+            .read_to_end(&mut data);
+        let metadata = match (read, self.head_object(src_bucket, src_name)) {
+            (Ok(_), Some(metadata)) => metadata,
+            (Err(e), _) => return Err(Error::io(e, src_bucket, src_name)),
+            (Ok(_), None) => {
+                return Err(Error::new(ErrorKind::ObjectNotFound, src_bucket, src_name))
+            }
+        };
+        let options = options.into_put_options(&metadata);
+        let mut reader = Box::new(Cursor::new(data)) as Box<dyn Read>;
+        self.put_object_with(dst_bucket, dst_name, &mut reader, options)
+    }
+    // Moves an object along with its metadata, replacing any object at the destination.
+    fn rename_object(
+        &mut self,
+        src_bucket: &str,
+        src_name: &str,
+        dst_bucket: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        if (src_bucket, src_name) == (dst_bucket, dst_name) {
+            return self
+                .get_object_with(src_bucket, src_name, GetOptions::new())
+                .map(|_| ());
+        }
+        self.copy_object(src_bucket, src_name, dst_bucket, dst_name)?;
+        self.remove_object(src_bucket, src_name)
+    }
+    fn remove_object(&mut self, bucket: &str, name: &str) -> Result<()> {
+        self.remove_object_with(bucket, name, RemoveOptions::new())
+    }
+    fn remove_object_with(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: RemoveOptions,
+    ) -> Result<()>;
+    fn remove_object_version(&mut self, bucket: &str, name: &str, version_id: &str) -> Result<()>;
+    fn list_object_versions(&self, bucket: &str) -> Option<Vec<ObjectVersion>>;
+    fn create_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        options: PutOptions,
+    ) -> Result<String>;
+    fn upload_part(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+        part_number: u32,
+        reader: &mut Box<dyn Read>,
+    ) -> Result<String>;
+    fn list_multipart_uploads(&self, bucket: &str) -> Option<Vec<MultipartUpload>>;
+    fn abort_multipart_upload(&mut self, bucket: &str, name: &str, upload_id: &str) -> Result<()>;
+    // The upload is kept until the object is written, so a failed completion can be retried.
+    fn complete_multipart_upload(
+        &mut self,
+        bucket: &str,
+        name: &str,
+        upload_id: &str,
+    ) -> Result<()>;
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::condition;
+    use crate::version::NULL_VERSION;
+    use std::time::Duration;
+    use std::{io, thread};
+
+    const EMPTY: &[u8] = b"";
+
+    pub fn get_reader() -> Box<dyn Read> {
+        Box::new(EMPTY)
+    }
+
+    pub fn get_data_reader(data: &'static [u8]) -> Box<dyn Read> {
+        Box::new(data)
+    }
+
+    pub fn get_bucket_names() -> Vec<&'static str> {
+        vec!["mars", "jupiter", "saturn", "uranus", "neptune", "pluto"]
+    }
+
+    pub fn get_object_names() -> Vec<&'static str> {
+        vec!["Io", "Europa", "Ganymede", "Callisto"]
+    }
+
+    fn create_buckets<S: Store>(store: &mut S, buckets: &[&str]) {
+        for bucket in buckets {
+            let _ = store.create_bucket(bucket);
+        }
+    }
+
+    fn populate<S: Store>(store: &mut S, bucket: &str, objects: &[&str]) {
+        let _ = store.create_bucket(bucket);
+        for object in objects {
+            let _ = store.insert_object(bucket, object, &mut get_reader());
+        }
+    }
+
+    // Generates one #[test] per shared test case, each handing a fresh store to the case
+    // through `$with_store`, so every backend runs the same suite.
+    macro_rules! store_tests {
+        ($with_store:ident) => {
+            store_tests!(
+                $with_store,
+                store_list_buckets_should_return_all_bucket_names,
+                store_create_bucket_should_add_bucket_when_bucket_did_not_exist,
+                store_create_bucket_should_reject_existing_bucket,
+                store_create_bucket_should_reject_invalid_name,
+                store_delete_bucket_should_delete_existing_bucket,
+                store_delete_bucket_should_return_error_when_bucket_did_not_exist,
+                store_delete_bucket_should_reject_non_empty_bucket,
+                store_delete_bucket_with_force_should_delete_non_empty_bucket,
+                store_list_objects_should_list_all_object_names_in_bucket,
+                store_list_objects_should_return_sorted_names,
+                store_list_objects_with_should_apply_prefix_delimiter_and_max_keys,
+                store_insert_object_should_add_object_when_object_did_not_exist,
+                store_insert_object_should_return_error_when_bucket_did_not_exist,
+                store_insert_object_should_not_add_object_when_object_already_exist,
+                store_put_object_should_replace_existing_object,
+                store_head_object_should_return_object_length,
+                store_head_object_should_return_supplied_metadata,
+                store_head_object_should_keep_creation_time_on_replace,
+                store_head_object_should_return_none_when_object_did_not_exist,
+                store_head_object_should_return_sha256_etag,
+                store_set_object_tags_should_keep_data_and_metadata,
+                store_set_object_tags_should_reject_invalid_tags,
+                store_find_objects_should_return_objects_with_matching_tags,
+                store_put_object_should_accept_matching_checksum,
+                store_put_object_should_reject_mismatched_checksum,
+                store_put_object_with_if_match_should_reject_stale_etag,
+                store_put_object_with_if_none_match_should_only_create_object,
+                store_get_object_should_return_existing_object,
+                store_get_object_with_should_return_error_when_object_did_not_exist,
+                store_get_object_with_verify_checksum_should_read_intact_object,
+                store_get_object_with_conditions_should_check_latest_version,
+                store_get_object_should_return_identical_data,
+                store_get_object_range_should_return_requested_bytes,
+                store_get_object_range_should_reject_unsatisfiable_range,
+                store_copy_object_should_keep_data_and_metadata,
+                store_copy_object_with_replaced_metadata_should_use_new_metadata,
+                store_copy_object_should_keep_tags_unless_replaced,
+                store_copy_object_should_stay_independent_of_source,
+                store_copy_object_should_return_error_when_source_or_bucket_did_not_exist,
+                store_rename_object_should_move_object_across_buckets,
+                store_remove_object_should_delete_existing_object,
+                store_remove_object_with_if_match_should_keep_changed_object,
+                store_list_object_versions_should_return_null_version_without_versioning,
+                store_get_object_with_version_id_should_return_null_version,
+                store_get_object_with_unknown_version_id_should_return_error,
+                store_remove_object_version_should_delete_null_version,
+                store_complete_multipart_upload_should_assemble_parts_in_order,
+                store_multipart_upload_should_not_be_visible_until_completed,
+                store_abort_multipart_upload_should_discard_upload,
+                store_upload_part_should_reject_invalid_part_number,
+                store_complete_multipart_upload_should_reject_upload_without_parts,
+                store_complete_multipart_upload_should_keep_upload_when_it_failed,
+                store_complete_multipart_upload_should_accept_matching_checksum,
+            );
+        };
+        ($with_store:ident, $($test:ident),+ $(,)?) => {
+            $(
+                #[test]
+                fn $test() {
+                    $with_store(crate::store::tests::$test);
+                }
+            )+
+        };
+    }
+
+    pub(crate) use store_tests;
+
+    pub fn store_list_buckets_should_return_all_bucket_names<S: Store>(mut store: S) {
+        let mut buckets = get_bucket_names();
+        create_buckets(&mut store, &buckets);
+        let mut output = store.list_buckets();
+        buckets.sort();
+        output.sort();
+        assert_eq!(buckets, output, "Store failed to list all bucket names.");
+    }
+
+    pub fn store_create_bucket_should_add_bucket_when_bucket_did_not_exist<S: Store>(mut store: S) {
+        let bucket = "earth";
+        assert!(
+            store.create_bucket(bucket).is_ok(),
+            "Store failed to create new bucket."
+        );
+        assert!(
+            store.list_buckets().contains(&bucket),
+            "Store failed to list new bucket."
+        );
+    }
+
+    pub fn store_create_bucket_should_reject_existing_bucket<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let result = store.create_bucket("earth");
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketAlreadyExists)),
+            "Store created an existing bucket."
+        );
+        assert!(
+            store.head_object("earth", "Moon").is_some(),
+            "Store emptied an existing bucket."
+        );
+    }
+
+    pub fn store_create_bucket_should_reject_invalid_name<S: Store>(mut store: S) {
+        for bucket in &[
+            "",
+            "io",
+            "-earth",
+            "earth..moon",
+            "earth/moon",
+            "Earth",
+            "127.0.0.1",
+        ] {
+            let result = store.create_bucket(bucket);
+            assert!(
+                matches!(result, Err(e) if matches!(e.kind(), ErrorKind::InvalidBucketName)),
+                "Store created bucket with invalid name {:?}.",
+                bucket
+            );
+        }
+        assert!(store.list_buckets().is_empty());
+    }
+
+    pub fn store_delete_bucket_should_delete_existing_bucket<S: Store>(mut store: S) {
+        create_buckets(&mut store, &get_bucket_names());
+        let bucket = "pluto";
+        let _ = store.delete_bucket(bucket);
+        assert!(
+            !store.list_buckets().contains(&bucket),
+            "Store failed to delete bucket."
+        );
+    }
+
+    pub fn store_delete_bucket_should_return_error_when_bucket_did_not_exist<S: Store>(
+        mut store: S,
+    ) {
+        let result = store.delete_bucket("earth");
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotFound)),
+            "Store did not report missing bucket."
+        );
+    }
+
+    pub fn store_delete_bucket_should_reject_non_empty_bucket<S: Store>(mut store: S) {
+        let bucket = "jupiter";
+        populate(&mut store, bucket, &get_object_names());
+        let result = store.delete_bucket(bucket);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotEmpty)),
+            "Store deleted a non-empty bucket."
+        );
+        assert!(
+            store.list_buckets().contains(&bucket),
+            "Store lost a non-empty bucket."
+        );
+    }
+
+    pub fn store_delete_bucket_with_force_should_delete_non_empty_bucket<S: Store>(mut store: S) {
+        let bucket = "jupiter";
+        populate(&mut store, bucket, &get_object_names());
+        let _ = store.create_multipart_upload(bucket, "Io", PutOptions::new());
+        assert!(
+            store
+                .delete_bucket_with(bucket, DeleteBucketOptions::new().force())
+                .is_ok(),
+            "Store failed to force deletion of a non-empty bucket."
+        );
+        assert!(
+            !store.list_buckets().contains(&bucket),
+            "Store kept a force deleted bucket."
+        );
+        let _ = store.create_bucket(bucket);
+        assert_eq!(store.list_objects(bucket), Some(vec![]));
+        assert_eq!(
+            store.list_multipart_uploads(bucket),
+            Some(vec![]),
+            "Store kept uploads of a force deleted bucket."
+        );
+    }
+
+    pub fn store_list_objects_should_list_all_object_names_in_bucket<S: Store>(mut store: S) {
+        let bucket = "jupiter";
+        let mut objects = get_object_names();
+        populate(&mut store, bucket, &objects);
+        let mut output = store.list_objects(bucket).unwrap_or_default();
+        objects.sort();
+        output.sort();
+        assert_eq!(
+            objects, output,
+            "Store failed to list all object names in a bucket."
+        );
+    }
+
+    pub fn store_list_objects_should_return_sorted_names<S: Store>(mut store: S) {
+        let bucket = "jupiter";
+        let mut objects = get_object_names();
+        populate(&mut store, bucket, &objects);
+        objects.sort();
+        assert_eq!(
+            store.list_objects(bucket),
+            Some(objects),
+            "Store failed to list object names in order."
+        );
+    }
+
+    pub fn store_list_objects_with_should_apply_prefix_delimiter_and_max_keys<S: Store>(
+        mut store: S,
+    ) {
+        let bucket = "jupiter";
+        let objects = vec![
+            "moons/Europa",
+            "moons/Io",
+            "moons/irregular/Himalia",
+            "rings/Halo",
+        ];
+        populate(&mut store, bucket, &objects);
+        let options = ListOptions::new()
+            .prefix("moons/")
+            .delimiter("/")
+            .max_keys(2);
+        let list = store.list_objects_with(bucket, options).unwrap_or_default();
+        assert_eq!(list.objects, vec!["moons/Europa", "moons/Io"]);
+        assert!(list.is_truncated, "Store did not truncate listing.");
+        let options = ListOptions::new()
+            .prefix("moons/")
+            .delimiter("/")
+            .continuation_token(&list.next_continuation_token.unwrap_or_default());
+        let list = store.list_objects_with(bucket, options).unwrap_or_default();
+        assert!(list.objects.is_empty(), "Store repeated listed objects.");
+        assert_eq!(list.common_prefixes, vec!["moons/irregular/"]);
+    }
+
+    pub fn store_insert_object_should_add_object_when_object_did_not_exist<S: Store>(mut store: S) {
+        let bucket = "earth";
+        let _ = store.create_bucket(bucket);
+        let object = "Moon";
+        assert!(
+            store
+                .insert_object(bucket, object, &mut get_reader())
+                .is_ok(),
+            "Store failed to insert new object."
+        );
+    }
+
+    pub fn store_insert_object_should_return_error_when_bucket_did_not_exist<S: Store>(
+        mut store: S,
+    ) {
+        let result = store.insert_object("earth", "Moon", &mut get_reader());
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotFound)),
+            "Store did not report missing bucket."
+        );
+        assert!(
+            store.list_buckets().is_empty(),
+            "Store created a bucket implicitly."
+        );
+        let result = store.create_multipart_upload("earth", "Moon", PutOptions::new());
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotFound)),
+            "Store started an upload into a missing bucket."
+        );
+    }
+
+    pub fn store_insert_object_should_not_add_object_when_object_already_exist<S: Store>(
+        mut store: S,
+    ) {
+        let bucket = "jupiter";
+        let objects = get_object_names();
+        let object = objects[0];
+        populate(&mut store, bucket, &objects);
+        assert!(
+            store
+                .insert_object(bucket, object, &mut get_reader())
+                .is_err(),
+            "Store insert duplicated object."
+        );
+    }
+
+    pub fn store_put_object_should_replace_existing_object<S: Store>(mut store: S) {
+        let bucket = "jupiter";
+        let objects = get_object_names();
+        let object = objects[0];
+        populate(&mut store, bucket, &objects);
+        assert!(
+            store.put_object(bucket, object, &mut get_reader()).is_ok(),
+            "Store failed to replace an existing object."
+        );
+    }
+
+    pub fn store_head_object_should_return_object_length<S: Store>(mut store: S) {
+        let bucket = "earth";
+        let _ = store.create_bucket(bucket);
+        let object = "Moon";
+        let _ = store.put_object(bucket, object, &mut get_data_reader(b"Luna"));
+        let length = store.head_object(bucket, object).map(|m| m.length);
+        assert_eq!(length, Some(4), "Store returned wrong object length.");
+    }
+
+    pub fn store_head_object_should_return_supplied_metadata<S: Store>(mut store: S) {
+        let bucket = "earth";
+        let _ = store.create_bucket(bucket);
+        let object = "Moon";
+        let options = PutOptions::new()
+            .content_type("text/plain")
+            .metadata("discovered-by", "Galileo Galilei");
+        let _ = store.put_object_with(bucket, object, &mut get_reader(), options);
+        let metadata = store.head_object(bucket, object).unwrap_or_default();
+        assert_eq!(
+            metadata.content_type.as_deref(),
+            Some("text/plain"),
+            "Store did not keep object content type."
+        );
+        assert_eq!(
+            metadata.user.get("discovered-by").map(|v| v.as_str()),
+            Some("Galileo Galilei"),
+            "Store did not keep user metadata."
+        );
+    }
+
+    pub fn store_head_object_should_keep_creation_time_on_replace<S: Store>(mut store: S) {
+        let bucket = "earth";
+        let _ = store.create_bucket(bucket);
+        let object = "Moon";
+        let _ = store.put_object(bucket, object, &mut get_reader());
+        let created = store.head_object(bucket, object).map(|m| m.created);
+        thread::sleep(Duration::from_millis(10));
+        let _ = store.put_object(bucket, object, &mut get_reader());
+        let metadata = store.head_object(bucket, object).unwrap_or_default();
+        assert_eq!(
+            created,
+            Some(metadata.created),
+            "Store changed creation time of a replaced object."
+        );
+        assert!(
+            metadata.modified > metadata.created,
+            "Store did not update modification time of a replaced object."
+        );
+    }
+
+    pub fn store_head_object_should_return_none_when_object_did_not_exist<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        assert!(
+            store.head_object("earth", "Moon").is_none(),
+            "Store returned metadata of a non-existent object."
+        );
+    }
+
+    pub fn store_head_object_should_return_sha256_etag<S: Store>(mut store: S) {
+        let bucket = "earth";
+        let _ = store.create_bucket(bucket);
+        let object = "Moon";
+        let _ = store.put_object(bucket, object, &mut get_data_reader(b"Luna"));
+        let etag = store.head_object(bucket, object).map(|m| m.etag);
+        assert_eq!(
+            etag,
+            Some(checksum::digest(b"Luna")),
+            "Store returned wrong object ETag."
+        );
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    pub fn store_set_object_tags_should_keep_data_and_metadata<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new()
+            .content_type("text/plain")
+            .tag("kind", "moon");
+        let _ = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        let before = store.head_object("earth", "Moon").unwrap_or_default();
+        assert_eq!(before.tags, tags(&[("kind", "moon")]));
+        assert!(
+            store
+                .set_object_tags("earth", "Moon", tags(&[("orbit", "earth")]))
+                .is_ok(),
+            "Store failed to set tags."
+        );
+        let after = store.head_object("earth", "Moon").unwrap_or_default();
+        assert_eq!(
+            after.tags,
+            tags(&[("orbit", "earth")]),
+            "Store did not replace tags."
+        );
+        assert_eq!(after.etag, before.etag);
+        assert_eq!(after.modified, before.modified);
+        assert_eq!(after.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(read_all(&store, "earth", "Moon"), Some(b"Luna".to_vec()));
+        let result = store.set_object_tags("earth", "Selene", Tags::new());
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ObjectNotFound)),
+            "Store tagged a missing object."
+        );
+    }
+
+    pub fn store_set_object_tags_should_reject_invalid_tags<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().tag("kind", "moon & satellite");
+        let result = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::InvalidTag)),
+            "Store wrote an object with invalid tags."
+        );
+        assert!(store.head_object("earth", "Moon").is_none());
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let result = store.set_object_tags("earth", "Moon", tags(&[("", "moon")]));
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::InvalidTag)),
+            "Store set invalid tags."
+        );
+    }
+
+    pub fn store_find_objects_should_return_objects_with_matching_tags<S: Store>(mut store: S) {
+        let _ = store.create_bucket("jupiter");
+        for (name, group) in &[
+            ("Io", "galilean"),
+            ("Europa", "galilean"),
+            ("Amalthea", "inner"),
+        ] {
+            let options = PutOptions::new().tag("group", group);
+            let _ = store.put_object_with("jupiter", name, &mut get_reader(), options);
+        }
+        let _ = store.put_object("jupiter", "Himalia", &mut get_reader());
+        let _ = store.set_object_tags(
+            "jupiter",
+            "Io",
+            tags(&[("group", "galilean"), ("volcanic", "")]),
+        );
+        let query = TagQuery::parse("group=galilean & !volcanic | !group").unwrap();
+        assert_eq!(
+            store.find_objects("jupiter", &query),
+            Some(vec!["Europa", "Himalia"]),
+            "Store did not find objects by their tags."
+        );
+        assert_eq!(store.find_objects("Saturn", &query), None);
+    }
+
+    pub fn store_put_object_should_accept_matching_checksum<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().checksum(&checksum::digest(b"Luna").to_uppercase());
+        assert!(
+            store
+                .put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options)
+                .is_ok(),
+            "Store rejected object with matching checksum."
+        );
+    }
+
+    pub fn store_put_object_should_reject_mismatched_checksum<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().checksum(&checksum::digest(b"Luna"));
+        let result = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Lune"), options);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ChecksumMismatch)),
+            "Store accepted object with mismatched checksum."
+        );
+        assert!(
+            store.get_object("earth", "Moon").is_none(),
+            "Store kept object with mismatched checksum."
+        );
+    }
+
+    fn is_precondition_failed(result: Result<()>) -> bool {
+        matches!(result, Err(e) if matches!(e.kind(), ErrorKind::PreconditionFailed))
+    }
+
+    pub fn store_put_object_with_if_match_should_reject_stale_etag<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let seen = Conditions::new().if_match(&checksum::digest(b"Luna"));
+        let options = PutOptions::new().conditions(seen.clone());
+        assert!(
+            store
+                .put_object_with("earth", "Moon", &mut get_data_reader(b"Selene"), options)
+                .is_ok(),
+            "Store failed to write object with matching ETag."
+        );
+        let options = PutOptions::new().conditions(seen);
+        let result = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Lune"), options);
+        assert!(
+            is_precondition_failed(result),
+            "Store accepted write with stale ETag."
+        );
+        assert_eq!(
+            read_all(&store, "earth", "Moon"),
+            Some(b"Selene".to_vec()),
+            "Store replaced object despite stale ETag."
+        );
+    }
+
+    pub fn store_put_object_with_if_none_match_should_only_create_object<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let absent =
+            || PutOptions::new().conditions(Conditions::new().if_none_match(condition::ANY));
+        assert!(
+            store
+                .put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), absent())
+                .is_ok(),
+            "Store failed to create object that did not exist."
+        );
+        let result =
+            store.put_object_with("earth", "Moon", &mut get_data_reader(b"Lune"), absent());
+        assert!(
+            is_precondition_failed(result),
+            "Store replaced object that had to be absent."
+        );
+        assert_eq!(read_all(&store, "earth", "Moon"), Some(b"Luna".to_vec()));
+    }
+
+    pub fn store_get_object_should_return_existing_object<S: Store>(mut store: S) {
+        let bucket = "earth";
+        let _ = store.create_bucket(bucket);
+        let object = "Moon";
+        let _ = store.put_object(bucket, object, &mut get_reader());
+        assert!(
+            store.get_object(bucket, object).is_some(),
+            "Store failed to return existing object."
+        );
+    }
+
+    pub fn store_get_object_should_return_identical_data<S: Store>(mut store: S) {
+        let bucket = "earth";
+        let _ = store.create_bucket(bucket);
+        let object = "Moon";
+        let input = b"Luna";
+        let _ = store.put_object(bucket, object, &mut get_data_reader(input));
+        let mut output = Vec::new();
+        if let Some(mut reader) = store.get_object(bucket, object) {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(
+            &input[..],
+            &output[..],
+            "Store failed to return identical data."
+        );
+    }
+
+    pub fn store_get_object_with_should_return_error_when_object_did_not_exist<S: Store>(
+        mut store: S,
+    ) {
+        let _ = store.create_bucket("earth");
+        let result = store.get_object_with("earth", "Moon", GetOptions::new());
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ObjectNotFound)),
+            "Store did not report missing object."
+        );
+    }
+
+    pub fn store_get_object_with_verify_checksum_should_read_intact_object<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let mut output = Vec::new();
+        let result = store
+            .get_object_with("earth", "Moon", GetOptions::new().verify_checksum())
+            .map(|mut reader| io::copy(&mut reader, &mut output));
+        assert!(
+            matches!(result, Ok(Ok(4))),
+            "Store failed to verify intact object."
+        );
+    }
+
+    pub fn store_get_object_with_conditions_should_check_latest_version<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let modified = store.head_object("earth", "Moon").unwrap().modified;
+        let etag = checksum::digest(b"Luna");
+        let get = |conditions: Conditions| {
+            store
+                .get_object_with("earth", "Moon", GetOptions::new().conditions(conditions))
+                .map(|_| ())
+        };
+        assert!(get(Conditions::new().if_match(&etag)).is_ok());
+        assert!(
+            is_precondition_failed(get(Conditions::new().if_none_match(&etag))),
+            "Store read object with an ETag not to match."
+        );
+        assert!(
+            is_precondition_failed(get(Conditions::new().if_modified_since(modified))),
+            "Store read object that was not modified since."
+        );
+        assert!(
+            get(Conditions::new().if_modified_since(modified - Duration::from_secs(2))).is_ok(),
+            "Store failed to read object modified since."
+        );
+        assert!(
+            is_precondition_failed(get(Conditions::new().if_match("Lune"))),
+            "Store read object with another ETag."
+        );
+    }
+
+    pub fn store_get_object_range_should_return_requested_bytes<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let read = |range: ByteRange| {
+            let mut output = Vec::new();
+            if let Ok(mut reader) = store.get_object_range("earth", "Moon", range) {
+                let _ = io::copy(&mut reader, &mut output);
+            }
+            output
+        };
+        assert_eq!(read(ByteRange::from(1..3)), b"el");
+        assert_eq!(read(ByteRange::from(2..)), b"lene");
+        assert_eq!(read(ByteRange::suffix(2)), b"ne");
+    }
+
+    pub fn store_get_object_range_should_reject_unsatisfiable_range<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let result = store.get_object_range("earth", "Moon", ByteRange::from(6..));
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::InvalidRange)),
+            "Store accepted an unsatisfiable range."
+        );
+    }
+
+    fn read_all<S: Store>(store: &S, bucket: &str, name: &str) -> Option<Vec<u8>> {
+        let mut output = Vec::new();
+        io::copy(&mut store.get_object(bucket, name)?, &mut output).ok()?;
+        Some(output)
+    }
+
+    pub fn store_copy_object_should_keep_data_and_metadata<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let options = PutOptions::new()
+            .content_type("text/plain")
+            .metadata("discovered-by", "Galileo Galilei");
+        let _ = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        assert!(
+            store.copy_object("earth", "Moon", "mars", "Phobos").is_ok(),
+            "Store failed to copy object."
+        );
+        assert_eq!(
+            read_all(&store, "mars", "Phobos"),
+            Some(b"Luna".to_vec()),
+            "Store returned wrong data of a copy."
+        );
+        let metadata = store.head_object("mars", "Phobos").unwrap_or_default();
+        assert_eq!(metadata.length, 4);
+        assert_eq!(metadata.etag, checksum::digest(b"Luna"));
+        assert_eq!(metadata.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(
+            metadata.user.get("discovered-by").map(|v| v.as_str()),
+            Some("Galileo Galilei"),
+            "Store did not keep metadata of a copy."
+        );
+        assert!(
+            store.head_object("earth", "Moon").is_some(),
+            "Store removed source of a copy."
+        );
+    }
+
+    pub fn store_copy_object_with_replaced_metadata_should_use_new_metadata<S: Store>(
+        mut store: S,
+    ) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new()
+            .content_type("text/plain")
+            .metadata("discovered-by", "Galileo Galilei");
+        let _ = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        let replaced = PutOptions::new().metadata("named-by", "Johannes Kepler");
+        let options = CopyOptions::new().replace_metadata(replaced);
+        let _ = store.copy_object_with("earth", "Moon", "earth", "Selene", options);
+        let metadata = store.head_object("earth", "Selene").unwrap_or_default();
+        assert_eq!(metadata.content_type, None);
+        assert_eq!(metadata.etag, checksum::digest(b"Luna"));
+        assert_eq!(
+            metadata.user.keys().collect::<Vec<_>>(),
+            vec!["named-by"],
+            "Store did not replace metadata of a copy."
+        );
+    }
+
+    pub fn store_copy_object_should_keep_tags_unless_replaced<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().tag("kind", "moon");
+        let _ = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        let _ = store.copy_object("earth", "Moon", "earth", "Luna");
+        let replaced =
+            CopyOptions::new().replace_metadata(PutOptions::new().tag("kind", "goddess"));
+        let _ = store.copy_object_with("earth", "Moon", "earth", "Selene", replaced);
+        let tags_of = |name| store.head_object("earth", name).map(|m| m.tags);
+        assert_eq!(
+            tags_of("Luna"),
+            Some(tags(&[("kind", "moon")])),
+            "Store did not keep tags of a copy."
+        );
+        assert_eq!(
+            tags_of("Selene"),
+            Some(tags(&[("kind", "goddess")])),
+            "Store did not replace tags of a copy."
+        );
+    }
+
+    pub fn store_copy_object_should_stay_independent_of_source<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.copy_object("earth", "Moon", "earth", "Selene");
+        let _ = store.put_object("earth", "Selene", &mut get_data_reader(b"Selene"));
+        let _ = store.remove_object("earth", "Moon");
+        assert_eq!(
+            read_all(&store, "earth", "Selene"),
+            Some(b"Selene".to_vec()),
+            "Store failed to replace a copy."
+        );
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.copy_object("earth", "Moon", "earth", "Selene");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Mond"));
+        assert_eq!(
+            read_all(&store, "earth", "Selene"),
+            Some(b"Luna".to_vec()),
+            "Store changed a copy along with its source."
+        );
+    }
+
+    pub fn store_copy_object_should_return_error_when_source_or_bucket_did_not_exist<S: Store>(
+        mut store: S,
+    ) {
+        let _ = store.create_bucket("earth");
+        let result = store.copy_object("earth", "Moon", "earth", "Selene");
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ObjectNotFound)),
+            "Store copied a missing object."
+        );
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let result = store.copy_object("earth", "Moon", "mars", "Phobos");
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotFound)),
+            "Store copied into a missing bucket."
+        );
+        let result = store.rename_object("earth", "Moon", "mars", "Phobos");
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::BucketNotFound)),
+            "Store renamed into a missing bucket."
+        );
+        assert!(
+            store.head_object("earth", "Moon").is_some(),
+            "Store lost the source of a failed rename."
+        );
+    }
+
+    pub fn store_rename_object_should_move_object_across_buckets<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.create_bucket("mars");
+        let options = PutOptions::new().content_type("text/plain");
+        let _ = store.put_object_with("earth", "Moon", &mut get_data_reader(b"Luna"), options);
+        let _ = store.put_object("mars", "Phobos", &mut get_data_reader(b"Fear"));
+        assert!(
+            store
+                .rename_object("earth", "Moon", "mars", "Phobos")
+                .is_ok(),
+            "Store failed to rename object."
+        );
+        assert!(
+            store.head_object("earth", "Moon").is_none(),
+            "Store kept source of a renamed object."
+        );
+        assert_eq!(store.list_objects("mars"), Some(vec!["Phobos"]));
+        assert_eq!(
+            read_all(&store, "mars", "Phobos"),
+            Some(b"Luna".to_vec()),
+            "Store returned wrong data of a renamed object."
+        );
+        assert_eq!(
+            store
+                .head_object("mars", "Phobos")
+                .and_then(|m| m.content_type),
+            Some("text/plain".into())
+        );
+        assert!(store
+            .rename_object("mars", "Phobos", "mars", "Phobos")
+            .is_ok());
+        assert!(store.head_object("mars", "Phobos").is_some());
+    }
+
+    pub fn store_remove_object_should_delete_existing_object<S: Store>(mut store: S) {
+        let bucket = "jupiter";
+        let object = "Callisto";
+        populate(&mut store, bucket, &get_object_names());
+        let _ = store.remove_object(bucket, object);
+        assert!(
+            store.get_object(bucket, object).is_none(),
+            "Store failed to remove existing object."
+        );
+    }
+
+    pub fn store_remove_object_with_if_match_should_keep_changed_object<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let stale = Conditions::new().if_match(&checksum::digest(b"Luna"));
+        let result =
+            store.remove_object_with("earth", "Moon", RemoveOptions::new().conditions(stale));
+        assert!(
+            is_precondition_failed(result),
+            "Store removed object with stale ETag."
+        );
+        assert!(store.head_object("earth", "Moon").is_some());
+        let seen = Conditions::new().if_match(&checksum::digest(b"Selene"));
+        assert!(
+            store
+                .remove_object_with("earth", "Moon", RemoveOptions::new().conditions(seen))
+                .is_ok(),
+            "Store failed to remove object with matching ETag."
+        );
+        assert!(store.head_object("earth", "Moon").is_none());
+    }
+
+    pub fn store_list_object_versions_should_return_null_version_without_versioning<S: Store>(
+        mut store: S,
+    ) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Selene"));
+        let versions = store.list_object_versions("earth").unwrap_or_default();
+        assert_eq!(versions.len(), 1, "Store kept overwritten version.");
+        assert_eq!(versions[0].name, "Moon");
+        assert_eq!(versions[0].version_id, NULL_VERSION);
+        assert_eq!(versions[0].length, 6);
+        assert!(versions[0].is_latest && !versions[0].is_delete_marker);
+        assert_eq!(
+            store.head_object("earth", "Moon").map(|m| m.version_id),
+            Some(NULL_VERSION.into()),
+            "Store returned wrong version ID."
+        );
+    }
+
+    pub fn store_get_object_with_version_id_should_return_null_version<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let options = GetOptions::new().version_id(NULL_VERSION);
+        assert!(
+            store.get_object_with("earth", "Moon", options).is_ok(),
+            "Store failed to return null version."
+        );
+    }
+
+    pub fn store_get_object_with_unknown_version_id_should_return_error<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        let options = GetOptions::new().version_id("Selene");
+        let result = store.get_object_with("earth", "Moon", options);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::VersionNotFound)),
+            "Store returned unknown version."
+        );
+    }
+
+    pub fn store_remove_object_version_should_delete_null_version<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let _ = store.put_object("earth", "Moon", &mut get_data_reader(b"Luna"));
+        assert!(store
+            .remove_object_version("earth", "Moon", NULL_VERSION)
+            .is_ok());
+        assert!(
+            store.get_object("earth", "Moon").is_none(),
+            "Store failed to remove null version."
+        );
+        let result = store.remove_object_version("earth", "Moon", NULL_VERSION);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::VersionNotFound)),
+            "Store removed a version twice."
+        );
+    }
+
+    pub fn store_complete_multipart_upload_should_assemble_parts_in_order<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let _ = store.upload_part("earth", "Moon", &upload_id, 2, &mut get_data_reader(b"na"));
+        let _ = store.upload_part("earth", "Moon", &upload_id, 1, &mut get_data_reader(b"Lu"));
+        assert!(
+            store
+                .complete_multipart_upload("earth", "Moon", &upload_id)
+                .is_ok(),
+            "Store failed to complete multipart upload."
+        );
+        let mut output = Vec::new();
+        if let Some(mut reader) = store.get_object("earth", "Moon") {
+            let _ = io::copy(&mut reader, &mut output);
+        }
+        assert_eq!(output, b"Luna", "Store assembled parts in wrong order.");
+        assert_eq!(
+            store.head_object("earth", "Moon").map(|m| m.etag),
+            Some(checksum::digest(b"Luna")),
+            "Store returned wrong ETag for assembled object."
+        );
+        assert_eq!(
+            store.list_multipart_uploads("earth"),
+            Some(vec![]),
+            "Store kept completed upload."
+        );
+    }
+
+    pub fn store_multipart_upload_should_not_be_visible_until_completed<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let _ = store.upload_part("earth", "Moon", &upload_id, 1, &mut get_data_reader(b"Lu"));
+        assert_eq!(
+            store.list_objects("earth"),
+            Some(vec![]),
+            "Store listed an incomplete upload."
+        );
+        assert!(
+            store.get_object("earth", "Moon").is_none(),
+            "Store returned an incomplete upload."
+        );
+        let uploads = store.list_multipart_uploads("earth").unwrap_or_default();
+        assert_eq!(uploads.len(), 1, "Store did not list upload in progress.");
+        assert_eq!(uploads[0].name, "Moon");
+        assert_eq!(uploads[0].upload_id, upload_id);
+    }
+
+    pub fn store_abort_multipart_upload_should_discard_upload<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let _ = store.upload_part("earth", "Moon", &upload_id, 1, &mut get_data_reader(b"Lu"));
+        assert!(store
+            .abort_multipart_upload("earth", "Moon", &upload_id)
+            .is_ok());
+        let result = store.complete_multipart_upload("earth", "Moon", &upload_id);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::UploadNotFound)),
+            "Store completed an aborted upload."
+        );
+        assert!(
+            store.get_object("earth", "Moon").is_none(),
+            "Store created object from an aborted upload."
+        );
+    }
+
+    pub fn store_upload_part_should_reject_invalid_part_number<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let result = store.upload_part("earth", "Moon", &upload_id, 0, &mut get_reader());
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::InvalidPart)),
+            "Store accepted invalid part number."
+        );
+    }
+
+    pub fn store_complete_multipart_upload_should_reject_upload_without_parts<S: Store>(
+        mut store: S,
+    ) {
+        let _ = store.create_bucket("earth");
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", PutOptions::new())
+            .unwrap_or_default();
+        let result = store.complete_multipart_upload("earth", "Moon", &upload_id);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::InvalidPart)),
+            "Store completed upload without parts."
+        );
+    }
+
+    pub fn store_complete_multipart_upload_should_accept_matching_checksum<S: Store>(mut store: S) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().checksum(&checksum::digest(b"Luna"));
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", options)
+            .unwrap_or_default();
+        let _ = store.upload_part(
+            "earth",
+            "Moon",
+            &upload_id,
+            1,
+            &mut get_data_reader(b"Luna"),
+        );
+        assert!(
+            store
+                .complete_multipart_upload("earth", "Moon", &upload_id)
+                .is_ok(),
+            "Store rejected upload with matching checksum."
+        );
+    }
+
+    pub fn store_complete_multipart_upload_should_keep_upload_when_it_failed<S: Store>(
+        mut store: S,
+    ) {
+        let _ = store.create_bucket("earth");
+        let options = PutOptions::new().checksum(&checksum::digest(b"Selene"));
+        let upload_id = store
+            .create_multipart_upload("earth", "Moon", options)
+            .unwrap_or_default();
+        let _ = store.upload_part(
+            "earth",
+            "Moon",
+            &upload_id,
+            1,
+            &mut get_data_reader(b"Luna"),
+        );
+        let result = store.complete_multipart_upload("earth", "Moon", &upload_id);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::ChecksumMismatch)),
+            "Store completed upload with mismatched checksum."
+        );
+        let uploads = store.list_multipart_uploads("earth").unwrap_or_default();
+        assert_eq!(
+            uploads.len(),
+            1,
+            "Store dropped upload after failed completion."
+        );
+
+        let _ = store.put_object("earth", "Io", &mut get_data_reader(b"Io"));
+        let conditions = Conditions::new().if_none_match("*");
+        let options = PutOptions::new().conditions(conditions);
+        let upload_id = store
+            .create_multipart_upload("earth", "Io", options)
+            .unwrap_or_default();
+        let _ = store.upload_part("earth", "Io", &upload_id, 1, &mut get_data_reader(b"Io"));
+        let result = store.complete_multipart_upload("earth", "Io", &upload_id);
+        assert!(
+            matches!(result, Err(e) if matches!(e.kind(), ErrorKind::PreconditionFailed)),
+            "Store completed upload with failed precondition."
+        );
+        let uploads = store.list_multipart_uploads("earth").unwrap_or_default();
+        assert_eq!(
+            uploads.len(),
+            2,
+            "Store dropped upload after failed precondition."
+        );
+    }
 }
@@ -0,0 +1,321 @@
+use crate::error::{Error, Kind as ErrorKind, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub type Tags = HashMap<String, String>;
+
+pub const MAX_TAGS: usize = 10;
+pub const MAX_KEY_LENGTH: usize = 128;
+pub const MAX_VALUE_LENGTH: usize = 256;
+
+// Tags are key-value pairs on an object that can be changed without writing the object again.
+// An object has at most 10 tags, with keys of 1 to 128 and values of up to 256 letters, digits
+// and `+-._:/@`, which keeps them usable in tag queries as is.
+pub fn validate(bucket: &str, name: &str, tags: &Tags) -> Result<()> {
+    let valid = tags.len() <= MAX_TAGS
+        && tags.iter().all(|(key, value)| {
+            (1..=MAX_KEY_LENGTH).contains(&key.len())
+                && value.len() <= MAX_VALUE_LENGTH
+                && key.chars().chain(value.chars()).all(is_tag_char)
+        });
+    match valid {
+        false => Err(Error::new(ErrorKind::InvalidTag, bucket, name)),
+        true => Ok(()),
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "+-._:/@".contains(c)
+}
+
+// Selects objects by their tags. Queries are written like `env=prod & (team=infra | !owner)`:
+// `key=value` and `key!=value` compare a tag, a bare `key` asks for the tag to be present, and
+// `!`, `&` and `|` negate and combine queries, `&` binding tighter than `|`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagQuery {
+    Equals(String, String),
+    Exists(String),
+    Not(Box<TagQuery>),
+    And(Vec<TagQuery>),
+    Or(Vec<TagQuery>),
+}
+
+impl TagQuery {
+    pub fn equals(key: &str, value: &str) -> Self {
+        TagQuery::Equals(key.into(), value.into())
+    }
+
+    pub fn exists(key: &str) -> Self {
+        TagQuery::Exists(key.into())
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut tokens = Tokens::new(s);
+        let query = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Some(query),
+            Some(_) => None,
+        }
+    }
+
+    pub fn matches(&self, tags: &Tags) -> bool {
+        match self {
+            TagQuery::Equals(key, value) => tags.get(key) == Some(value),
+            TagQuery::Exists(key) => tags.contains_key(key),
+            TagQuery::Not(query) => !query.matches(tags),
+            TagQuery::And(queries) => queries.iter().all(|query| query.matches(tags)),
+            TagQuery::Or(queries) => queries.iter().any(|query| query.matches(tags)),
+        }
+    }
+}
+
+// Written back in the query syntax, so `parse` reads it again.
+impl fmt::Display for TagQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagQuery::Equals(key, value) => write!(f, "{}={}", key, value),
+            TagQuery::Exists(key) => write!(f, "{}", key),
+            TagQuery::Not(query) => match **query {
+                TagQuery::Equals(_, _) | TagQuery::Exists(_) => write!(f, "!{}", query),
+                _ => write!(f, "!({})", query),
+            },
+            TagQuery::And(queries) => {
+                for (i, query) in queries.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { " & " };
+                    match query {
+                        TagQuery::Or(_) => write!(f, "{}({})", separator, query)?,
+                        _ => write!(f, "{}{}", separator, query)?,
+                    }
+                }
+                Ok(())
+            }
+            TagQuery::Or(queries) => {
+                for (i, query) in queries.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { " | " };
+                    write!(f, "{}{}", separator, query)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Equals,
+    NotEquals,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Invalid,
+}
+
+struct Tokens<'a> {
+    chars: Peekable<Chars<'a>>,
+    peeked: Option<Option<Token>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(s: &'a str) -> Self {
+        Tokens {
+            chars: s.chars().peekable(),
+            peeked: None,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read());
+        }
+        self.peeked.as_ref().and_then(|token| token.as_ref())
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        match self.peeked.take() {
+            Some(token) => token,
+            None => self.read(),
+        }
+    }
+
+    fn read(&mut self) -> Option<Token> {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+        let token = match self.chars.next()? {
+            '=' => Token::Equals,
+            '!' if self.chars.peek() == Some(&'=') => {
+                self.chars.next();
+                Token::NotEquals
+            }
+            '!' => Token::Not,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if is_tag_char(c) => {
+                let mut word = c.to_string();
+                while let Some(c) = self.chars.peek().copied().filter(|c| is_tag_char(*c)) {
+                    word.push(c);
+                    self.chars.next();
+                }
+                Token::Word(word)
+            }
+            _ => Token::Invalid,
+        };
+        Some(token)
+    }
+
+    // A tag value may be empty, so it is only read when there is one.
+    fn value(&mut self) -> String {
+        match self.peek() {
+            Some(Token::Word(_)) => match self.next() {
+                Some(Token::Word(value)) => value,
+                _ => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+}
+
+fn parse_or(tokens: &mut Tokens) -> Option<TagQuery> {
+    let mut queries = vec![parse_and(tokens)?];
+    while tokens.peek() == Some(&Token::Or) {
+        tokens.next();
+        queries.push(parse_and(tokens)?);
+    }
+    Some(combine(queries, TagQuery::Or))
+}
+
+fn parse_and(tokens: &mut Tokens) -> Option<TagQuery> {
+    let mut queries = vec![parse_unary(tokens)?];
+    while tokens.peek() == Some(&Token::And) {
+        tokens.next();
+        queries.push(parse_unary(tokens)?);
+    }
+    Some(combine(queries, TagQuery::And))
+}
+
+fn parse_unary(tokens: &mut Tokens) -> Option<TagQuery> {
+    match tokens.next()? {
+        Token::Not => Some(TagQuery::Not(Box::new(parse_unary(tokens)?))),
+        Token::Open => {
+            let query = parse_or(tokens)?;
+            match tokens.next()? {
+                Token::Close => Some(query),
+                _ => None,
+            }
+        }
+        Token::Word(key) => match tokens.peek() {
+            Some(Token::Equals) => {
+                tokens.next();
+                Some(TagQuery::Equals(key, tokens.value()))
+            }
+            Some(Token::NotEquals) => {
+                tokens.next();
+                let query = TagQuery::Equals(key, tokens.value());
+                Some(TagQuery::Not(Box::new(query)))
+            }
+            _ => Some(TagQuery::Exists(key)),
+        },
+        _ => None,
+    }
+}
+
+fn combine(mut queries: Vec<TagQuery>, f: fn(Vec<TagQuery>) -> TagQuery) -> TagQuery {
+    match queries.len() {
+        1 => queries.remove(0),
+        _ => f(queries),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn validate_should_reject_invalid_tags() {
+        assert!(validate("earth", "Moon", &tags(&[("env", "prod"), ("team", "")])).is_ok());
+        let too_many = (0..=MAX_TAGS)
+            .map(|i| (format!("key{}", i), String::new()))
+            .collect::<Tags>();
+        let long = "x".repeat(MAX_KEY_LENGTH + 1);
+        for invalid in &[
+            tags(&[("", "prod")]),
+            tags(&[("env", "prod & test")]),
+            tags(&[(long.as_str(), "prod")]),
+            too_many,
+        ] {
+            assert!(
+                matches!(validate("earth", "Moon", invalid), Err(e) if *e.kind() == ErrorKind::InvalidTag),
+                "validate failed to reject {:?}.",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn parse_should_follow_operator_precedence() {
+        let query = TagQuery::parse("env=prod & team!=infra | !(owner)").unwrap();
+        assert_eq!(
+            query,
+            TagQuery::Or(vec![
+                TagQuery::And(vec![
+                    TagQuery::equals("env", "prod"),
+                    TagQuery::Not(Box::new(TagQuery::equals("team", "infra"))),
+                ]),
+                TagQuery::Not(Box::new(TagQuery::exists("owner"))),
+            ]),
+            "TagQuery parsed the query wrongly."
+        );
+    }
+
+    #[test]
+    fn parse_should_read_displayed_query() {
+        for s in &["env=prod", "a & (b | c=)", "!(a=1 & b) | c", "a & b & !c"] {
+            let query = TagQuery::parse(s).unwrap();
+            assert_eq!(
+                TagQuery::parse(&query.to_string()),
+                Some(query),
+                "TagQuery failed to read back {}.",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn parse_should_reject_malformed_queries() {
+        for s in &[
+            "",
+            "=prod",
+            "(env",
+            "env)",
+            "env & ",
+            "env prod",
+            "env=pr od",
+            "env#",
+        ] {
+            assert!(TagQuery::parse(s).is_none(), "TagQuery parsed {}.", s);
+        }
+    }
+
+    #[test]
+    fn matches_should_evaluate_query_against_tags() {
+        let query = TagQuery::parse("env=prod & (team=infra | !owner)").unwrap();
+        assert!(query.matches(&tags(&[("env", "prod"), ("team", "infra")])));
+        assert!(query.matches(&tags(&[("env", "prod")])));
+        assert!(!query.matches(&tags(&[("env", "prod"), ("owner", "ops")])));
+        assert!(!query.matches(&tags(&[("env", "test"), ("team", "infra")])));
+    }
+}
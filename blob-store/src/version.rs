@@ -0,0 +1,47 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use std::time::SystemTime;
+
+// Version ID of objects written while versioning is not enabled on their bucket.
+pub const NULL_VERSION: &str = "null";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectVersion {
+    pub name: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub is_delete_marker: bool,
+    pub modified: SystemTime,
+    pub length: u64,
+    pub etag: String,
+}
+
+// The version ID and time given to a new version, or to a delete marker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamp {
+    pub version_id: String,
+    pub time: SystemTime,
+}
+
+impl Stamp {
+    pub fn new() -> Self {
+        Stamp {
+            version_id: new_version_id(),
+            time: SystemTime::now(),
+        }
+    }
+}
+
+impl Default for Stamp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn new_version_id() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}